@@ -3,12 +3,17 @@
 use std::sync::LazyLock;
 
 use bevy_ecs::{
-    system::{Commands, EntityCommands},
+    entity::Entity,
+    error::ErrorContext,
+    lifecycle::HookContext,
+    resource::Resource,
+    system::{Commands, EntityCommands, In, IntoSystem, SystemId},
     world::{DeferredWorld, EntityRef, EntityWorldMut, World},
 };
-use ustr::Ustr;
+use ustr::{Ustr, UstrMap};
 
 use super::{Props, Value};
+use crate::Class;
 
 // -----------------------------------------------------------------------------
 // Core immutable properties access
@@ -24,9 +29,9 @@ pub trait PropsExt {
     /// Returns an immutable reference to a property value. If the property is
     /// of the wrong type or is not set, a reference to a default value will be
     /// returned instead.
-    fn get_prop<T>(&self, name: impl Into<Ustr>) -> T
+    fn get_prop<'a, T>(&'a self, name: impl Into<Ustr>) -> T
     where
-        T: From<Value> + Default + 'static,
+        T: From<&'a Value> + Default,
     {
         self.props().get(name)
     }
@@ -85,18 +90,36 @@ pub trait PropsMutExt {
     {
         self.props_mut().get_mut(name)
     }
+
+    /// Runs the [`PropWatchers`] for changes made through
+    /// [`PropCommandsExt`]. This is an implementation detail of the blanket
+    /// implementation of that trait.
+    #[doc(hidden)]
+    fn notify_watchers(&mut self, _changes: Vec<PropChange>) {}
 }
 
 impl PropsMutExt for World {
     fn props_mut(&mut self) -> &mut Props {
         self.get_resource_or_init::<Props>().into_inner()
     }
+
+    fn notify_watchers(&mut self, changes: Vec<PropChange>) {
+        PropWatchers::run(self, changes);
+    }
 }
 
 impl<'w> PropsMutExt for EntityWorldMut<'w> {
     fn props_mut(&mut self) -> &mut Props {
         self.entry::<Props>().or_default().into_mut().into_inner()
     }
+
+    fn notify_watchers(&mut self, mut changes: Vec<PropChange>) {
+        let entity = self.id();
+        for change in &mut changes {
+            change.entity = Some(entity);
+        }
+        self.world_scope(|world| PropWatchers::run(world, changes));
+    }
 }
 
 // -----------------------------------------------------------------------------
@@ -108,6 +131,39 @@ pub trait PropCommandsExt {
     /// Sets a property assoceated with this object.
     fn set_prop(&mut self, name: impl Into<Ustr>, value: impl Into<Value>) -> &mut Self;
 
+    /// Sets a property assoceated with this object, unless it is already set.
+    ///
+    /// ```rust
+    /// # use bevy_ecs::prelude::*;
+    /// # use bevy_mod_props::*;
+    /// let mut world = World::new();
+    /// let mut entity = world.spawn_empty();
+    /// entity
+    ///     .set_prop("gold", 10.0)
+    ///     .set_prop_if_absent("gold", 0.0);
+    /// assert_eq!(entity.get_prop::<f32>("gold"), 10.0);
+    /// ```
+    fn set_prop_if_absent(&mut self, name: impl Into<Ustr>, value: impl Into<Value>) -> &mut Self;
+
+    /// Sets several properties assoceated with this object at once. This
+    /// applies all the values through a single mutable access (and for
+    /// commands, a single queued command), so change detection only fires
+    /// once.
+    ///
+    /// ```rust
+    /// # use bevy_ecs::prelude::*;
+    /// # use bevy_mod_props::*;
+    /// let mut world = World::new();
+    /// let mut entity = world.spawn_empty();
+    /// entity.set_props([("health", 100.0), ("mana", 50.0)]);
+    /// assert_eq!(entity.get_prop::<f32>("health"), 100.0);
+    /// assert_eq!(entity.get_prop::<f32>("mana"), 50.0);
+    /// ```
+    fn set_props<K, V>(&mut self, props: impl IntoIterator<Item = (K, V)>) -> &mut Self
+    where
+        K: Into<Ustr>,
+        V: Into<Value>;
+
     /// Removes a property from this object.
     fn remove_prop(&mut self, name: impl Into<Ustr>) -> &mut Self;
 
@@ -117,17 +173,62 @@ pub trait PropCommandsExt {
 
 impl<P: PropsMutExt> PropCommandsExt for P {
     fn set_prop(&mut self, name: impl Into<Ustr>, value: impl Into<Value>) -> &mut Self {
-        self.props_mut().set(name, value);
+        self.set_props([(name, value)])
+    }
+
+    fn set_prop_if_absent(&mut self, name: impl Into<Ustr>, value: impl Into<Value>) -> &mut Self {
+        let name = name.into();
+        let props = self.props_mut();
+        if !props.contains_key(name) {
+            let value = value.into();
+            props.set(name, value.clone());
+            self.notify_watchers(vec![PropChange::new(name, None, Some(value))]);
+        }
+        self
+    }
+
+    fn set_props<K, V>(&mut self, props: impl IntoIterator<Item = (K, V)>) -> &mut Self
+    where
+        K: Into<Ustr>,
+        V: Into<Value>,
+    {
+        let target = self.props_mut();
+        let mut changes = Vec::new();
+        for (name, value) in props {
+            let name = name.into();
+            let value = value.into();
+            let old = target.get_value(name).cloned();
+            if target.set_changed(name, value.clone()) {
+                changes.push(PropChange::new(name, old, Some(value)));
+            }
+        }
+        if !changes.is_empty() {
+            self.notify_watchers(changes);
+        }
         self
     }
 
     fn remove_prop(&mut self, name: impl Into<Ustr>) -> &mut Self {
-        self.props_mut().remove(name);
+        let name = name.into();
+        let props = self.props_mut();
+        let old = props.get_value(name).cloned();
+        props.remove(name);
+        if old.is_some() {
+            self.notify_watchers(vec![PropChange::new(name, old, None)]);
+        }
         self
     }
 
     fn clear_props(&mut self) -> &mut Self {
-        self.props_mut().clear();
+        let props = self.props_mut();
+        let changes: Vec<PropChange> = props
+            .iter()
+            .map(|(name, value)| PropChange::new(*name, Some(value.clone()), None))
+            .collect();
+        props.clear();
+        if !changes.is_empty() {
+            self.notify_watchers(changes);
+        }
         self
     }
 }
@@ -142,6 +243,30 @@ impl<'w, 's> PropCommandsExt for Commands<'w, 's> {
         self
     }
 
+    fn set_prop_if_absent(&mut self, name: impl Into<Ustr>, value: impl Into<Value>) -> &mut Self {
+        let name = name.into();
+        let value = value.into();
+        self.queue(move |world: &mut World| {
+            world.set_prop_if_absent(name, value);
+        });
+        self
+    }
+
+    fn set_props<K, V>(&mut self, props: impl IntoIterator<Item = (K, V)>) -> &mut Self
+    where
+        K: Into<Ustr>,
+        V: Into<Value>,
+    {
+        let props: Vec<(Ustr, Value)> = props
+            .into_iter()
+            .map(|(name, value)| (name.into(), value.into()))
+            .collect();
+        self.queue(move |world: &mut World| {
+            world.set_props(props);
+        });
+        self
+    }
+
     fn remove_prop(&mut self, name: impl Into<Ustr>) -> &mut Self {
         let name = name.into();
         self.queue(move |world: &mut World| {
@@ -168,6 +293,30 @@ impl<'a> PropCommandsExt for EntityCommands<'a> {
         self
     }
 
+    fn set_prop_if_absent(&mut self, name: impl Into<Ustr>, value: impl Into<Value>) -> &mut Self {
+        let name = name.into();
+        let value = value.into();
+        self.queue(move |mut entity: EntityWorldMut| {
+            entity.set_prop_if_absent(name, value);
+        });
+        self
+    }
+
+    fn set_props<K, V>(&mut self, props: impl IntoIterator<Item = (K, V)>) -> &mut Self
+    where
+        K: Into<Ustr>,
+        V: Into<Value>,
+    {
+        let props: Vec<(Ustr, Value)> = props
+            .into_iter()
+            .map(|(name, value)| (name.into(), value.into()))
+            .collect();
+        self.queue(move |mut entity: EntityWorldMut| {
+            entity.set_props(props);
+        });
+        self
+    }
+
     fn remove_prop(&mut self, name: impl Into<Ustr>) -> &mut Self {
         let name = name.into();
         self.queue(move |mut entity: EntityWorldMut| {
@@ -183,3 +332,286 @@ impl<'a> PropCommandsExt for EntityCommands<'a> {
         self
     }
 }
+
+// -----------------------------------------------------------------------------
+// Property watchers
+
+/// Describes a change to a single property, and is passed to the systems
+/// registered with [`WatchPropsExt::watch_prop`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropChange {
+    /// The entity whose properties changed, or `None` for the world's
+    /// properties.
+    pub entity: Option<Entity>,
+    /// The name of the property that changed.
+    pub name: Ustr,
+    /// The previous value, or `None` if the property was not set.
+    pub old: Option<Value>,
+    /// The new value, or `None` if the property was removed.
+    pub new: Option<Value>,
+}
+
+impl PropChange {
+    fn new(name: Ustr, old: Option<Value>, new: Option<Value>) -> PropChange {
+        PropChange {
+            entity: None,
+            name,
+            old,
+            new,
+        }
+    }
+}
+
+/// The systems to run when specific properties change. Watchers are usually
+/// added with [`WatchPropsExt::watch_prop`], but systems registered with the
+/// world can also be added directly.
+///
+/// Watchers only see changes made through [`PropCommandsExt`] (on the world,
+/// an entity, or through commands), and only when the value actually changes:
+/// setting a property to the value it already has, or removing a property
+/// that isn't set, does nothing. Changes made directly through a `&mut Props`
+/// are not seen, unless whatever made them reports them with
+/// [`PropWatchers::run`]. In particular, reading a missing property with
+/// [`PropsMutExt::get_prop_mut`] inserts a default value without counting as
+/// a change.
+#[derive(Resource, Default)]
+pub struct PropWatchers {
+    watchers: UstrMap<Vec<SystemId<In<PropChange>>>>,
+}
+
+impl PropWatchers {
+    /// Adds a watcher for a property.
+    pub fn add(&mut self, name: impl Into<Ustr>, system: SystemId<In<PropChange>>) {
+        self.watchers.entry(name.into()).or_default().push(system);
+    }
+
+    /// Removes a watcher from a property. The system stays registered with
+    /// the world.
+    pub fn remove(&mut self, name: impl Into<Ustr>, system: SystemId<In<PropChange>>) {
+        if let Some(watchers) = self.watchers.get_mut(&name.into()) {
+            watchers.retain(|watcher| *watcher != system);
+        }
+    }
+
+    /// Returns the names of the properties that have any watchers.
+    pub fn watched(&self) -> impl Iterator<Item = Ustr> + '_ {
+        self.watchers
+            .iter()
+            .filter(|(_, watchers)| !watchers.is_empty())
+            .map(|(name, _)| *name)
+    }
+
+    /// Runs the watchers for each change, in order. Changes made through
+    /// [`PropCommandsExt`] are reported automatically; this is for code that
+    /// changes a `&mut Props` directly, which should collect its changes and
+    /// report them once it no longer holds the props.
+    pub fn run(world: &mut World, changes: Vec<PropChange>) {
+        let Some(watchers) = world.get_resource::<PropWatchers>() else {
+            return;
+        };
+        let mut calls = Vec::new();
+        for change in changes {
+            for system in watchers.watchers.get(&change.name).into_iter().flatten() {
+                calls.push((*system, change.clone()));
+            }
+        }
+
+        for (system, change) in calls {
+            if let Err(error) = world.run_system_with(system, change) {
+                let error_handler = world.default_error_handler();
+                error_handler(
+                    error.into(),
+                    ErrorContext::Command {
+                        name: "PropWatchers::run".into(),
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Adds property watchers to [`World`].
+pub trait WatchPropsExt {
+    /// Registers a system to run whenever the named property changes, on the
+    /// world or on any entity. The system receives a [`PropChange`]
+    /// describing the change; see [`PropWatchers`] for which changes are
+    /// seen.
+    ///
+    /// ```rust
+    /// # use bevy_ecs::prelude::*;
+    /// # use bevy_mod_props::*;
+    /// #[derive(Resource, Default)]
+    /// struct Alerts(Vec<f32>);
+    ///
+    /// let mut world = World::new();
+    /// world.init_resource::<Alerts>();
+    /// world.watch_prop("alertness", |change: In<PropChange>, mut alerts: ResMut<Alerts>| {
+    ///     alerts.0.push(change.new.as_ref().and_then(Value::as_num).unwrap_or(0.0));
+    /// });
+    ///
+    /// let mut guard = world.spawn_empty();
+    /// guard.set_prop("alertness", 5.0).set_prop("mood", "bored");
+    /// guard.set_prop("alertness", 5.0).set_prop("alertness", 8.0);
+    /// guard.remove_prop("alertness");
+    /// assert_eq!(world.resource::<Alerts>().0, [5.0, 8.0, 0.0]);
+    /// ```
+    fn watch_prop<M>(
+        &mut self,
+        name: impl Into<Ustr>,
+        system: impl IntoSystem<In<PropChange>, (), M> + 'static,
+    ) -> SystemId<In<PropChange>>;
+}
+
+impl WatchPropsExt for World {
+    fn watch_prop<M>(
+        &mut self,
+        name: impl Into<Ustr>,
+        system: impl IntoSystem<In<PropChange>, (), M> + 'static,
+    ) -> SystemId<In<PropChange>> {
+        let system = self.register_system(system);
+        self.get_resource_or_init::<PropWatchers>()
+            .add(name, system);
+        system
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Property archetypes
+
+/// Default properties for each class of entity.
+///
+/// When a [`Props`] component is inserted empty on an entity with a [`Class`],
+/// it is seeded with the defaults registered for that class. If no defaults
+/// are registered for the class (or this resource does not exist) nothing
+/// happens.
+///
+/// ```rust
+/// # use bevy_ecs::prelude::*;
+/// # use bevy_mod_props::*;
+/// let mut world = World::new();
+/// world.insert_resource(
+///     PropArchetypes::new().with("guard", Props::new().with("alertness", 5.0)),
+/// );
+///
+/// let guard = world.spawn((Class::new("guard"), Props::new())).id();
+/// assert_eq!(world.entity(guard).get_prop::<f32>("alertness"), 5.0);
+/// ```
+///
+/// Note that the class must be present when the props are inserted; adding a
+/// class to an entity that already has props does not seed any defaults.
+#[derive(Resource, Default)]
+pub struct PropArchetypes {
+    archetypes: UstrMap<Props>,
+}
+
+impl PropArchetypes {
+    pub fn new() -> PropArchetypes {
+        PropArchetypes::default()
+    }
+
+    /// Registers the default properties for a class.
+    pub fn insert(&mut self, class: impl Into<Ustr>, defaults: Props) {
+        self.archetypes.insert(class.into(), defaults);
+    }
+
+    /// Registers the default properties for a class, and can be chained.
+    pub fn with(mut self, class: impl Into<Ustr>, defaults: Props) -> Self {
+        self.insert(class, defaults);
+        self
+    }
+
+    /// Returns the default properties for a class, if any are registered.
+    pub fn get(&self, class: impl Into<Ustr>) -> Option<&Props> {
+        self.archetypes.get(&class.into())
+    }
+
+    /// Removes the default properties for a class.
+    pub fn remove(&mut self, class: impl Into<Ustr>) -> Option<Props> {
+        self.archetypes.remove(&class.into())
+    }
+}
+
+impl Props {
+    pub(crate) fn on_insert(mut world: DeferredWorld, context: HookContext) {
+        let Some(&class) = world.entity(context.entity).get::<Class>() else {
+            return;
+        };
+        let Some(defaults) = world
+            .get_resource::<PropArchetypes>()
+            .and_then(|archetypes| archetypes.get(*class))
+            .cloned()
+        else {
+            return;
+        };
+        if let Some(mut props) = world.get_mut::<Props>(context.entity)
+            && props.is_empty()
+        {
+            // Keep the generation moving forward, so the seeded defaults
+            // don't look like an older version of these properties
+            let generation = props.generation.wrapping_add(1);
+            *props = defaults;
+            props.generation = generation;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy_ecs::prelude::*;
+
+    use crate::{PropChange, PropCommandsExt, Props, PropsExt, WatchPropsExt};
+
+    // The number of properties the entity had each time a watcher ran
+    #[derive(Resource, Default)]
+    struct Seen(Vec<usize>);
+
+    fn record(change: In<PropChange>, props: Query<&Props>, mut seen: ResMut<Seen>) {
+        let props = props.get(change.entity.unwrap()).unwrap();
+        seen.0.push(props.len());
+    }
+
+    // Sets three properties with `set`, and returns how many separate writes
+    // the watchers saw
+    fn count_writes(set: impl FnOnce(&mut World, Entity)) -> usize {
+        let mut world = World::new();
+        world.init_resource::<Seen>();
+        for name in ["health", "mana", "stamina"] {
+            world.watch_prop(name, record);
+        }
+        let entity = world.spawn(Props::new()).id();
+
+        set(&mut world, entity);
+        world.flush();
+
+        let entity_ref = world.entity(entity);
+        assert_eq!(entity_ref.props().len(), 3);
+        assert_eq!(entity_ref.props()["mana"], 50.0);
+        let mut seen = world.resource::<Seen>().0.clone();
+        assert_eq!(seen.len(), 3);
+        seen.dedup();
+        seen.len()
+    }
+
+    #[test]
+    fn batched_set_writes_once() {
+        // A single command sets every property before any watcher runs
+        let batched = count_writes(|world, entity| {
+            world.commands().entity(entity).set_props([
+                ("health", 100.0),
+                ("mana", 50.0),
+                ("stamina", 10.0),
+            ]);
+        });
+        assert_eq!(batched, 1);
+
+        let separate = count_writes(|world, entity| {
+            world
+                .commands()
+                .entity(entity)
+                .set_prop("health", 100.0)
+                .set_prop("mana", 50.0)
+                .set_prop("stamina", 10.0);
+        });
+        assert_eq!(separate, 3);
+    }
+}
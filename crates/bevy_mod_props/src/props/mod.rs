@@ -1,9 +1,14 @@
 //! Defines the core props datatype.
 
+use std::collections::BTreeSet;
+use std::collections::HashMap;
 use std::collections::btree_map::*;
 use std::fmt;
-use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Sub, SubAssign};
-use std::sync::LazyLock;
+use std::hash::{Hash, Hasher};
+use std::ops::{
+    Add, AddAssign, Deref, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Sub, SubAssign,
+};
+use std::sync::{Arc, LazyLock};
 
 pub use ustr::Ustr;
 
@@ -65,11 +70,59 @@ pub use ext::*;
 /// numbers, the result is zero.
 ///
 /// Doing any kind of math with `Value` always returns a `Value::Num` variant.
-#[derive(Debug, Copy, Clone)]
+///
+/// Besides `f32`, the other operand may also be an `i32`, `u32` or `usize`,
+/// which is converted to `f32` first. This saves casting counts by hand.
+///
+/// ```rust
+/// # use bevy_mod_props::*;
+/// let mut props = Props::new().with("count", 1.0);
+/// let items = vec!["apple", "pear"];
+/// props["count"] += items.len();
+/// assert_eq!(props["count"], 3.0);
+///
+/// props["count"] -= 5i32;
+/// assert_eq!(props["count"], -2.0);
+/// assert_eq!(props["count"].clone() * 3u32, Value::Num(-6.0));
+/// assert_eq!(Value::from("none") + 4i32, Value::Num(4.0));
+/// ```
+///
+/// # Strings
+///
+/// Strings are usually stored as interned [`Ustr`]s, which are cheap to copy
+/// and compare, but are never freed. This is the right choice for strings
+/// drawn from a fixed vocabulary, like moods or item names. Strings that are
+/// created dynamically and may be different every time, like names entered by
+/// the player or procedurally generated text, should instead be stored with
+/// [`Value::StrOwned`] (see [`Props::set_owned`]) so they don't fill up the
+/// interner over a long session.
+///
+/// Both kinds of string have the type [`ValueType::Str`], and compare and
+/// hash the same when their contents are equal.
+#[derive(Debug, Clone)]
 pub enum Value {
     Bool(bool),
     Num(f32),
     Str(Ustr),
+    StrOwned(Arc<str>),
+}
+
+/// The type of a [`Value`], without the value itself.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ValueType {
+    Bool,
+    Num,
+    Str,
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueType::Bool => write!(f, "boolean"),
+            ValueType::Num => write!(f, "number"),
+            ValueType::Str => write!(f, "string"),
+        }
+    }
 }
 
 // -----------------------------------------------------------------------------
@@ -90,6 +143,7 @@ impl fmt::Display for Value {
             Value::Bool(bool) => write!(f, "{bool}"),
             Value::Num(num) => write!(f, "{num}"),
             Value::Str(ustr) => write!(f, "{ustr}"),
+            Value::StrOwned(str) => write!(f, "{str}"),
         }
     }
 }
@@ -109,6 +163,22 @@ impl From<f32> for Value {
     }
 }
 
+/// Values only store single-precision numbers, so converting from an `f64`
+/// rounds to the nearest `f32`. Precision beyond `f32` is lost, and values
+/// outside the range of `f32` become infinite.
+///
+/// ```rust
+/// # use bevy_mod_props::*;
+/// let props = Props::new().with("distance", 1234.5678_f64);
+/// let distance: f64 = props.get("distance");
+/// assert!((distance - 1234.5678).abs() < 1e-3);
+/// ```
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Num(value as f32)
+    }
+}
+
 impl From<&str> for Value {
     fn from(value: &str) -> Self {
         Value::Str(value.into())
@@ -154,24 +224,232 @@ impl From<Value> for f64 {
     }
 }
 
-impl From<Value> for &str {
+impl From<Value> for Ustr {
     fn from(value: Value) -> Self {
+        <Ustr as From<&Value>>::from(&value)
+    }
+}
+
+impl From<&Value> for bool {
+    fn from(value: &Value) -> Self {
         match value {
-            Value::Str(str) => str.as_str(),
-            _ => "",
+            Value::Bool(bool) => *bool,
+            _ => false,
         }
     }
 }
 
-impl From<Value> for Ustr {
-    fn from(value: Value) -> Self {
+impl From<&Value> for f32 {
+    fn from(value: &Value) -> Self {
         match value {
-            Value::Str(str) => str,
+            Value::Num(num) => *num,
+            _ => 0.0,
+        }
+    }
+}
+
+impl From<&Value> for f64 {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Num(num) => *num as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+impl<'a> From<&'a Value> for &'a str {
+    fn from(value: &'a Value) -> Self {
+        value.as_str().unwrap_or("")
+    }
+}
+
+/// Converting an owned string to a [`Ustr`] interns it.
+impl From<&Value> for Ustr {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Str(str) => *str,
+            Value::StrOwned(str) => Ustr::from(str),
             _ => Ustr::from(""),
         }
     }
 }
 
+impl From<&Value> for Value {
+    fn from(value: &Value) -> Self {
+        value.clone()
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Parsing
+
+/// An error returned by [`Value::parse_as`] when a string can't be parsed as
+/// the requested type.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseValueError {
+    pub string: String,
+    pub expected: ValueType,
+}
+
+impl fmt::Display for ParseValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unable to parse \"{}\" as a {}",
+            self.string, self.expected
+        )
+    }
+}
+
+impl std::error::Error for ParseValueError {}
+
+/// An error returned when a property doesn't have the type an operation
+/// requires, such as by [`Props::try_add`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ValueTypeError {
+    pub name: Ustr,
+    pub expected: ValueType,
+    /// The actual type of the property, or `None` if it is not set.
+    pub found: Option<ValueType>,
+}
+
+impl fmt::Display for ValueTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.found {
+            Some(found) => write!(
+                f,
+                "expected property \"{}\" to be a {}, but it is a {}",
+                self.name, self.expected, found
+            ),
+            None => write!(
+                f,
+                "expected property \"{}\" to be a {}, but it is not set",
+                self.name, self.expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValueTypeError {}
+
+impl Value {
+    /// Returns the type of this value.
+    pub fn ty(&self) -> ValueType {
+        match self {
+            Value::Bool(_) => ValueType::Bool,
+            Value::Num(_) => ValueType::Num,
+            Value::Str(_) | Value::StrOwned(_) => ValueType::Str,
+        }
+    }
+
+    /// Returns the contents of this value if it is a boolean.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::Value;
+    /// assert_eq!(Value::Bool(true).as_bool(), Some(true));
+    /// assert_eq!(Value::Num(1.0).as_bool(), None);
+    /// ```
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(bool) => Some(*bool),
+            _ => None,
+        }
+    }
+
+    /// Returns the contents of this value if it is a number.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::Value;
+    /// assert_eq!(Value::Num(3.0).as_num(), Some(3.0));
+    /// assert_eq!(Value::from("3.0").as_num(), None);
+    /// ```
+    pub fn as_num(&self) -> Option<f32> {
+        match self {
+            Value::Num(num) => Some(*num),
+            _ => None,
+        }
+    }
+
+    /// Returns the contents of this value if it is a string, whether interned
+    /// or owned.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::Value;
+    /// assert_eq!(Value::from("hello").as_str(), Some("hello"));
+    /// assert_eq!(Value::StrOwned("hello".into()).as_str(), Some("hello"));
+    /// assert_eq!(Value::Bool(false).as_str(), None);
+    /// ```
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(str) => Some(str.as_str()),
+            Value::StrOwned(str) => Some(str),
+            _ => None,
+        }
+    }
+
+    /// Parses a value from a string, inferring its type. This is useful when
+    /// importing properties from formats where everything is a string.
+    ///
+    /// The exact strings `true` and `false` become booleans. Otherwise, if the
+    /// string is a finite number (as understood by [`f32::from_str`]) it
+    /// becomes a number. Anything else becomes a string.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::Value;
+    /// assert_eq!(Value::parse("true"), Value::Bool(true));
+    /// assert_eq!(Value::parse("42.0"), Value::Num(42.0));
+    /// assert_eq!(Value::parse("1e3"), Value::Num(1000.0));
+    /// assert_eq!(Value::parse("hello"), Value::from("hello"));
+    ///
+    /// // Parsing is case sensitive, and special floats are not recognized
+    /// assert_eq!(Value::parse("True"), Value::from("True"));
+    /// assert_eq!(Value::parse("inf"), Value::from("inf"));
+    /// ```
+    ///
+    /// [`f32::from_str`]: std::str::FromStr::from_str
+    pub fn parse(string: &str) -> Value {
+        if let Ok(bool) = string.parse::<bool>() {
+            Value::Bool(bool)
+        } else if let Some(num) = string.parse::<f32>().ok().filter(|num| num.is_finite()) {
+            Value::Num(num)
+        } else {
+            Value::Str(Ustr::from(string))
+        }
+    }
+
+    /// Parses a value of a known type from a string, returning an error if the
+    /// string does not contain a value of that type. Strings always parse
+    /// successfully. Like [`Value::parse`], only finite numbers are accepted,
+    /// so infinities, `NaN` and numbers too large for an `f32` are errors.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::{Value, ValueType};
+    /// assert_eq!(Value::parse_as("1", ValueType::Num), Ok(Value::Num(1.0)));
+    /// assert_eq!(Value::parse_as("1", ValueType::Str), Ok(Value::from("1")));
+    /// assert!(Value::parse_as("1", ValueType::Bool).is_err());
+    ///
+    /// assert!(Value::parse_as("inf", ValueType::Num).is_err());
+    /// assert!(Value::parse_as("NaN", ValueType::Num).is_err());
+    /// assert!(Value::parse_as("1e39", ValueType::Num).is_err());
+    /// assert_eq!(Value::parse_as("NaN", ValueType::Str), Ok(Value::from("NaN")));
+    /// ```
+    pub fn parse_as(string: &str, ty: ValueType) -> Result<Value, ParseValueError> {
+        let value = match ty {
+            ValueType::Bool => string.parse::<bool>().ok().map(Value::Bool),
+            ValueType::Num => string
+                .parse::<f32>()
+                .ok()
+                .filter(|num| num.is_finite())
+                .map(Value::Num),
+            ValueType::Str => Some(Value::Str(Ustr::from(string))),
+        };
+        value.ok_or_else(|| ParseValueError {
+            string: string.to_string(),
+            expected: ty,
+        })
+    }
+}
+
 // -----------------------------------------------------------------------------
 // Referencing and casting
 
@@ -195,6 +473,9 @@ impl AsRef<f32> for Value {
 
 static EMPTY_USTR: LazyLock<Ustr> = LazyLock::new(|| Ustr::from(""));
 
+/// Owned strings are not interned, so they can't be referenced as a [`Ustr`]
+/// and act like the empty string. Use [`Value::as_str`] to read either kind
+/// of string.
 impl AsRef<Ustr> for Value {
     fn as_ref(&self) -> &Ustr {
         match self {
@@ -204,6 +485,27 @@ impl AsRef<Ustr> for Value {
     }
 }
 
+/// Reads either kind of string as text. Values that aren't strings act like
+/// the empty string.
+///
+/// ```rust
+/// # use bevy_mod_props::*;
+/// let props = Props::new().with("name", "clippy").with("age", 3.0);
+///
+/// let name: &str = props["name"].as_ref();
+/// let age: &str = props["age"].as_ref();
+/// assert_eq!(name, "clippy");
+/// assert_eq!(age, "");
+///
+/// assert_eq!(props.get::<&str>("name"), "clippy");
+/// assert_eq!(props.get::<&str>("age"), "");
+/// ```
+impl AsRef<str> for Value {
+    fn as_ref(&self) -> &str {
+        self.as_str().unwrap_or("")
+    }
+}
+
 impl AsMut<bool> for Value {
     fn as_mut(&mut self) -> &mut bool {
         match self {
@@ -240,12 +542,13 @@ impl AsMut<f32> for Value {
     }
 }
 
+/// Owned strings are interned first, keeping their contents.
 impl AsMut<Ustr> for Value {
     fn as_mut(&mut self) -> &mut Ustr {
         match self {
             Value::Str(str) => str,
             _ => {
-                *self = Value::Str(Ustr::from(""));
+                *self = Value::Str(Ustr::from(self.as_str().unwrap_or("")));
                 let Value::Str(str) = self else {
                     unreachable!();
                 };
@@ -302,37 +605,25 @@ impl PartialEq<Value> for f32 {
 
 impl PartialEq<&str> for Value {
     fn eq(&self, other: &&str) -> bool {
-        match self {
-            Value::Str(this) => *this == Ustr::from(other),
-            _ => false,
-        }
+        self.as_str() == Some(*other)
     }
 }
 
 impl PartialEq<Value> for &str {
     fn eq(&self, other: &Value) -> bool {
-        match other {
-            Value::Str(that) => *self == Ustr::from(that),
-            _ => false,
-        }
+        other == self
     }
 }
 
 impl PartialEq<String> for Value {
     fn eq(&self, other: &String) -> bool {
-        match self {
-            Value::Str(this) => *this == Ustr::from(other),
-            _ => false,
-        }
+        self.as_str() == Some(other.as_str())
     }
 }
 
 impl PartialEq<Value> for String {
     fn eq(&self, other: &Value) -> bool {
-        match other {
-            Value::Str(that) => *self == Ustr::from(that),
-            _ => false,
-        }
+        other == self
     }
 }
 
@@ -340,6 +631,7 @@ impl PartialEq<Ustr> for Value {
     fn eq(&self, other: &Ustr) -> bool {
         match self {
             Value::Str(this) => this == other,
+            Value::StrOwned(this) => **this == **other,
             _ => false,
         }
     }
@@ -347,10 +639,7 @@ impl PartialEq<Ustr> for Value {
 
 impl PartialEq<Value> for Ustr {
     fn eq(&self, other: &Value) -> bool {
-        match other {
-            Value::Str(that) => self == that,
-            _ => false,
-        }
+        other == self
     }
 }
 
@@ -360,13 +649,58 @@ impl PartialEq<Value> for Value {
             (Value::Bool(this), Value::Bool(that)) => this == that,
             (Value::Num(this), Value::Num(that)) => this == that,
             (Value::Str(this), Value::Str(that)) => this == that,
-            _ => false,
+            (this, that) => this.as_str().is_some() && this.as_str() == that.as_str(),
         }
     }
 }
 
 impl Eq for Value {}
 
+// -----------------------------------------------------------------------------
+// Hashing
+
+/// Values hash consistently with their equality: numbers are hashed by their
+/// bits, with `0.0` and `-0.0` hashed the same since they are equal, and
+/// interned and owned strings are both hashed by their contents.
+///
+/// All `NaN` values hash the same, but `NaN` is still not equal to anything
+/// (including itself), so a `NaN` inserted into a hash set can never be found
+/// again, and inserting it twice creates two entries.
+///
+/// ```rust
+/// # use std::collections::HashSet;
+/// # use bevy_mod_props::*;
+/// let values: HashSet<Value> = [
+///     Value::from(true),
+///     Value::from(1.0),
+///     Value::from(-0.0),
+///     Value::from(0.0),
+///     Value::from("one"),
+///     Value::StrOwned("one".into()),
+///     Value::from(false),
+/// ]
+/// .into_iter()
+/// .collect();
+///
+/// assert_eq!(values.len(), 5);
+/// assert!(values.contains(&Value::from(0.0)));
+/// assert!(values.contains(&Value::from("one")));
+/// assert!(!values.contains(&Value::from(2.0)));
+/// ```
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.ty().hash(state);
+        match self {
+            Value::Bool(bool) => bool.hash(state),
+            Value::Num(num) if *num == 0.0 => 0.0f32.to_bits().hash(state),
+            Value::Num(num) if num.is_nan() => f32::NAN.to_bits().hash(state),
+            Value::Num(num) => num.to_bits().hash(state),
+            Value::Str(str) => str.as_str().hash(state),
+            Value::StrOwned(str) => str.hash(state),
+        }
+    }
+}
+
 // -----------------------------------------------------------------------------
 // Comparison
 
@@ -410,6 +744,7 @@ impl PartialOrd<Ustr> for Value {
     fn partial_cmp(&self, that: &Ustr) -> Option<std::cmp::Ordering> {
         match self {
             Value::Str(this) => this.partial_cmp(that),
+            Value::StrOwned(this) => (**this).partial_cmp(that.as_str()),
             _ => None,
         }
     }
@@ -417,10 +752,7 @@ impl PartialOrd<Ustr> for Value {
 
 impl PartialOrd<Value> for Ustr {
     fn partial_cmp(&self, other: &Value) -> Option<std::cmp::Ordering> {
-        match other {
-            Value::Str(that) => self.partial_cmp(that),
-            _ => None,
-        }
+        other.partial_cmp(self).map(std::cmp::Ordering::reverse)
     }
 }
 
@@ -430,7 +762,7 @@ impl PartialOrd<Value> for Value {
             (Value::Bool(this), Value::Bool(that)) => this.partial_cmp(that),
             (Value::Num(this), Value::Num(that)) => this.partial_cmp(that),
             (Value::Str(this), Value::Str(that)) => this.partial_cmp(that),
-            _ => None,
+            (this, that) => this.as_str()?.partial_cmp(that.as_str()?),
         }
     }
 }
@@ -465,7 +797,7 @@ impl Add<Value> for f32 {
 
 impl AddAssign<f32> for Value {
     fn add_assign(&mut self, rhs: f32) {
-        *self = *self + rhs
+        *self = std::mem::take(self) + rhs
     }
 }
 
@@ -483,7 +815,7 @@ impl Add<Value> for Value {
 
 impl AddAssign<Value> for Value {
     fn add_assign(&mut self, rhs: Value) {
-        *self = *self + rhs
+        *self = std::mem::take(self) + rhs
     }
 }
 
@@ -517,7 +849,7 @@ impl Sub<Value> for f32 {
 
 impl SubAssign<f32> for Value {
     fn sub_assign(&mut self, rhs: f32) {
-        *self = *self - rhs
+        *self = std::mem::take(self) - rhs
     }
 }
 
@@ -536,7 +868,7 @@ impl Sub<Value> for Value {
 
 impl SubAssign<Value> for Value {
     fn sub_assign(&mut self, rhs: Value) {
-        *self = *self - rhs
+        *self = std::mem::take(self) - rhs
     }
 }
 
@@ -570,7 +902,7 @@ impl Mul<Value> for f32 {
 
 impl MulAssign<f32> for Value {
     fn mul_assign(&mut self, rhs: f32) {
-        *self = *self * rhs
+        *self = std::mem::take(self) * rhs
     }
 }
 
@@ -587,7 +919,7 @@ impl Mul<Value> for Value {
 
 impl MulAssign<Value> for Value {
     fn mul_assign(&mut self, rhs: Value) {
-        *self = *self * rhs
+        *self = std::mem::take(self) * rhs
     }
 }
 
@@ -623,7 +955,7 @@ impl Div<Value> for f32 {
 
 impl DivAssign<f32> for Value {
     fn div_assign(&mut self, rhs: f32) {
-        *self = *self / rhs
+        *self = std::mem::take(self) / rhs
     }
 }
 
@@ -642,13 +974,130 @@ impl Div<Value> for Value {
 
 impl DivAssign<Value> for Value {
     fn div_assign(&mut self, rhs: Value) {
-        *self = *self / rhs
+        *self = std::mem::take(self) / rhs
     }
 }
 
+// -----------------------------------------------------------------------------
+// Integer operands
+
+// Integers are converted to `f32` and then behave exactly like `f32` operands.
+// Large integers may lose precision in the conversion.
+
+macro_rules! impl_integer_math {
+    ($($int:ty),*) => {$(
+        impl Add<$int> for Value {
+            type Output = Value;
+
+            fn add(self, rhs: $int) -> Self::Output {
+                self + rhs as f32
+            }
+        }
+
+        impl Add<Value> for $int {
+            type Output = Value;
+
+            fn add(self, rhs: Value) -> Self::Output {
+                self as f32 + rhs
+            }
+        }
+
+        impl AddAssign<$int> for Value {
+            fn add_assign(&mut self, rhs: $int) {
+                *self += rhs as f32
+            }
+        }
+
+        impl Sub<$int> for Value {
+            type Output = Value;
+
+            fn sub(self, rhs: $int) -> Self::Output {
+                self - rhs as f32
+            }
+        }
+
+        impl Sub<Value> for $int {
+            type Output = Value;
+
+            fn sub(self, rhs: Value) -> Self::Output {
+                self as f32 - rhs
+            }
+        }
+
+        impl SubAssign<$int> for Value {
+            fn sub_assign(&mut self, rhs: $int) {
+                *self -= rhs as f32
+            }
+        }
+
+        impl Mul<$int> for Value {
+            type Output = Value;
+
+            fn mul(self, rhs: $int) -> Self::Output {
+                self * rhs as f32
+            }
+        }
+
+        impl Mul<Value> for $int {
+            type Output = Value;
+
+            fn mul(self, rhs: Value) -> Self::Output {
+                self as f32 * rhs
+            }
+        }
+
+        impl MulAssign<$int> for Value {
+            fn mul_assign(&mut self, rhs: $int) {
+                *self *= rhs as f32
+            }
+        }
+
+        impl Div<$int> for Value {
+            type Output = Value;
+
+            fn div(self, rhs: $int) -> Self::Output {
+                self / rhs as f32
+            }
+        }
+
+        impl Div<Value> for $int {
+            type Output = Value;
+
+            fn div(self, rhs: Value) -> Self::Output {
+                self as f32 / rhs
+            }
+        }
+
+        impl DivAssign<$int> for Value {
+            fn div_assign(&mut self, rhs: $int) {
+                *self /= rhs as f32
+            }
+        }
+    )*};
+}
+
+impl_integer_math!(i32, u32, usize);
+
 // -----------------------------------------------------------------------------
 // Property Maps
 
+/// Decides how [`Props::merge_with`] combines a property that is set in both
+/// sets of properties.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Replace the existing value with the incoming one.
+    #[default]
+    Overwrite,
+    /// Leave the existing value alone.
+    KeepExisting,
+    /// Add the values together if both are numbers, otherwise overwrite.
+    Sum,
+    /// Keep the larger value if both are numbers, otherwise overwrite.
+    Max,
+    /// Keep the smaller value if both are numbers, otherwise overwrite.
+    Min,
+}
+
 /// A simple key-value property store, accessable either as a component or a
 /// resource.
 ///
@@ -658,11 +1107,37 @@ impl DivAssign<Value> for Value {
 ///
 /// When accessing a property, if a value has not been set or has the wrong
 /// type, the property should be treated as if it has the default value of the
-/// correct type. For example, toggling a
+/// correct type. For example, toggling a missing boolean sets it to `true`,
+/// and adding to a missing number treats it as `0.0`.
+///
+/// Typed accessors like [`Props::get`] follow this rule, but indexing can't
+/// know which type is expected, so indexing a missing property always returns
+/// [`Value::default`], which is `false`. Use `get` to read a missing property
+/// as a number or string.
+///
+/// ```rust
+/// # use bevy_mod_props::*;
+/// let mut props = Props::new();
+/// assert_eq!(props["missing"], false);
+/// assert_ne!(props["missing"], 0.0);
+/// assert_ne!(props["missing"], "");
+/// assert_eq!(props.get::<f32>("missing"), 0.0);
+/// assert_eq!(props.get::<&str>("missing"), "");
+///
+/// // Arithmetic treats non-numbers as zero, so this works either way
+/// props["count"] += 1.0;
+/// assert_eq!(props["count"], 1.0);
+/// ```
 #[derive(Default, Clone, Debug)]
 #[cfg_attr(feature = "bevy", derive(Component, Resource))]
+#[cfg_attr(feature = "bevy", component(on_insert = Props::on_insert))]
 pub struct Props {
     properties: BTreeMap<Ustr, Value>,
+    generation: u64,
+    // Names of removed properties, when tombstones are enabled. A name stays
+    // here if the property is set again, so it only counts as a tombstone
+    // while the property is absent.
+    tombstones: Option<BTreeSet<Ustr>>,
 }
 
 impl Props {
@@ -672,25 +1147,150 @@ impl Props {
         Props::default()
     }
 
+    /// Creates a new set of properties, expecting roughly `capacity` of them
+    /// to be set.
+    ///
+    /// Properties are currently stored in a [`BTreeMap`], which allocates per
+    /// node and has no notion of capacity, so the hint is ignored. It exists
+    /// so that code spawning many entities with a known number of properties
+    /// can say so, and will benefit if the storage ever becomes
+    /// capacity-aware.
+    pub fn with_capacity_hint(_capacity: usize) -> Props {
+        Props::default()
+    }
+
+    /// Takes a read-only snapshot of these properties that can be cheaply
+    /// cloned and shared between threads. See [`SharedProps`].
+    pub fn share(&self) -> SharedProps {
+        SharedProps(Arc::new(self.clone()))
+    }
+
+    /// Returns a counter that increases whenever these properties are
+    /// modified, or might have been through a mutable reference. Reading
+    /// properties never changes it, so caches derived from a set of
+    /// properties can compare generations to tell when they are stale.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let mut props = Props::new();
+    /// let initial = props.generation();
+    ///
+    /// props.set("gold", 10.0);
+    /// let after_set = props.generation();
+    /// assert!(after_set > initial);
+    ///
+    /// let _ = props.get::<f32>("gold");
+    /// let _ = &props["gold"];
+    /// assert_eq!(props.generation(), after_set);
+    ///
+    /// props["gold"] += 5.0;
+    /// assert!(props.generation() > after_set);
+    /// let after_index = props.generation();
+    ///
+    /// props.remove("gold");
+    /// assert!(props.generation() > after_index);
+    /// let after_remove = props.generation();
+    ///
+    /// props.clear();
+    /// assert!(props.generation() > after_remove);
+    /// ```
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn touch(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
     /// Gets the given key’s corresponding entry in the map for in-place manipulation.
     pub fn entry(&mut self, name: impl Into<Ustr>) -> Entry<Ustr, Value> {
+        self.touch();
         self.properties.entry(name.into())
     }
 
     /// Returns an immutable reference to a property value. If the property is
     /// of the wrong type or is not set, a reference to a default value will be
     /// returned instead.
-    pub fn get<T>(&self, name: impl Into<Ustr>) -> T
+    pub fn get<'a, T>(&'a self, name: impl Into<Ustr>) -> T
     where
-        T: From<Value> + Default + 'static,
+        T: From<&'a Value> + Default,
     {
-        if let Some(&value) = self.properties.get(&name.into()) {
+        // Skip interning the name when there is nothing to look up, which is
+        // common for entities that have never had properties set
+        if self.properties.is_empty() {
+            return T::default();
+        }
+        if let Some(value) = self.properties.get(&name.into()) {
             value.into()
         } else {
             T::default()
         }
     }
 
+    /// Returns the value of a property, or `None` if it is not set. Unlike
+    /// [`Props::get`], this doesn't fabricate a default, so combined with
+    /// accessors like [`Value::as_num`] it can tell a missing or mistyped
+    /// property apart from one that is set to zero.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let props = Props::new().with("health", 0.0).with("name", "clippy");
+    /// assert_eq!(props.get_value("health").and_then(Value::as_num), Some(0.0));
+    /// assert_eq!(props.get_value("name").and_then(Value::as_num), None);
+    /// assert_eq!(props.get_value("mana").and_then(Value::as_num), None);
+    /// ```
+    pub fn get_value(&self, name: impl Into<Ustr>) -> Option<&Value> {
+        if self.properties.is_empty() {
+            return None;
+        }
+        self.properties.get(&name.into())
+    }
+
+    /// Returns a string property as a borrowed `&str`, or `""` if it is not set
+    /// or isn't a string. Works for both interned and owned strings.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let mut props = Props::new().with("name", "clippy").with("gold", 10.0);
+    /// props.set_owned("title", "the helpful");
+    /// assert_eq!(props.get_str("name"), "clippy");
+    /// assert_eq!(props.get_str("title"), "the helpful");
+    /// assert_eq!(props.get_str("gold"), "");
+    /// assert_eq!(props.get_str("missing"), "");
+    /// ```
+    pub fn get_str(&self, name: impl Into<Ustr>) -> &str {
+        self.get_value(name).and_then(Value::as_str).unwrap_or("")
+    }
+
+    /// Returns a numeric property, or `0.0` if it is not set or isn't a number.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let props = Props::new().with("gold", 10.0).with("name", "clippy");
+    /// assert_eq!(props.get_num("gold"), 10.0);
+    /// assert_eq!(props.get_num("name"), 0.0);
+    /// assert_eq!(props.get_num("missing"), 0.0);
+    /// ```
+    pub fn get_num(&self, name: impl Into<Ustr>) -> f32 {
+        self.get_value(name).and_then(Value::as_num).unwrap_or(0.0)
+    }
+
+    /// Returns a boolean property, or `false` if it is not set or isn't a
+    /// boolean.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let props = Props::new().with("met_clippy", true).with("gold", 1.0);
+    /// assert!(props.get_bool("met_clippy"));
+    /// assert!(!props.get_bool("gold"));
+    /// assert!(!props.get_bool("missing"));
+    /// ```
+    pub fn get_bool(&self, name: impl Into<Ustr>) -> bool {
+        self.get_value(name)
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+    }
+
     /// Returns a mutable reference to a property value. If the propety value is
     /// of the wrong type or not set, a default value of the correct type will
     /// be inserted.
@@ -698,14 +1298,303 @@ impl Props {
     where
         Value: AsMut<T>,
     {
+        self.touch();
         self.properties.entry(name.into()).or_default().as_mut()
     }
 
     /// Sets a property value.
     pub fn set(&mut self, name: impl Into<Ustr>, value: impl Into<Value>) {
+        self.touch();
         self.properties.insert(name.into(), value.into());
     }
 
+    /// Sets a property to a string without interning it, as a
+    /// [`Value::StrOwned`]. Use this for dynamic strings that would otherwise
+    /// fill up the interner, like names entered by the player. See the
+    /// [`Value`] docs for more on the two kinds of string.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let mut props = Props::new();
+    /// let name = Ustr::from("player_name");
+    ///
+    /// props.set_owned(name, format!("Player #{}", 1234));
+    /// assert_eq!(props.get::<&str>(name), "Player #1234");
+    /// assert_eq!(props[name], "Player #1234");
+    /// assert!(Ustr::from_existing("Player #1234").is_none());
+    /// ```
+    pub fn set_owned(&mut self, name: impl Into<Ustr>, string: impl Into<Arc<str>>) {
+        self.touch();
+        self.properties
+            .insert(name.into(), Value::StrOwned(string.into()));
+    }
+
+    /// Sets a property value, and returns true if this changed the stored
+    /// value. Setting a property to the value it already has returns false.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let mut props = Props::new();
+    /// assert!(props.set_changed("mood", "happy"));
+    /// assert!(!props.set_changed("mood", "happy"));
+    /// assert!(props.set_changed("mood", "sad"));
+    /// ```
+    ///
+    /// Values of different types are never equal, so changing the type of a
+    /// property always counts as a change, even if the values would compare
+    /// the same with [`Props::get`].
+    pub fn set_changed(&mut self, name: impl Into<Ustr>, value: impl Into<Value>) -> bool {
+        let value = value.into();
+        self.touch();
+        self.properties.insert(name.into(), value.clone()) != Some(value)
+    }
+
+    /// Sets a property value only if the property is not already present.
+    /// Unlike `get_mut`, this never overwrites an existing value, even one of
+    /// a different type.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let mut props = Props::new().with("health", 50.0);
+    /// props.set_if_absent("health", 100.0);
+    /// props.set_if_absent("mana", 100.0);
+    /// assert_eq!(props["health"], 50.0);
+    /// assert_eq!(props["mana"], 100.0);
+    /// ```
+    pub fn set_if_absent(&mut self, name: impl Into<Ustr>, value: impl Into<Value>) {
+        if let Entry::Vacant(entry) = self.properties.entry(name.into()) {
+            entry.insert(value.into());
+            self.touch();
+        }
+    }
+
+    /// Returns a mutable reference to a property value, inserting the result of
+    /// `f` first if the property is not already present. Like
+    /// [`Entry::or_insert_with`], the closure only runs when the property is
+    /// missing, and an existing value is left alone even if it has a different
+    /// type.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let mut props = Props::new().with("health", 50.0);
+    /// let mut calls = 0;
+    /// props.get_or_insert_with("health", || { calls += 1; 100.0 });
+    /// props.get_or_insert_with("mana", || { calls += 1; 100.0 });
+    /// assert_eq!(calls, 1);
+    /// assert_eq!(props["health"], 50.0);
+    /// assert_eq!(props["mana"], 100.0);
+    /// ```
+    pub fn get_or_insert_with<V>(
+        &mut self,
+        name: impl Into<Ustr>,
+        f: impl FnOnce() -> V,
+    ) -> &mut Value
+    where
+        V: Into<Value>,
+    {
+        self.touch();
+        self.properties
+            .entry(name.into())
+            .or_insert_with(|| f().into())
+    }
+
+    /// Adds to a numeric property, and returns its new value. Unlike
+    /// `props[name] += delta`, this never changes the type of a property: if
+    /// the property is not a number, or is not set, an error is returned and
+    /// nothing is modified.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let mut props = Props::new().with("health", 10.0).with("name", "clippy");
+    /// assert_eq!(props.try_add("health", 5.0), Ok(15.0));
+    /// assert_eq!(props["health"], 15.0);
+    ///
+    /// let error = props.try_add("name", 5.0).unwrap_err();
+    /// assert_eq!(error.found, Some(ValueType::Str));
+    /// assert_eq!(props["name"], "clippy");
+    ///
+    /// assert_eq!(props.try_add("mana", 5.0).unwrap_err().found, None);
+    /// assert!(!props.contains_key("mana"));
+    /// ```
+    pub fn try_add(&mut self, name: impl Into<Ustr>, delta: f32) -> Result<f32, ValueTypeError> {
+        let name = name.into();
+        match self.properties.get_mut(&name) {
+            Some(Value::Num(num)) => {
+                *num += delta;
+                let num = *num;
+                self.touch();
+                Ok(num)
+            }
+            value => Err(ValueTypeError {
+                name,
+                expected: ValueType::Num,
+                found: value.map(|value| value.ty()),
+            }),
+        }
+    }
+
+    /// Copies every property from `other` into this set, using `policy` to
+    /// decide what happens to properties that are set in both. Properties
+    /// only set in `other` are always copied over.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let base = Props::new()
+    ///     .with("strength", 10.0)
+    ///     .with("speed", 5.0)
+    ///     .with("name", "clippy");
+    /// let buff = Props::new()
+    ///     .with("strength", 3.0)
+    ///     .with("speed", 8.0)
+    ///     .with("name", "hero")
+    ///     .with("haste", true);
+    ///
+    /// let merged = |policy| {
+    ///     let mut props = base.clone();
+    ///     props.merge_with(&buff, policy);
+    ///     (props["strength"].clone(), props["speed"].clone(), props["name"].clone(), props["haste"].clone())
+    /// };
+    ///
+    /// let hero = Value::from("hero");
+    /// let clippy = Value::from("clippy");
+    /// let haste = Value::from(true);
+    /// assert_eq!(merged(MergePolicy::Overwrite), (3.0.into(), 8.0.into(), hero.clone(), haste.clone()));
+    /// assert_eq!(merged(MergePolicy::KeepExisting), (10.0.into(), 5.0.into(), clippy, haste.clone()));
+    /// assert_eq!(merged(MergePolicy::Sum), (13.0.into(), 13.0.into(), hero.clone(), haste.clone()));
+    /// assert_eq!(merged(MergePolicy::Max), (10.0.into(), 8.0.into(), hero.clone(), haste.clone()));
+    /// assert_eq!(merged(MergePolicy::Min), (3.0.into(), 5.0.into(), hero, haste));
+    /// ```
+    pub fn merge_with(&mut self, other: &Props, policy: MergePolicy) {
+        self.touch();
+        for (&name, value) in other.iter() {
+            match self.properties.entry(name) {
+                Entry::Vacant(entry) => {
+                    entry.insert(value.clone());
+                }
+                Entry::Occupied(mut entry) => {
+                    let existing = entry.get_mut();
+                    *existing = match (policy, &*existing, value) {
+                        (MergePolicy::KeepExisting, _, _) => continue,
+                        (MergePolicy::Sum, Value::Num(lhs), Value::Num(rhs)) => {
+                            Value::Num(lhs + rhs)
+                        }
+                        (MergePolicy::Max, Value::Num(lhs), Value::Num(rhs)) => {
+                            Value::Num(lhs.max(*rhs))
+                        }
+                        (MergePolicy::Min, Value::Num(lhs), Value::Num(rhs)) => {
+                            Value::Num(lhs.min(*rhs))
+                        }
+                        _ => value.clone(),
+                    };
+                }
+            }
+        }
+    }
+
+    /// Computes what has changed in this set of properties since `baseline`.
+    /// Returns the properties that were added or changed, along with the
+    /// names of properties that were removed. Applying these to `baseline`
+    /// with [`apply_delta`](Props::apply_delta) reproduces this set.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let baseline = Props::new()
+    ///     .with("health", 100.0)
+    ///     .with("mood", "calm")
+    ///     .with("poisoned", true);
+    /// let current = Props::new()
+    ///     .with("health", 80.0)
+    ///     .with("mood", "calm")
+    ///     .with("gold", 5.0);
+    ///
+    /// let (delta, removed) = current.changed_since(&baseline);
+    /// assert_eq!(delta.len(), 2);
+    /// assert_eq!(delta.get::<f32>("health"), 80.0);
+    /// assert_eq!(delta.get::<f32>("gold"), 5.0);
+    /// assert_eq!(removed, [Ustr::from("poisoned")]);
+    ///
+    /// let mut synced = baseline.clone();
+    /// synced.apply_delta(&delta, &removed);
+    /// assert!(synced.iter().eq(current.iter()));
+    /// ```
+    pub fn changed_since(&self, baseline: &Props) -> (Props, Vec<Ustr>) {
+        let delta = Props {
+            properties: self
+                .iter()
+                .filter(|(name, value)| baseline.properties.get(*name) != Some(*value))
+                .map(|(name, value)| (*name, value.clone()))
+                .collect(),
+            generation: 0,
+            tombstones: None,
+        };
+        let mut removed: Vec<_> = baseline
+            .keys()
+            .copied()
+            .filter(|name| !self.properties.contains_key(name))
+            .chain(self.tombstones())
+            .collect();
+        removed.sort();
+        removed.dedup();
+        (delta, removed)
+    }
+
+    /// Applies changes computed by [`changed_since`](Props::changed_since),
+    /// setting every property in `delta` and removing every property in
+    /// `removed`.
+    pub fn apply_delta(&mut self, delta: &Props, removed: &[Ustr]) {
+        self.touch();
+        for name in removed {
+            self.properties.remove(name);
+            if let Some(tombstones) = &mut self.tombstones {
+                tombstones.insert(*name);
+            }
+        }
+        for (name, value) in delta.iter() {
+            self.properties.insert(*name, value.clone());
+        }
+    }
+
+    /// Compares two sets of properties by what reading them would return,
+    /// rather than by what they store. A property set to the default of its
+    /// type (`false`, zero, or the empty string) is treated the same as one
+    /// that is not set, since reading either gives the same result.
+    ///
+    /// Only the defaults of the types themselves are considered. Defaults
+    /// that come from elsewhere, like the class defaults in `PropArchetypes`,
+    /// are not taken into account.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let explicit = Props::new().with("alerted", false).with("gold", 5.0);
+    /// let implicit = Props::new().with("gold", 5.0);
+    /// assert!(explicit.semantic_eq(&implicit));
+    /// assert!(explicit != implicit);
+    ///
+    /// let rich = Props::new().with("gold", 50.0);
+    /// assert!(!rich.semantic_eq(&implicit));
+    /// ```
+    pub fn semantic_eq(&self, other: &Props) -> bool {
+        let is_default = |value: &Value| match value {
+            Value::Bool(bool) => !bool,
+            Value::Num(num) => *num == 0.0,
+            Value::Str(ustr) => ustr.is_empty(),
+            Value::StrOwned(str) => str.is_empty(),
+        };
+        let matches = |lhs: &Props, rhs: &Props| {
+            lhs.iter()
+                .all(|(name, value)| match rhs.properties.get(name) {
+                    Some(other) => value == other || (is_default(value) && is_default(other)),
+                    None => is_default(value),
+                })
+        };
+        matches(self, other) && matches(other, self)
+    }
+
+    /// Returns true if the property has been set.
+    pub fn contains_key(&self, name: impl Into<Ustr>) -> bool {
+        !self.properties.is_empty() && self.properties.contains_key(&name.into())
+    }
+
     /// Sets a property value, and can be chained.
     pub fn with(mut self, name: impl Into<Ustr>, value: impl Into<Value>) -> Self {
         self.set(name, value);
@@ -714,15 +1603,113 @@ impl Props {
 
     ////Removes a property. Subsiquently accessing this property with `get` or
     /// `get_mut` will return a default value.
+    ///
+    /// If tombstones are enabled, the property is also recorded as removed.
+    /// See [`with_tombstones`](Props::with_tombstones).
     pub fn remove(&mut self, name: impl Into<Ustr>) {
-        self.properties.remove(&name.into());
+        let name = name.into();
+        let removed = !self.properties.is_empty() && self.properties.remove(&name).is_some();
+        let buried = self
+            .tombstones
+            .as_mut()
+            .is_some_and(|tombstones| tombstones.insert(name));
+        if removed || buried {
+            self.touch();
+        }
     }
 
-    /// Clears all properties.
+    /// Clears all properties. If tombstones are enabled, every property that
+    /// was set is recorded as removed.
     pub fn clear(&mut self) {
+        self.touch();
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.extend(self.properties.keys());
+        }
         self.properties.clear();
     }
 
+    /// Enables tombstones, and can be chained. While enabled, removing a
+    /// property leaves a tombstone behind, so that an explicit removal can be
+    /// told apart from a property that was never set. Reading a removed
+    /// property still returns a default value, but
+    /// [`changed_since`](Props::changed_since) reports it as removed even if
+    /// the baseline never had it, which lets removals reach every peer when
+    /// syncing.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let mut props = Props::new().with_tombstones().with("poisoned", true);
+    /// props.remove("poisoned");
+    /// assert_eq!(props.get::<bool>("poisoned"), false);
+    /// assert!(!props.contains_key("poisoned"));
+    /// assert!(props.is_tombstone("poisoned"));
+    ///
+    /// // Removals show up in a diff against any baseline
+    /// let (delta, removed) = props.changed_since(&Props::new());
+    /// assert!(delta.is_empty());
+    /// assert_eq!(removed, [Ustr::from("poisoned")]);
+    ///
+    /// // Setting the property again brings it back to life
+    /// props.set("poisoned", true);
+    /// assert!(!props.is_tombstone("poisoned"));
+    ///
+    /// props.remove("poisoned");
+    /// props.compact();
+    /// assert_eq!(props.tombstones().count(), 0);
+    /// ```
+    pub fn with_tombstones(mut self) -> Self {
+        self.set_tombstones(true);
+        self
+    }
+
+    /// Enables or disables tombstones. Disabling them discards any that were
+    /// recorded. See [`with_tombstones`](Props::with_tombstones).
+    pub fn set_tombstones(&mut self, enabled: bool) {
+        if enabled {
+            self.tombstones.get_or_insert_default();
+        } else {
+            self.tombstones = None;
+        }
+    }
+
+    /// Returns true if the property was removed while tombstones were
+    /// enabled, and hasn't been set since.
+    pub fn is_tombstone(&self, name: impl Into<Ustr>) -> bool {
+        let name = name.into();
+        self.tombstones
+            .as_ref()
+            .is_some_and(|tombstones| tombstones.contains(&name))
+            && !self.properties.contains_key(&name)
+    }
+
+    /// Returns the names of removed properties, in order. See
+    /// [`is_tombstone`](Props::is_tombstone).
+    pub fn tombstones(&self) -> impl Iterator<Item = Ustr> + '_ {
+        self.tombstones
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|name| !self.properties.contains_key(name))
+    }
+
+    /// Discards all tombstones, once the removals they record have been
+    /// synced. Tombstones stay enabled.
+    pub fn compact(&mut self) {
+        if let Some(tombstones) = &mut self.tombstones {
+            tombstones.clear();
+        }
+    }
+
+    /// Returns the number of properties that have been set.
+    pub fn len(&self) -> usize {
+        self.properties.len()
+    }
+
+    /// Returns true if no properties have been set.
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
     /// Creates a borrowing iterator over all property names and values.
     pub fn iter(&self) -> Iter<Ustr, Value> {
         self.properties.iter()
@@ -750,12 +1737,158 @@ impl Props {
 
     /// Creates a mutable borrowing iterator over property values.
     pub fn values_mut(&mut self) -> ValuesMut<Ustr, Value> {
+        self.touch();
         self.properties.values_mut()
     }
+
+    /// Creates an iterator over only the boolean properties. Properties of
+    /// other types are skipped.
+    pub fn bools(&self) -> impl Iterator<Item = (Ustr, bool)> {
+        self.properties
+            .iter()
+            .filter_map(|(name, value)| match value {
+                Value::Bool(bool) => Some((*name, *bool)),
+                _ => None,
+            })
+    }
+
+    /// Creates an iterator over only the numeric properties. Properties of
+    /// other types are skipped.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let props = Props::new()
+    ///     .with("health", 100.0)
+    ///     .with("name", "clippy")
+    ///     .with("alive", true)
+    ///     .with("mana", 50.0);
+    ///
+    /// let nums: Vec<_> = props.nums().collect();
+    /// assert_eq!(nums, [(Ustr::from("health"), 100.0), (Ustr::from("mana"), 50.0)]);
+    ///
+    /// let bools: Vec<_> = props.bools().collect();
+    /// assert_eq!(bools, [(Ustr::from("alive"), true)]);
+    ///
+    /// let strs: Vec<_> = props.strs().collect();
+    /// assert_eq!(strs, [(Ustr::from("name"), "clippy")]);
+    /// ```
+    pub fn nums(&self) -> impl Iterator<Item = (Ustr, f32)> {
+        self.properties
+            .iter()
+            .filter_map(|(name, value)| match value {
+                Value::Num(num) => Some((*name, *num)),
+                _ => None,
+            })
+    }
+
+    /// Creates an iterator over only the string properties, both interned
+    /// and owned. Properties of other types are skipped.
+    pub fn strs(&self) -> impl Iterator<Item = (Ustr, &str)> {
+        self.properties
+            .iter()
+            .filter_map(|(name, value)| Some((*name, value.as_str()?)))
+    }
+
+    /// Creates an iterator over the properties under the given prefix, such as
+    /// `inventory.sword` and `inventory.shield` for the prefix `inventory`.
+    /// Properties are stored in order, so this only visits the properties
+    /// under the prefix rather than filtering all of them.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let props = Props::new()
+    ///     .with("inventory.sword", 1.0)
+    ///     .with("inventory.shield", 2.0)
+    ///     .with("inventory_size", 10.0)
+    ///     .with("name", "clippy");
+    ///
+    /// let names: Vec<_> = props.iter_prefix("inventory").map(|(name, _)| name.as_str()).collect();
+    /// assert_eq!(names, ["inventory.shield", "inventory.sword"]);
+    /// ```
+    pub fn iter_prefix(&self, prefix: impl Into<PropPath>) -> impl Iterator<Item = (Ustr, &Value)> {
+        let start = Ustr::from(&format!("{}.", prefix.into().as_str()));
+        self.properties
+            .range(start..)
+            .take_while(move |(name, _)| name.starts_with(start.as_str()))
+            .map(|(name, value)| (*name, value))
+    }
+
+    /// Sets several boolean flags under the given prefix to true.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let mut props = Props::new();
+    /// props.set_flags("status", &["poisoned", "burning", "slowed"]);
+    /// assert!(props.all_flags("status"));
+    ///
+    /// props.set("status.burning", false);
+    /// assert!(props.any_flag("status"));
+    /// assert!(!props.all_flags("status"));
+    /// ```
+    pub fn set_flags(&mut self, prefix: impl Into<PropPath>, flags: &[&str]) {
+        let prefix = prefix.into();
+        for flag in flags {
+            self.set(prefix.clone().join(*flag), true);
+        }
+    }
+
+    /// Returns true if any boolean flag under the given prefix is set. Other
+    /// types of properties under the prefix are ignored.
+    pub fn any_flag(&self, prefix: impl Into<PropPath>) -> bool {
+        self.iter_prefix(prefix)
+            .any(|(_, value)| matches!(value, Value::Bool(true)))
+    }
+
+    /// Returns true if every boolean flag under the given prefix is set,
+    /// including when there are none. Other types of properties under the
+    /// prefix are ignored.
+    pub fn all_flags(&self, prefix: impl Into<PropPath>) -> bool {
+        self.iter_prefix(prefix)
+            .all(|(_, value)| !matches!(value, Value::Bool(false)))
+    }
+
+    /// Returns a view of the properties under the given prefix. Names passed
+    /// to the view are joined to the prefix with a `.`, so they can be written
+    /// without repeating the prefix each time.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let mut props = Props::new();
+    /// props.sub("inventory.sword").set("count", 3.0);
+    /// assert_eq!(props["inventory.sword.count"], 3.0);
+    /// assert_eq!(props.sub("inventory.sword").get::<f32>("count"), 3.0);
+    /// ```
+    pub fn sub(&mut self, prefix: impl Into<PropPath>) -> SubProps<'_> {
+        SubProps {
+            props: self,
+            prefix: prefix.into(),
+        }
+    }
+}
+
+/// Props are equal if they store exactly the same properties, with equal
+/// values. Unlike [`Props::semantic_eq`], a property set to `false` is not
+/// equal to one that isn't set. The [`generation`](Props::generation) is not
+/// compared, and neither are [tombstones](Props::with_tombstones), so a
+/// removed property is the same as one that was never set.
+///
+/// ```rust
+/// # use bevy_mod_props::*;
+/// let mut removed = Props::new().with_tombstones().with("poisoned", true);
+/// removed.remove("poisoned");
+/// assert!(removed.is_tombstone("poisoned"));
+/// assert!(removed == Props::new());
+/// ```
+impl PartialEq for Props {
+    fn eq(&self, other: &Props) -> bool {
+        self.properties == other.properties
+    }
 }
 
 static DEFAULT_VALUE: LazyLock<Value> = LazyLock::new(Value::default);
 
+/// Missing properties index as [`Value::default`], regardless of what type
+/// they would have. See the [`Props`] docs.
 impl<S: Into<Ustr>> Index<S> for Props {
     type Output = Value;
 
@@ -770,6 +1903,16 @@ impl<S: Into<Ustr>> IndexMut<S> for Props {
     }
 }
 
+impl<K: Into<Ustr>, V: Into<Value>> Extend<(K, V)> for Props {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.touch();
+        self.properties.extend(
+            iter.into_iter()
+                .map(|(name, value)| (name.into(), value.into())),
+        );
+    }
+}
+
 impl IntoIterator for Props {
     type Item = (Ustr, Value);
     type IntoIter = IntoIter<Ustr, Value>;
@@ -778,3 +1921,291 @@ impl IntoIterator for Props {
         self.properties.into_iter()
     }
 }
+
+/// Converts from a map with plain string keys, interning each of them.
+///
+/// ```rust
+/// # use std::collections::HashMap;
+/// # use bevy_mod_props::*;
+/// let mut map = HashMap::new();
+/// map.insert("name".to_string(), Value::from("clippy"));
+/// map.insert("gold".to_string(), Value::from(10.0));
+///
+/// let props = Props::from(map.clone());
+/// assert_eq!(props["name"], "clippy");
+/// assert_eq!(props["gold"], 10.0);
+/// assert_eq!(HashMap::from(props), map);
+/// ```
+impl From<HashMap<String, Value>> for Props {
+    fn from(map: HashMap<String, Value>) -> Props {
+        let mut props = Props::new();
+        props.extend(
+            map.into_iter()
+                .map(|(name, value)| (Ustr::from(&name), value)),
+        );
+        props
+    }
+}
+
+/// Like the conversion from a [`HashMap`], this interns every key.
+impl From<BTreeMap<String, Value>> for Props {
+    fn from(map: BTreeMap<String, Value>) -> Props {
+        let mut props = Props::new();
+        props.extend(
+            map.into_iter()
+                .map(|(name, value)| (Ustr::from(&name), value)),
+        );
+        props
+    }
+}
+
+impl From<Props> for HashMap<String, Value> {
+    fn from(props: Props) -> HashMap<String, Value> {
+        props
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect()
+    }
+}
+
+impl From<Props> for BTreeMap<String, Value> {
+    fn from(props: Props) -> BTreeMap<String, Value> {
+        props
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect()
+    }
+}
+
+/// Creates a [`Props`] from a list of `name => value` pairs. Names can be
+/// anything that converts into a [`Ustr`], and values anything that converts
+/// into a [`Value`].
+///
+/// ```rust
+/// # use bevy_mod_props::*;
+/// let props = props! {
+///     "health" => 100.0,
+///     "name" => "clippy",
+///     "alive" => true,
+/// };
+/// assert_eq!(
+///     props,
+///     Props::new()
+///         .with("health", 100.0)
+///         .with("name", "clippy")
+///         .with("alive", true)
+/// );
+/// assert!(props!{}.is_empty());
+/// ```
+#[macro_export]
+macro_rules! props {
+    ($($name:expr => $value:expr),* $(,)?) => {
+        $crate::Props::new()$(.with($name, $value))*
+    };
+}
+
+// -----------------------------------------------------------------------------
+// Shared Properties
+
+/// A read-only snapshot of a set of properties, returned by [`Props::share`].
+///
+/// Cloning a `SharedProps` doesn't copy the properties, so it can be handed
+/// to background tasks without holding a borrow of the world or cloning the
+/// whole map for each of them. All of the read-only methods of [`Props`] are
+/// available through [`Deref`].
+///
+/// ```rust
+/// # use bevy_mod_props::*;
+/// let props = Props::new().with("name", "clippy").with("gold", 10.0);
+/// let shared = props.share();
+///
+/// let task = std::thread::spawn({
+///     let shared = shared.clone();
+///     move || shared.get::<f32>("gold") * 2.0
+/// });
+/// assert_eq!(task.join().unwrap(), 20.0);
+/// assert_eq!(shared["name"], "clippy");
+/// ```
+#[derive(Clone, Debug)]
+pub struct SharedProps(Arc<Props>);
+
+impl Deref for SharedProps {
+    type Target = Props;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<Props> for SharedProps {
+    fn from(props: Props) -> Self {
+        SharedProps(Arc::new(props))
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Property Paths
+
+/// A dotted property name, built up one segment at a time.
+///
+/// ```rust
+/// # use bevy_mod_props::*;
+/// let path = PropPath::new("inventory").join("sword").join("count");
+/// assert_eq!(path.as_str(), "inventory.sword.count");
+///
+/// // paths can be used anywhere a property name is expected
+/// let props = Props::new().with(path, 3.0);
+/// assert_eq!(props["inventory.sword.count"], 3.0);
+/// ```
+#[derive(Default, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PropPath(String);
+
+impl PropPath {
+    /// Creates a new path with a single segment.
+    pub fn new(segment: impl Into<Ustr>) -> PropPath {
+        PropPath(segment.into().to_string())
+    }
+
+    /// Appends a segment to the path, separated by a `.`.
+    pub fn join(mut self, segment: impl Into<Ustr>) -> PropPath {
+        if !self.0.is_empty() {
+            self.0.push('.');
+        }
+        self.0.push_str(&segment.into());
+        self
+    }
+
+    /// Returns the full path as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for PropPath {
+    fn from(value: &str) -> Self {
+        PropPath(value.to_string())
+    }
+}
+
+impl From<String> for PropPath {
+    fn from(value: String) -> Self {
+        PropPath(value)
+    }
+}
+
+impl From<Ustr> for PropPath {
+    fn from(value: Ustr) -> Self {
+        PropPath(value.to_string())
+    }
+}
+
+impl From<PropPath> for Ustr {
+    fn from(value: PropPath) -> Self {
+        Ustr::from(&value.0)
+    }
+}
+
+impl From<&PropPath> for Ustr {
+    fn from(value: &PropPath) -> Self {
+        Ustr::from(&value.0)
+    }
+}
+
+impl fmt::Display for PropPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A view of the properties under a common prefix, returned by
+/// [`Props::sub`].
+pub struct SubProps<'a> {
+    props: &'a mut Props,
+    prefix: PropPath,
+}
+
+impl<'a> SubProps<'a> {
+    fn path(&self, name: impl Into<Ustr>) -> Ustr {
+        self.prefix.clone().join(name).into()
+    }
+
+    /// Returns a property value under the prefix. See [`Props::get`].
+    pub fn get<'b, T>(&'b self, name: impl Into<Ustr>) -> T
+    where
+        T: From<&'b Value> + Default,
+    {
+        self.props.get(self.path(name))
+    }
+
+    /// Returns a mutable reference to a property value under the prefix. See
+    /// [`Props::get_mut`].
+    pub fn get_mut<T>(&mut self, name: impl Into<Ustr>) -> &mut T
+    where
+        Value: AsMut<T>,
+    {
+        let path = self.path(name);
+        self.props.get_mut(path)
+    }
+
+    /// Sets a property value under the prefix.
+    pub fn set(&mut self, name: impl Into<Ustr>, value: impl Into<Value>) {
+        let path = self.path(name);
+        self.props.set(path, value);
+    }
+
+    /// Removes a property under the prefix.
+    ///
+    /// ```rust
+    /// # use bevy_mod_props::*;
+    /// let mut props = Props::new().with("inventory.sword.count", 3.0);
+    /// props.sub("inventory.sword").remove("count");
+    /// assert!(!props.contains_key("inventory.sword.count"));
+    /// ```
+    pub fn remove(&mut self, name: impl Into<Ustr>) {
+        let path = self.path(name);
+        self.props.remove(path);
+    }
+
+    /// Returns a view of the properties under a further prefix.
+    pub fn sub(&mut self, prefix: impl Into<Ustr>) -> SubProps<'_> {
+        SubProps {
+            prefix: self.prefix.clone().join(prefix),
+            props: self.props,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use ustr::Ustr;
+
+    use crate::{Props, Value};
+
+    #[test]
+    fn props_macro_matches_with_chain() {
+        let name = String::from("clippy");
+        let title: Arc<str> = Arc::from("paperclip");
+        let props = props! {
+            "health" => 100.0,
+            Ustr::from("alive") => true,
+            "name" => name.clone(),
+            format!("{name}.title") => Value::StrOwned(title.clone()),
+        };
+        let expected = Props::new()
+            .with("health", 100.0)
+            .with(Ustr::from("alive"), true)
+            .with("name", name.clone())
+            .with(format!("{name}.title"), Value::StrOwned(title));
+        assert_eq!(props, expected);
+        assert_eq!(props["clippy.title"], "paperclip");
+
+        // The trailing comma is optional
+        assert_eq!(
+            props! { "health" => 100.0, "alive" => true },
+            props! { "health" => 100.0, "alive" => true, },
+        );
+        assert_eq!(props! {}, Props::new());
+    }
+}
@@ -1,5 +1,7 @@
 //! Defines extension traits for using the registry with bevy
 
+use std::ops::Deref;
+
 use bevy_ecs::{
     entity::{Entity, EntityDoesNotExistError, EntityHashSet},
     system::EntityCommands,
@@ -71,9 +73,44 @@ pub enum EntityNamedError {
     EntityDoesNotExist(#[from] EntityDoesNotExistError),
 }
 
+/// Whether a name is in use, returned by [`RegistryLookupExt::name_status`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NameStatus {
+    /// No entity is registered with the name.
+    Free,
+    /// The name belongs to an entity that exists.
+    Live(Entity),
+    /// The name is registered to an entity that no longer exists.
+    Stale(Entity),
+}
+
 pub trait RegistryLookupExt {
     fn lookup_name(&self, name: impl Into<Ustr>) -> Result<Entity, EntityNotFoundError>;
 
+    /// Reports whether a name is registered, and if so whether its entity
+    /// still exists. This can tell apart the two reasons
+    /// [`entity_named`](RegistryLookupExt::entity_named) might fail.
+    ///
+    /// ```rust
+    /// # use bevy_ecs::prelude::*;
+    /// # use bevy_mod_props::*;
+    /// let mut world = World::new();
+    /// let clippy = world.spawn(Identity::new("clippy")).id();
+    /// world.flush();
+    /// assert_eq!(world.name_status("clippy"), NameStatus::Live(clippy));
+    /// assert_eq!(world.name_status("nobody"), NameStatus::Free);
+    ///
+    /// // Names registered in bulk are not cleaned up when their entity is
+    /// // despawned
+    /// let ghost = world.spawn_empty().id();
+    /// world
+    ///     .resource_mut::<Registry>()
+    ///     .register_bulk([(ghost, Ustr::from("ghost"), None)]);
+    /// world.despawn(ghost);
+    /// assert_eq!(world.name_status("ghost"), NameStatus::Stale(ghost));
+    /// ```
+    fn name_status(&self, name: impl Into<Ustr>) -> NameStatus;
+
     fn lookup_class(&self, class: impl Into<Ustr>) -> &EntityHashSet;
 
     fn entity_named(&self, name: impl Into<Ustr>) -> Result<EntityRef, EntityNamedError>;
@@ -81,6 +118,25 @@ pub trait RegistryLookupExt {
     fn entity_class(&self, class: impl Into<Ustr>) -> EntityClassIter;
 }
 
+/// Iterates over the members of a class, returned by
+/// [`RegistryLookupExt::entity_class`]. The number of members is known up
+/// front.
+///
+/// ```rust
+/// # use bevy_ecs::prelude::*;
+/// # use bevy_mod_props::*;
+/// let mut world = World::new();
+/// for _ in 0..3 {
+///     world.spawn(Class::new("guard"));
+/// }
+/// world.spawn(Class::new("thief"));
+/// world.flush();
+///
+/// let guards = world.entity_class("guard");
+/// assert_eq!(guards.len(), 3);
+/// assert_eq!(world.entity_mut_class("thief").len(), 1);
+/// assert_eq!(world.entity_class("knight").len(), 0);
+/// ```
 pub struct EntityClassIter<'w> {
     entities: bevy_ecs::entity::hash_set::IntoIter,
     world: &'w World,
@@ -93,6 +149,18 @@ impl<'w> Iterator for EntityClassIter<'w> {
         let entity = self.entities.next()?;
         Some(self.world.entity(entity))
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // The bevy wrapper doesn't forward its size hint, but the set
+        // iterator it wraps knows exactly how many entities are left
+        self.entities.deref().size_hint()
+    }
+}
+
+impl<'w> ExactSizeIterator for EntityClassIter<'w> {
+    fn len(&self) -> usize {
+        self.entities.deref().len()
+    }
 }
 
 impl RegistryLookupExt for World {
@@ -112,6 +180,14 @@ impl RegistryLookupExt for World {
         }
     }
 
+    fn name_status(&self, name: impl Into<Ustr>) -> NameStatus {
+        match self.lookup_name(name) {
+            Ok(entity) if self.get_entity(entity).is_ok() => NameStatus::Live(entity),
+            Ok(entity) => NameStatus::Stale(entity),
+            Err(_) => NameStatus::Free,
+        }
+    }
+
     fn entity_named(&self, name: impl Into<Ustr>) -> Result<EntityRef, EntityNamedError> {
         let entity = self.lookup_name(name)?;
         let entity_ref = self.get_entity(entity)?;
@@ -143,6 +219,14 @@ impl<'w> RegistryLookupExt for DeferredWorld<'w> {
         }
     }
 
+    fn name_status(&self, name: impl Into<Ustr>) -> NameStatus {
+        match self.lookup_name(name) {
+            Ok(entity) if self.get_entity(entity).is_ok() => NameStatus::Live(entity),
+            Ok(entity) => NameStatus::Stale(entity),
+            Err(_) => NameStatus::Free,
+        }
+    }
+
     fn entity_named(&self, name: impl Into<Ustr>) -> Result<EntityRef, EntityNamedError> {
         let entity = self.lookup_name(name)?;
         let entity_ref = self.get_entity(entity)?;
@@ -190,6 +274,16 @@ impl<'w> Iterator for EntityClassMutIter<'w> {
         let entity_mut = unsafe { entity.fetch_mut(self.world_cell).unwrap() };
         Some(entity_mut)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entities.deref().size_hint()
+    }
+}
+
+impl<'w> ExactSizeIterator for EntityClassMutIter<'w> {
+    fn len(&self) -> usize {
+        self.entities.deref().len()
+    }
 }
 
 impl RegistryLookupMutExt for World {
@@ -252,4 +346,14 @@ impl<'w> Iterator for EntityClassDeferredIter<'w> {
         let entity_mut = unsafe { entity.fetch_deferred_mut(self.world_cell).unwrap() };
         Some(entity_mut)
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entities.deref().size_hint()
+    }
+}
+
+impl<'w> ExactSizeIterator for EntityClassDeferredIter<'w> {
+    fn len(&self) -> usize {
+        self.entities.deref().len()
+    }
 }
@@ -250,4 +250,108 @@ impl Registry {
     pub fn lookup_entity(&self, entity: Entity) -> &EntityRegistration {
         self.reigrations.get(&entity).unwrap_or(&*EMPTY_REG)
     }
+
+    /// Returns true if the entity belongs to the class, without iterating
+    /// over its members.
+    ///
+    /// ```rust
+    /// # use bevy_ecs::prelude::*;
+    /// # use bevy_mod_props::*;
+    /// let mut world = World::new();
+    /// world.init_resource::<Registry>();
+    /// let guard = world.spawn(Class::new("guard")).id();
+    /// world.spawn(Class::new("guard"));
+    /// assert!(world.resource::<Registry>().is_in_class(guard, "guard"));
+    /// assert!(!world.resource::<Registry>().is_in_class(guard, "thief"));
+    /// assert_eq!(world.resource::<Registry>().class_size("guard"), 2);
+    ///
+    /// world.entity_mut(guard).remove::<Class>();
+    /// assert!(!world.resource::<Registry>().is_in_class(guard, "guard"));
+    /// assert_eq!(world.resource::<Registry>().class_size("guard"), 1);
+    /// ```
+    pub fn is_in_class(&self, entity: Entity, class: impl Into<Ustr>) -> bool {
+        self.lookup_class(class).contains(&entity)
+    }
+
+    /// Returns the number of entities in the class.
+    pub fn class_size(&self, class: impl Into<Ustr>) -> usize {
+        self.lookup_class(class).len()
+    }
+
+    /// Registers the names and (optional) classes of many entities at once,
+    /// as if [`Identity`] and [`Class`] components had been inserted on them.
+    /// This is much faster than inserting the components one at a time when
+    /// loading large scenes, since it doesn't go through the component hooks.
+    ///
+    /// An entry whose name is already used by a different entity (either
+    /// previously or earlier in the same batch) is rejected entirely, and is
+    /// returned as `(entity, name)`. Re-registering the same name for the same
+    /// entity is allowed. An entry without a class leaves the entity in the
+    /// class it already has.
+    ///
+    /// ```rust
+    /// # use bevy_ecs::prelude::*;
+    /// # use bevy_mod_props::*;
+    /// let mut world = World::new();
+    /// let [alice, bob, impostor] = [(); 3].map(|_| world.spawn_empty().id());
+    ///
+    /// let mut registry = Registry::default();
+    /// let rejected = registry.register_bulk([
+    ///     (alice, Ustr::from("alice"), Some(Ustr::from("guard"))),
+    ///     (bob, Ustr::from("bob"), None),
+    ///     (impostor, Ustr::from("alice"), Some(Ustr::from("guard"))),
+    /// ]);
+    ///
+    /// assert_eq!(rejected, [(impostor, Ustr::from("alice"))]);
+    /// assert_eq!(registry.lookup_name("alice").unwrap(), alice);
+    /// assert_eq!(registry.lookup_name("bob").unwrap(), bob);
+    /// assert!(registry.lookup_class("guard").contains(&alice));
+    /// assert!(!registry.lookup_class("guard").contains(&impostor));
+    ///
+    /// // Renaming alice without a class keeps the class
+    /// registry.register_bulk([(alice, Ustr::from("alicia"), None)]);
+    /// assert_eq!(registry.lookup_name("alicia").unwrap(), alice);
+    /// assert!(registry.lookup_class("guard").contains(&alice));
+    /// ```
+    ///
+    /// Note that this only updates the registry. Entities registered this way
+    /// are not unregistered when they are despawned unless they also have the
+    /// matching [`Identity`] and [`Class`] components.
+    pub fn register_bulk(
+        &mut self,
+        entries: impl IntoIterator<Item = (Entity, Ustr, Option<Ustr>)>,
+    ) -> Vec<(Entity, Ustr)> {
+        let mut rejected = Vec::new();
+        for (entity, name, class) in entries {
+            if let Some(&owner) = self.named_entities.get(&name)
+                && owner != entity
+            {
+                rejected.push((entity, name));
+                continue;
+            }
+
+            let registration = self.reigrations.entry(entity).or_default();
+            // Replace any previous registration, like re-inserting the
+            // components would
+            if let Some(old_name) = registration.name.replace(name)
+                && old_name != name
+            {
+                self.named_entities.remove(&old_name);
+            }
+            // Without a class, the entity keeps the one it has, like inserting
+            // only an `Identity` would
+            if let Some(class) = class {
+                if let Some(old_class) = registration.class.replace(class)
+                    && old_class != class
+                    && let Some(members) = self.entity_classes.get_mut(&old_class)
+                {
+                    members.remove(&entity);
+                }
+                self.entity_classes.entry(class).or_default().insert(entity);
+            }
+
+            self.named_entities.insert(name, entity);
+        }
+        rejected
+    }
 }
@@ -1,12 +1,14 @@
 use std::{
+    collections::{HashMap, HashSet},
+    hash::{BuildHasher, Hash, Hasher, RandomState},
     ops::{Deref, DerefMut},
     path::PathBuf,
-    sync::LazyLock,
+    sync::{LazyLock, Mutex},
 };
 
 use bevy_app::{App, Plugin, PostUpdate};
 use bevy_asset::{
-    Asset, AssetApp, AssetLoader, AssetServer, Assets, Handle, LoadContext, io::Reader,
+    Asset, AssetApp, AssetLoader, AssetServer, Assets, Handle, LoadContext, LoadState, io::Reader,
 };
 use bevy_ecs::{
     entity::Entity,
@@ -14,27 +16,128 @@ use bevy_ecs::{
     message::{Message, Messages},
     resource::Resource,
     schedule::IntoScheduleConfigs,
-    system::{Res, ResMut},
+    system::{BoxedSystem, In, IntoSystem, Res, ResMut, SystemId},
     world::{Mut, World},
 };
-use bevy_mod_props::{Props, PropsMutExt, Registry};
+use bevy_mod_props::{PropChange, PropWatchers, Props, PropsExt, PropsMutExt, Registry, Value};
 use bevy_reflect::TypePath;
+use bevy_tasks::{AsyncComputeTaskPool, Task, futures::check_ready};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use trill::{core::engine::ResponseEngine, script::ScriptCompiler};
+use tracing::{error, warn};
+use trill::{
+    core::{
+        Delivery,
+        engine::{ResponseEngine, RuleHandle, fill_template},
+    },
+    script::{ScriptCompiler, ScriptReport},
+};
 
 pub use trill::*;
-use ustr::{Ustr, UstrMap};
+use ustr::{Ustr, UstrMap, UstrSet};
 
-pub struct TrillPlugin;
+#[derive(Default)]
+pub struct TrillPlugin {
+    emit_as_message: bool,
+    coalesce_requests: bool,
+    cache_matches: bool,
+    strict_fields: bool,
+    tracked_classes: Vec<Ustr>,
+    // Taken when the plugin is built
+    field_handlers: Mutex<Vec<(Ustr, BoxedSystem<In<ResponseField>>)>>,
+}
+
+impl TrillPlugin {
+    /// Also writes every [`Response`] as a message, so that a single system
+    /// can read all responses through a `MessageReader<Response>`. Responses
+    /// are still triggered on their entities either way.
+    pub fn emit_as_message(mut self, emit_as_message: bool) -> Self {
+        self.emit_as_message = emit_as_message;
+        self
+    }
+
+    /// Collapses requests for the same entity and concept that arrive in the
+    /// same frame into a single request. See
+    /// [`ResponseSettings::coalesce_requests`].
+    pub fn coalesce_requests(mut self, coalesce_requests: bool) -> Self {
+        self.coalesce_requests = coalesce_requests;
+        self
+    }
+
+    /// Reuses the matched rule for identical requests processed in the same
+    /// frame. See [`ResponseSettings::cache_matches`].
+    pub fn cache_matches(mut self, cache_matches: bool) -> Self {
+        self.cache_matches = cache_matches;
+        self
+    }
+
+    /// Warns about response fields that nothing handles. See
+    /// [`ResponseSettings::strict_fields`].
+    pub fn strict_fields(mut self, strict_fields: bool) -> Self {
+        self.strict_fields = strict_fields;
+        self
+    }
+
+    /// Keeps a `<class>_count` world prop up to date with the number of
+    /// entities in the class, as reported by the [`Registry`]. This lets rules
+    /// depend on how many members of a class are around, for example with a
+    /// criterion like `(guard_count in 3..)`. See [`TrackedClasses`].
+    pub fn track_class_count(mut self, class: impl Into<Ustr>) -> Self {
+        self.tracked_classes.push(class.into());
+        self
+    }
+
+    /// Runs a system whenever a response has the given field, after the
+    /// [`Response`] itself is triggered and the other requests in the same
+    /// batch have been matched. The system receives the responding
+    /// entity and the value of the field as a [`ResponseField`]. This lets
+    /// systems like audio or animation react to their own fields without
+    /// observing every response. See [`ResponseFieldHandlers`].
+    pub fn on_field<M>(
+        self,
+        field: impl Into<Ustr>,
+        system: impl IntoSystem<In<ResponseField>, (), M>,
+    ) -> Self {
+        let system: BoxedSystem<In<ResponseField>> = Box::new(IntoSystem::into_system(system));
+        self.field_handlers
+            .lock()
+            .unwrap()
+            .push((field.into(), system));
+        self
+    }
+}
 
 impl Plugin for TrillPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<EngineState>()
+            .insert_resource(ResponseSettings {
+                coalesce_requests: self.coalesce_requests,
+                cache_matches: self.cache_matches,
+                strict_fields: self.strict_fields,
+            })
             .init_asset::<TrillFile>()
             .init_asset_loader::<TrillFileLoader>()
             .add_message::<RequestResponse>()
             .add_message::<LoadResponseEngine>()
-            .add_systems(PostUpdate, (load_engine, manage_responses).chain());
+            .add_systems(
+                PostUpdate,
+                (update_class_counts, load_engine, manage_responses).chain(),
+            );
+        if self.emit_as_message {
+            app.add_message::<Response>();
+        }
+        if !self.tracked_classes.is_empty() {
+            app.insert_resource(TrackedClasses::new(self.tracked_classes.iter().copied()));
+        }
+
+        let world = app.world_mut();
+        world.init_resource::<ResponseFieldHandlers>();
+        for (field, system) in self.field_handlers.lock().unwrap().drain(..) {
+            let system = world.register_boxed_system(system);
+            world
+                .resource_mut::<ResponseFieldHandlers>()
+                .add(field, system);
+        }
     }
 }
 
@@ -50,6 +153,18 @@ pub enum TrillFileError {
     Io(#[from] std::io::Error),
     #[error("trill file not valid utf8: {0}")]
     NonUTF8(#[from] std::string::FromUtf8Error),
+    #[error("trill file failed to compile:\n{0}")]
+    CompileFailed(String),
+}
+
+/// Settings for loading a [`TrillFile`].
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TrillFileSettings {
+    /// Compile the file on its own while loading it, and fail the load with
+    /// [`TrillFileError::CompileFailed`] if it has any errors. This surfaces
+    /// script errors through the asset system, but will reject files that
+    /// depend on definitions from other files, so it is off by default.
+    pub validate_on_load: bool,
 }
 
 #[derive(Default)]
@@ -57,19 +172,25 @@ struct TrillFileLoader;
 
 impl AssetLoader for TrillFileLoader {
     type Asset = TrillFile;
-    type Settings = ();
+    type Settings = TrillFileSettings;
     type Error = TrillFileError;
 
     async fn load(
         &self,
         reader: &mut dyn Reader,
-        _settings: &Self::Settings,
+        settings: &Self::Settings,
         load_context: &mut LoadContext<'_>,
     ) -> Result<Self::Asset, Self::Error> {
         let name = format!("{}", load_context.path().file_stem().unwrap().display());
         let mut bytes = Vec::new();
         reader.read_to_end(&mut bytes).await?;
         let source = String::from_utf8(bytes)?;
+        if settings.validate_on_load {
+            let (engine, report) = ScriptCompiler::new().with_module(&name, &source).compile();
+            if engine.is_none() {
+                return Err(TrillFileError::CompileFailed(report.render()));
+            }
+        }
         Ok(TrillFile { name, source })
     }
 
@@ -103,6 +224,31 @@ impl LoadResponseEngine {
         self
     }
 
+    /// Removes all partition variables, including the defaults (`concept`,
+    /// `name` and `class`).
+    ///
+    /// Partitioning on `concept` in particular lets the engine skip most
+    /// rules for most requests, so removing it may make matching slower.
+    pub fn clear_partitions(mut self) -> Self {
+        self.partition_variables.clear();
+        self
+    }
+
+    /// Replaces all partition variables, including the defaults. See
+    /// [`LoadResponseEngine::clear_partitions`].
+    pub fn with_partitions<I>(mut self, variables: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Ustr>,
+    {
+        self.partition_variables = variables.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn partitions(&self) -> &[Ustr] {
+        &self.partition_variables
+    }
+
     pub fn add_source(mut self, source: TrillSource) -> Self {
         self.sources.push(source);
         self
@@ -135,6 +281,9 @@ pub enum EngineState {
         partition_variables: Vec<Ustr>,
         files: Vec<Handle<TrillFile>>,
     },
+    /// All the files have loaded, and are being compiled on the
+    /// [`AsyncComputeTaskPool`] so that large scripts don't stall the frame.
+    Compiling(Task<(Option<ResponseEngine>, ScriptReport)>),
     Loaded(ResponseEngine),
     LoadFailed,
 }
@@ -169,25 +318,216 @@ fn load_engine(
         files,
     } = &*engine_state
     {
+        // If any file failed to load, the engine never will
+        let failure = files
+            .iter()
+            .find_map(|handle| match asset_server.get_load_state(handle) {
+                Some(LoadState::Failed(load_error)) => Some((handle, load_error)),
+                _ => None,
+            });
+        if let Some((handle, load_error)) = failure {
+            match handle.path() {
+                Some(path) => error!("failed to load trill file {path}: {load_error}"),
+                None => error!("failed to load trill file: {load_error}"),
+            }
+            *engine_state = EngineState::LoadFailed;
+            return;
+        }
+
         let files = files
             .iter()
-            .map(|s| trill_files.get(s))
+            .map(|handle| Some((handle, trill_files.get(handle)?)))
             .collect::<Option<Vec<_>>>();
         if let Some(files) = files {
             let mut compiler = ScriptCompiler::new();
-            for file in files {
-                compiler.add_module(&file.name, &file.source);
+            for &(handle, file) in &files {
+                // Files with the same stem in different directories share a
+                // name, so tell them apart by path where there is one
+                let shared = files.iter().filter(|(_, f)| f.name == file.name).count() > 1;
+                match handle.path() {
+                    Some(path) if shared => compiler.add_module(path.to_string(), &file.source),
+                    _ => compiler.add_module(&file.name, &file.source),
+                };
             }
             for var in partition_variables {
                 compiler.add_partition_variable(*var);
             }
-            let (engine, report) = compiler.compile();
-            report.print();
-            *engine_state = match engine {
-                Some(engine) => EngineState::Loaded(engine),
-                None => EngineState::LoadFailed,
+            let task = AsyncComputeTaskPool::get().spawn(async move { compiler.compile() });
+            *engine_state = EngineState::Compiling(task);
+            // The task is checked from the next frame on
+            return;
+        }
+    }
+
+    if let EngineState::Compiling(task) = &mut *engine_state
+        && let Some((engine, report)) = check_ready(task)
+    {
+        report.log();
+        *engine_state = match engine {
+            Some(engine) => EngineState::Loaded(engine),
+            None => EngineState::LoadFailed,
+        }
+    }
+}
+
+/// The classes whose sizes are mirrored into world props by
+/// [`update_class_counts`]. This is inserted by [`TrillPlugin`] when any
+/// classes are tracked with [`TrillPlugin::track_class_count`].
+#[derive(Resource, Debug, Default, Clone)]
+pub struct TrackedClasses {
+    // Each class, along with the name of the prop holding its size
+    classes: Vec<(Ustr, Ustr)>,
+}
+
+impl TrackedClasses {
+    pub fn new(classes: impl IntoIterator<Item = Ustr>) -> TrackedClasses {
+        let mut tracked = TrackedClasses::default();
+        for class in classes {
+            tracked.add(class);
+        }
+        tracked
+    }
+
+    /// Starts tracking the size of a class.
+    pub fn add(&mut self, class: impl Into<Ustr>) {
+        let class = class.into();
+        if self.classes.iter().all(|(tracked, _)| *tracked != class) {
+            let prop = Ustr::from(&format!("{class}_count"));
+            self.classes.push((class, prop));
+        }
+    }
+
+    /// The name of the world prop holding the size of a class.
+    pub fn prop_name(&self, class: impl Into<Ustr>) -> Option<Ustr> {
+        let class = class.into();
+        self.classes
+            .iter()
+            .find(|(tracked, _)| *tracked == class)
+            .map(|(_, prop)| *prop)
+    }
+}
+
+/// Sets a `<class>_count` world prop for each of the [`TrackedClasses`].
+/// Props are only written when a count changes.
+pub fn update_class_counts(world: &mut World) {
+    let Some(tracked) = world.get_resource::<TrackedClasses>() else {
+        return;
+    };
+    let registry = world.get_resource::<Registry>();
+    let world_props = world.props();
+    let changed: Vec<_> = tracked
+        .classes
+        .iter()
+        .map(|(class, prop)| {
+            let count = registry.map_or(0, |registry| registry.class_size(*class));
+            (*prop, count as f32)
+        })
+        .filter(|(prop, count)| world_props.get_value(*prop) != Some(&Value::Num(*count)))
+        .collect();
+    if !changed.is_empty() {
+        world.props_mut().extend(changed);
+    }
+}
+
+/// Controls how [`manage_responses`] processes requests. This is inserted by
+/// [`TrillPlugin`]; if it doesn't exist, the defaults are used.
+#[derive(Resource, Debug, Default, Clone)]
+pub struct ResponseSettings {
+    /// When true, requests for the same entity and concept that are processed
+    /// together (in the same frame) are collapsed into one, so the entity
+    /// responds once. The first of the requests is kept, and the rest are
+    /// dropped even if they have different props. This prevents bursts of
+    /// duplicate requests from consuming several responses of a `list` or
+    /// `deplete` group at once.
+    pub coalesce_requests: bool,
+    /// When true, the rule matched for a request is remembered for the rest
+    /// of the frame, and reused for later requests with exactly the same
+    /// request and character props (including the `name` and `class` added
+    /// from the [`Registry`]). This saves matching many identical entities,
+    /// like a crowd of unnamed members of a class, against the rules again
+    /// and again. A response is still selected for each request, so response
+    /// groups advance as usual.
+    ///
+    /// Once the world props change during a frame (usually because a rule
+    /// set a global variable) nothing more is cached until the next frame.
+    /// See [`MatchCacheStats`] for how well the cache is doing.
+    pub cache_matches: bool,
+    /// When true, a warning is logged the first time a response has a field
+    /// that nothing handles, which usually means the field name has a typo
+    /// (like `lien` instead of `line`). A field is handled if it has a
+    /// handler or was claimed in the [`ResponseFieldHandlers`], or if it is a
+    /// `prop:` field. This is meant for debugging content, so it is off by
+    /// default.
+    pub strict_fields: bool,
+}
+
+/// How many matches were reused during the last run of [`manage_responses`],
+/// when [`ResponseSettings::cache_matches`] is enabled.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MatchCacheStats {
+    /// Requests that reused a rule matched earlier in the frame.
+    pub hits: usize,
+    /// Requests that were matched against the rules.
+    pub misses: usize,
+}
+
+// Remembers the rule matched for each distinct query during one run of
+// `manage_responses`
+#[derive(Default)]
+struct MatchCache {
+    hasher: RandomState,
+    entries: HashMap<u64, (Props, Props, Option<RuleHandle>)>,
+    world_generation: Option<u64>,
+    disabled: bool,
+    stats: MatchCacheStats,
+}
+
+impl MatchCache {
+    fn find_best_rule(
+        &mut self,
+        engine: &mut ResponseEngine,
+        request_props: &Props,
+        charicter_props: &Props,
+        world_props: &Props,
+    ) -> Option<RuleHandle> {
+        let mut rng = rand::rng();
+        // Every cached match depends on the world props
+        let generation = *self
+            .world_generation
+            .get_or_insert(world_props.generation());
+        if generation != world_props.generation() {
+            self.disabled = true;
+            self.entries.clear();
+        }
+        if self.disabled {
+            self.stats.misses += 1;
+            return engine.find_best_rule(request_props, charicter_props, world_props, &mut rng);
+        }
+
+        let mut hasher = self.hasher.build_hasher();
+        for props in [request_props, charicter_props] {
+            props.len().hash(&mut hasher);
+            for (name, value) in props.iter() {
+                name.hash(&mut hasher);
+                value.hash(&mut hasher);
             }
         }
+        let key = hasher.finish();
+        // Hashes can collide, so check the props are really the same
+        if let Some((request, charicter, rule)) = self.entries.get(&key)
+            && request == request_props
+            && charicter == charicter_props
+            && rule.is_none_or(|rule| engine.is_rule_enabled(rule))
+        {
+            self.stats.hits += 1;
+            return *rule;
+        }
+
+        self.stats.misses += 1;
+        let rule = engine.find_best_rule(request_props, charicter_props, world_props, &mut rng);
+        let entry = (request_props.clone(), charicter_props.clone(), rule);
+        self.entries.insert(key, entry);
+        rule
     }
 }
 
@@ -195,17 +535,82 @@ static CONCEPT: LazyLock<Ustr> = LazyLock::new(|| Ustr::from("concept"));
 
 #[derive(Message)]
 pub struct RequestResponse {
-    entity: Entity,
+    target: ResponseTarget,
     props: Props,
+    force_delivery: Option<Delivery>,
+}
+
+/// The entity a [`RequestResponse`] is addressed to.
+#[derive(Debug, Copy, Clone)]
+pub enum ResponseTarget {
+    Entity(Entity),
+    // Resolved through the `Registry` when the request is processed
+    Name(Ustr),
+    Class(Ustr),
 }
 
 impl RequestResponse {
     pub fn new(entity: Entity, concept: impl AsRef<str>) -> RequestResponse {
         RequestResponse {
-            entity,
+            target: ResponseTarget::Entity(entity),
             props: Props::new().with(*CONCEPT, concept.as_ref()),
+            force_delivery: None,
         }
     }
+
+    /// Requests a response from an entity without giving a concept, for
+    /// ambient reactions. Only rules that don't test the concept can match.
+    pub fn ambient(entity: Entity) -> RequestResponse {
+        RequestResponse {
+            target: ResponseTarget::Entity(entity),
+            props: Props::new(),
+            force_delivery: None,
+        }
+    }
+
+    /// Requests a response from the entity with the given name. The name is
+    /// looked up when the request is processed; if no entity has that name at
+    /// that point, a warning is logged and the request is dropped.
+    pub fn to_name(name: impl Into<Ustr>, concept: impl AsRef<str>) -> RequestResponse {
+        RequestResponse {
+            target: ResponseTarget::Name(name.into()),
+            props: Props::new().with(*CONCEPT, concept.as_ref()),
+            force_delivery: None,
+        }
+    }
+
+    /// Requests a response from every member of the given class. Each member
+    /// is matched against the rules separately, using its own props, and each
+    /// member that matches a rule receives its own [`Response`].
+    ///
+    /// Members are processed in ascending [`Entity`] order, so responses are
+    /// triggered in a stable order. The world props are shared between all
+    /// members, so a response that modifies them (through an observer) may
+    /// affect which responses later members receive. An empty class produces
+    /// no responses.
+    pub fn to_class(class: impl Into<Ustr>, concept: impl AsRef<str>) -> RequestResponse {
+        RequestResponse {
+            target: ResponseTarget::Class(class.into()),
+            props: Props::new().with(*CONCEPT, concept.as_ref()),
+            force_delivery: None,
+        }
+    }
+
+    pub fn target(&self) -> ResponseTarget {
+        self.target
+    }
+
+    /// Selects the response with the given delivery, instead of the one the
+    /// matched response group was written with. This only affects this
+    /// request; see [`ResponseEngine::find_best_match_with_delivery`].
+    pub fn force_delivery(mut self, delivery: Delivery) -> RequestResponse {
+        self.force_delivery = Some(delivery);
+        self
+    }
+
+    pub fn forced_delivery(&self) -> Option<Delivery> {
+        self.force_delivery
+    }
 }
 
 impl Deref for RequestResponse {
@@ -222,22 +627,304 @@ impl DerefMut for RequestResponse {
     }
 }
 
-#[derive(EntityEvent)]
+/// A response chosen by the engine. This is triggered as an [`EntityEvent`]
+/// on the responding entity, and is also written as a [`Message`] if
+/// `Messages<Response>` exists (see [`TrillPlugin::emit_as_message`]).
+///
+/// Before the response is sent, every `${variable}` in its fields is replaced
+/// with the value of the variable from the request, the responding entity or
+/// the world, in that order of precedence (see [`fill_template`]). Then any
+/// field named like `prop:<name>` is parsed with [`Value::parse`] and written
+/// to the `<name>` property of the responding entity. These fields are still
+/// included in the response.
+#[derive(EntityEvent, Message, Clone)]
 pub struct Response {
     entity: Entity,
     properties: UstrMap<String>,
 }
 
 impl Response {
+    /// Returns the entity that is responding.
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
     pub fn get(&self, key: impl Into<Ustr>) -> Option<&str> {
         self.properties.get(&key.into()).map(|s| s.as_str())
     }
 }
 
+/// The input to a response field handler, registered with
+/// [`TrillPlugin::on_field`].
+#[derive(Debug, Clone)]
+pub struct ResponseField {
+    /// The entity that is responding.
+    pub entity: Entity,
+    /// The value of the field in the response.
+    pub value: String,
+}
+
+/// The systems to run for each response field. Handlers are usually added
+/// with [`TrillPlugin::on_field`], but systems registered with the world can
+/// also be added directly.
+///
+/// When a response has several fields with handlers, they are run in order
+/// of field name, and handlers for the same field run in the order they were
+/// added. Handlers are only run for requests processed by
+/// [`manage_responses`], once every request has been matched, so they can use
+/// the world [`Props`], the [`Registry`] and send new requests.
+#[derive(Resource, Default)]
+pub struct ResponseFieldHandlers {
+    handlers: UstrMap<Vec<SystemId<In<ResponseField>>>>,
+    claimed: UstrSet,
+    unhandled: UstrSet,
+}
+
+impl ResponseFieldHandlers {
+    /// Adds a handler for a field.
+    pub fn add(&mut self, field: impl Into<Ustr>, system: SystemId<In<ResponseField>>) {
+        self.handlers.entry(field.into()).or_default().push(system);
+    }
+
+    /// Marks a field as handled without adding a handler, for fields that are
+    /// read by observers of [`Response`] instead. Claimed fields are never
+    /// reported by [`ResponseSettings::strict_fields`].
+    pub fn claim(&mut self, field: impl Into<Ustr>) {
+        self.claimed.insert(field.into());
+    }
+
+    /// Returns true if the field has a handler or has been claimed.
+    pub fn is_handled(&self, field: impl Into<Ustr>) -> bool {
+        let field = field.into();
+        self.handlers.contains_key(&field) || self.claimed.contains(&field)
+    }
+
+    /// Returns the unhandled fields that have been warned about so far, when
+    /// [`ResponseSettings::strict_fields`] is enabled.
+    pub fn unhandled_fields(&self) -> &UstrSet {
+        &self.unhandled
+    }
+}
+
+/// Triggered on an entity whenever one of its requests matches a rule, before
+/// its [`Response`] (if any) is triggered. Observers can use this to run game
+/// logic when specific rules fire, whether or not they produce a response.
+///
+/// This is only triggered for requests processed by [`manage_responses`].
+#[derive(EntityEvent, Debug, Clone)]
+pub struct RuleFired {
+    pub entity: Entity,
+    /// The name of the rule that fired.
+    pub rule: Ustr,
+    /// The name of the response group the response came from, or `None` if
+    /// the rule's response groups had no responses left to give.
+    pub response_group: Option<Ustr>,
+}
+
 pub fn manage_responses(world: &mut World) {
+    let settings = world
+        .get_resource::<ResponseSettings>()
+        .cloned()
+        .unwrap_or_default();
+    let coalesce_requests = settings.coalesce_requests;
+    let mut cache = settings.cache_matches.then(MatchCache::default);
+    // Field handlers and prop watchers run once the engine, props and
+    // registry are back in the world, so they can use them
+    let mut handled_responses = Vec::new();
+    let watched = WatchedProps::new(world);
+    let mut prop_changes = Vec::new();
+    with_engine(world, |world, engine, world_props, registry| {
+        world.resource_scope(|world, mut requests: Mut<Messages<RequestResponse>>| {
+            let mut handled = HashSet::new();
+            for request in requests.drain() {
+                let targets: Vec<Entity> = match request.target {
+                    ResponseTarget::Entity(entity) => vec![entity],
+                    ResponseTarget::Name(name) => match registry.lookup_name(name) {
+                        Ok(entity) => vec![entity],
+                        Err(error) => {
+                            warn!("dropping response request: {error}");
+                            continue;
+                        }
+                    },
+                    ResponseTarget::Class(class) => {
+                        let mut members: Vec<Entity> =
+                            registry.lookup_class(class).iter().copied().collect();
+                        members.sort();
+                        members
+                    }
+                };
+
+                for entity in targets {
+                    if coalesce_requests && !handled.insert((entity, request[*CONCEPT].clone())) {
+                        continue;
+                    }
+                    let before = watched.snapshot(world, world_props, entity);
+                    let found = find_response(
+                        world,
+                        engine,
+                        world_props,
+                        registry,
+                        entity,
+                        &request,
+                        cache.as_mut(),
+                    );
+                    watched.changes(world, world_props, entity, before, &mut prop_changes);
+                    let Some((fired, response)) = found else {
+                        continue;
+                    };
+                    world.trigger(fired);
+                    let Some(response) = response else {
+                        continue;
+                    };
+                    if let Some(mut messages) = world.get_resource_mut::<Messages<Response>>() {
+                        messages.write(response.clone());
+                    }
+                    world.trigger(response.clone());
+                    handled_responses.push(response);
+                }
+            }
+        })
+    });
+    PropWatchers::run(world, prop_changes);
+    for response in &handled_responses {
+        run_field_handlers(world, response, settings.strict_fields);
+    }
+    if let Some(cache) = cache {
+        world.insert_resource(cache.stats);
+    }
+}
+
+/// Adds synchronous response matching to [`World`].
+pub trait RespondExt {
+    /// Immediately finds the best response for an entity to a concept, using
+    /// the loaded engine. Like a [`RequestResponse`], this applies the
+    /// matching rule's instructions, but the response is returned rather than
+    /// triggered on the entity or written as a message.
+    ///
+    /// Returns `None` if the engine is not loaded, the entity does not exist,
+    /// or no rule matches.
+    fn respond(&mut self, entity: Entity, concept: impl AsRef<str>) -> Option<Response>;
+}
+
+impl RespondExt for World {
+    fn respond(&mut self, entity: Entity, concept: impl AsRef<str>) -> Option<Response> {
+        let request = RequestResponse::new(entity, concept);
+        let watched = WatchedProps::new(self);
+        let mut prop_changes = Vec::new();
+        let found = with_engine(self, |world, engine, world_props, registry| {
+            let before = watched.snapshot(world, world_props, entity);
+            let found = find_response(world, engine, world_props, registry, entity, &request, None);
+            watched.changes(world, world_props, entity, before, &mut prop_changes);
+            found
+        });
+        PropWatchers::run(self, prop_changes);
+        found.flatten().and_then(|(_, response)| response)
+    }
+}
+
+// The props that have watchers, which are compared before and after each
+// request so that changes made by rule instructions and `prop:` fields can be
+// reported to the watchers
+struct WatchedProps(Vec<Ustr>);
+
+impl WatchedProps {
+    fn new(world: &World) -> WatchedProps {
+        let names = world
+            .get_resource::<PropWatchers>()
+            .map(|watchers| watchers.watched().collect())
+            .unwrap_or_default();
+        WatchedProps(names)
+    }
+
+    // Records the values of the watched props of an entity and the world
+    fn snapshot(&self, world: &World, world_props: &Props, entity: Entity) -> Vec<Option<Value>> {
+        let character_props = world.get::<Props>(entity);
+        let mut values = Vec::with_capacity(self.0.len() * 2);
+        for name in &self.0 {
+            values.push(character_props.and_then(|props| props.get_value(*name).cloned()));
+            values.push(world_props.get_value(*name).cloned());
+        }
+        values
+    }
+
+    // Compares the watched props with a snapshot, and adds any differences
+    // to `changes`
+    fn changes(
+        &self,
+        world: &World,
+        world_props: &Props,
+        entity: Entity,
+        before: Vec<Option<Value>>,
+        changes: &mut Vec<PropChange>,
+    ) {
+        if self.0.is_empty() {
+            return;
+        }
+        let after = self.snapshot(world, world_props, entity);
+        let targets = [Some(entity), None].into_iter().cycle();
+        let names = self.0.iter().flat_map(|name| [*name, *name]);
+        for (((name, entity), old), new) in names.zip(targets).zip(before).zip(after) {
+            if old != new {
+                changes.push(PropChange {
+                    entity,
+                    name,
+                    old,
+                    new,
+                });
+            }
+        }
+    }
+}
+
+// Runs the handlers for each field of a response that has any, and warns
+// about fields that have none when `strict` is set
+fn run_field_handlers(world: &mut World, response: &Response, strict: bool) {
+    let Some(mut handlers) = world.get_resource_mut::<ResponseFieldHandlers>() else {
+        return;
+    };
+    if strict {
+        let mut unhandled: Vec<Ustr> = response
+            .properties
+            .keys()
+            .filter(|field| !field.starts_with("prop:") && !handlers.is_handled(**field))
+            .copied()
+            .collect();
+        unhandled.sort();
+        for field in unhandled {
+            if handlers.unhandled.insert(field) {
+                warn!("response field {field} is not handled by anything");
+            }
+        }
+    }
+
+    let mut calls = Vec::new();
+    for (field, value) in &response.properties {
+        for system in handlers.handlers.get(field).into_iter().flatten() {
+            let input = ResponseField {
+                entity: response.entity,
+                value: value.clone(),
+            };
+            calls.push((*field, *system, input));
+        }
+    }
+    calls.sort_by_key(|(field, _, _)| *field);
+
+    for (field, system, input) in calls {
+        if let Err(error) = world.run_system_with(system, input) {
+            warn!("failed to run handler for response field {field}: {error}");
+        }
+    }
+}
+
+// Runs a closure with the loaded engine, the world props and the registry, or
+// does nothing if the engine is not loaded
+fn with_engine<R>(
+    world: &mut World,
+    f: impl FnOnce(&mut World, &mut ResponseEngine, &mut Props, &Registry) -> R,
+) -> Option<R> {
     world.resource_scope(|world, mut engine_state: Mut<EngineState>| {
         let EngineState::Loaded(engine) = &mut *engine_state else {
-            return;
+            return None;
         };
 
         world.get_resource_or_init::<Props>();
@@ -245,34 +932,903 @@ pub fn manage_responses(world: &mut World) {
             let world_props = world_props.into_inner();
             world.get_resource_or_init::<Registry>();
             world.resource_scope(|world, registry: Mut<Registry>| {
-                world.resource_scope(|world, mut requests: Mut<Messages<RequestResponse>>| {
-                    for mut request in requests.drain() {
-                        let mut entity = world.entity_mut(request.entity);
-                        let charicter_props = entity.props_mut();
-
-                        let registration = registry.lookup_entity(request.entity);
-                        if let Some(name) = registration.name {
-                            request.props.set("name", name);
-                        }
-                        if let Some(class) = registration.class {
-                            request.props.set("class", class);
-                        }
-
-                        let mut rng = rand::rng();
-                        if let Some(properties) = engine.find_best_response(
-                            &request.props,
-                            charicter_props,
-                            world_props,
-                            &mut rng,
-                        ) {
-                            world.trigger(Response {
-                                entity: request.entity,
-                                properties: properties.clone(),
-                            });
-                        }
-                    }
-                })
+                Some(f(world, engine, world_props, &registry))
             })
         })
-    });
+    })
+}
+
+// Matches a request against the rules for a single entity, applying the
+// instructions of the matching rule. Returns `None` if no rule matched.
+fn find_response(
+    world: &mut World,
+    engine: &mut ResponseEngine,
+    world_props: &mut Props,
+    registry: &Registry,
+    entity: Entity,
+    request: &RequestResponse,
+    cache: Option<&mut MatchCache>,
+) -> Option<(RuleFired, Option<Response>)> {
+    let Ok(mut entity_mut) = world.get_entity_mut(entity) else {
+        warn!("dropping response request: entity {entity} does not exist");
+        return None;
+    };
+    let charicter_props = entity_mut.props_mut();
+
+    let mut request_props = request.props.clone();
+    let delivery = request.force_delivery;
+    let registration = registry.lookup_entity(entity);
+    if let Some(name) = registration.name {
+        request_props.set("name", name);
+    }
+    if let Some(class) = registration.class {
+        request_props.set("class", class);
+    }
+
+    let mut rng = rand::rng();
+    let found = match cache {
+        Some(cache) => {
+            let rule =
+                cache.find_best_rule(engine, &request_props, charicter_props, world_props)?;
+            // The cache only returns rules that are still enabled
+            engine.fire_rule(
+                rule,
+                &request_props,
+                charicter_props,
+                world_props,
+                delivery,
+                &mut rng,
+            )?
+        }
+        None => engine.find_best_match_with_delivery(
+            &request_props,
+            charicter_props,
+            world_props,
+            delivery,
+            &mut rng,
+        ),
+    };
+    let fired = RuleFired {
+        entity,
+        rule: found.rule?,
+        response_group: found.response_group,
+    };
+    let Some(properties) = found.response else {
+        return Some((fired, None));
+    };
+
+    // Variables set by the rule for this request shadow the request itself,
+    // which shadows the responder, which shadows the world
+    let scopes = [
+        &found.request_props,
+        &request_props,
+        &*charicter_props,
+        &*world_props,
+    ];
+    let properties: UstrMap<String> = properties
+        .iter()
+        .map(|(key, value)| (*key, fill_template(value, &scopes).into_owned()))
+        .collect();
+
+    // Fields like `prop:mood` are written into the props of the responder
+    for (key, value) in &properties {
+        if let Some(prop) = key.strip_prefix("prop:") {
+            charicter_props.set(prop, Value::parse(value));
+        }
+    }
+
+    let response = Response { entity, properties };
+    Some((fired, Some(response)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bevy_app::{App, Last, TaskPoolPlugin};
+    use bevy_asset::{AssetPlugin, AssetServer, Handle, LoadState};
+    use bevy_ecs::resource::Resource;
+    use bevy_ecs::{
+        message::MessageWriter,
+        message::{MessageReader, Messages},
+        observer::On,
+        system::{In, Res, ResMut},
+        world::World,
+    };
+    use bevy_mod_props::{
+        Class, Identity, PropChange, Props, PropsExt, PropsMutExt, Registry, WatchPropsExt,
+    };
+    use trill::script::ScriptCompiler;
+    use ustr::Ustr;
+
+    use crate::{
+        Delivery, EngineState, LoadResponseEngine, MatchCacheStats, RequestResponse, RespondExt,
+        Response, ResponseField, ResponseFieldHandlers, ResponseSettings, RuleFired,
+        TrackedClasses, TrillFile, TrillFileSettings, TrillPlugin, manage_responses,
+    };
+
+    #[derive(Resource, Default)]
+    struct Responses(Vec<String>);
+
+    fn test_world(script: &str) -> World {
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+
+        let mut world = World::new();
+        world.insert_resource(EngineState::Loaded(engine.unwrap()));
+        world.init_resource::<Messages<RequestResponse>>();
+        world.init_resource::<Responses>();
+        world
+    }
+
+    #[test]
+    fn request_response_by_name() {
+        let mut world = test_world(
+            r#"
+            (criterion ConceptIdle (concept == idle))
+            (criterion IsClippy (name == clippy))
+            (rule Idle (ConceptIdle IsClippy) (Idle))
+            (response Idle (line "hello"))
+        "#,
+        );
+
+        world.spawn(Identity::new("bystander"));
+        world.spawn(Identity::new("clippy")).observe(
+            |response: On<Response>, mut responses: ResMut<Responses>| {
+                responses.0.push(response.get("line").unwrap().to_string());
+            },
+        );
+        world.flush();
+
+        world.write_message(RequestResponse::to_name("clippy", "idle"));
+        world.write_message(RequestResponse::to_name("nobody", "idle"));
+        manage_responses(&mut world);
+
+        assert_eq!(world.resource::<Responses>().0, ["hello"]);
+    }
+
+    #[test]
+    fn ambient_request() {
+        let mut world = test_world(
+            r#"
+            (criterion ConceptGreet (concept == greet))
+            (criterion Awake (awake == true))
+            (rule Greet (ConceptGreet Awake) (Greet))
+            (rule Mutter (Awake) (Mutter))
+            (response Greet (line "hello"))
+            (response Mutter (line "hmm"))
+        "#,
+        );
+
+        let clippy = world
+            .spawn(Props::new().with("awake", true))
+            .observe(|response: On<Response>, mut responses: ResMut<Responses>| {
+                responses.0.push(response.get("line").unwrap().to_string());
+            })
+            .id();
+        world.flush();
+
+        world.write_message(RequestResponse::ambient(clippy));
+        manage_responses(&mut world);
+        world.write_message(RequestResponse::new(clippy, "greet"));
+        manage_responses(&mut world);
+
+        assert_eq!(world.resource::<Responses>().0, ["hmm", "hello"]);
+    }
+
+    #[test]
+    fn forced_delivery() {
+        let mut world = test_world(
+            r#"
+            (criterion ConceptIdle (concept == idle))
+            (rule Idle (ConceptIdle) (Idle))
+            (response Idle shuffle
+                (line "one")
+                (line "two")
+                (line "three")
+                (line "four"))
+        "#,
+        );
+
+        let clippy = world
+            .spawn_empty()
+            .observe(|response: On<Response>, mut responses: ResMut<Responses>| {
+                responses.0.push(response.get("line").unwrap().to_string());
+            })
+            .id();
+        world.flush();
+
+        for _ in 0..4 {
+            world
+                .write_message(RequestResponse::new(clippy, "idle").force_delivery(Delivery::List));
+            manage_responses(&mut world);
+        }
+        assert_eq!(
+            world.resource::<Responses>().0,
+            ["one", "two", "three", "four"]
+        );
+
+        // The group's own shuffle is untouched, so it still goes through
+        // every response before repeating any
+        world.resource_mut::<Responses>().0.clear();
+        for _ in 0..4 {
+            world.write_message(RequestResponse::new(clippy, "idle"));
+            manage_responses(&mut world);
+        }
+        let mut lines = world.resource::<Responses>().0.clone();
+        lines.sort();
+        assert_eq!(lines, ["four", "one", "three", "two"]);
+
+        // The forced list has run out
+        world.write_message(RequestResponse::new(clippy, "idle").force_delivery(Delivery::List));
+        manage_responses(&mut world);
+        assert_eq!(world.resource::<Responses>().0.len(), 4);
+    }
+
+    #[test]
+    fn request_response_by_class() {
+        let mut world = test_world(
+            r#"
+            (criterion ConceptGasp (concept == gasp))
+            (criterion IsCrowd (class == crowd))
+            (rule Gasp (ConceptGasp IsCrowd) (Gasp))
+            (response Gasp (line "gasp!"))
+        "#,
+        );
+
+        let observer = |response: On<Response>, mut responses: ResMut<Responses>| {
+            responses.0.push(response.get("line").unwrap().to_string());
+        };
+        for _ in 0..3 {
+            world.spawn(Class::new("crowd")).observe(observer);
+        }
+        world.spawn(Class::new("guard")).observe(observer);
+        world.flush();
+
+        world.write_message(RequestResponse::to_class("crowd", "gasp"));
+        manage_responses(&mut world);
+
+        assert_eq!(world.resource::<Responses>().0, ["gasp!", "gasp!", "gasp!"]);
+    }
+
+    #[test]
+    fn cache_identical_matches() {
+        let script = r#"
+            (criterion ConceptGasp (concept == gasp))
+            (criterion IsCrowd (class == crowd))
+            (criterion Startled (startled == true))
+            (rule Gasp (ConceptGasp IsCrowd) (Gasp))
+            (rule Shriek (ConceptGasp IsCrowd Startled) (Shriek))
+            (response Gasp loop (line "one") (line "two") (line "three"))
+            (response Shriek (line "eek!"))
+        "#;
+        let mut world = test_world(script);
+        world.insert_resource(ResponseSettings {
+            cache_matches: true,
+            ..Default::default()
+        });
+
+        let observer = |response: On<Response>, mut responses: ResMut<Responses>| {
+            responses.0.push(response.get("line").unwrap().to_string());
+        };
+        for _ in 0..50 {
+            world.spawn(Class::new("crowd")).observe(observer);
+        }
+        world
+            .spawn((Class::new("crowd"), Props::new().with("startled", true)))
+            .observe(observer);
+        world.flush();
+
+        world.write_message(RequestResponse::to_class("crowd", "gasp"));
+        manage_responses(&mut world);
+
+        // Only the first member and the startled one are matched against the
+        // rules, but every member still gets the next response in the loop
+        assert_eq!(
+            *world.resource::<MatchCacheStats>(),
+            MatchCacheStats {
+                hits: 49,
+                misses: 2
+            }
+        );
+        let responses = &world.resource::<Responses>().0;
+        let gasps: Vec<_> = responses.iter().filter(|line| *line != "eek!").collect();
+        assert_eq!(responses.len(), 51);
+        assert_eq!(gasps.len(), 50);
+        assert_eq!(gasps[..4], ["one", "two", "three", "one"]);
+
+        // Changing the world props stops caching for the rest of the frame
+        let mut world = test_world(&script.replace("(Gasp))", "(Gasp) $gasps:+1)"));
+        world.insert_resource(ResponseSettings {
+            cache_matches: true,
+            ..Default::default()
+        });
+        for _ in 0..5 {
+            world.spawn(Class::new("crowd"));
+        }
+        world.flush();
+
+        world.write_message(RequestResponse::to_class("crowd", "gasp"));
+        manage_responses(&mut world);
+
+        assert_eq!(
+            *world.resource::<MatchCacheStats>(),
+            MatchCacheStats { hits: 0, misses: 5 }
+        );
+        assert_eq!(world.props().get_num("gasps"), 5.0);
+    }
+
+    #[test]
+    fn coalesce_duplicate_requests() {
+        let mut world = test_world(
+            r#"
+            (criterion ConceptIdle (concept == idle))
+            (rule Idle (ConceptIdle) (Idle))
+            (response Idle list (line "one") (line "two") (line "three"))
+        "#,
+        );
+        world.insert_resource(ResponseSettings {
+            coalesce_requests: true,
+            ..Default::default()
+        });
+
+        let entity = world
+            .spawn_empty()
+            .observe(|response: On<Response>, mut responses: ResMut<Responses>| {
+                responses.0.push(response.get("line").unwrap().to_string());
+            })
+            .id();
+        world.flush();
+
+        world.write_message(RequestResponse::new(entity, "idle"));
+        world.write_message(RequestResponse::new(entity, "idle"));
+        manage_responses(&mut world);
+        assert_eq!(world.resource::<Responses>().0, ["one"]);
+
+        // Requests are only coalesced within a single frame
+        world.write_message(RequestResponse::new(entity, "idle"));
+        manage_responses(&mut world);
+        assert_eq!(world.resource::<Responses>().0, ["one", "two"]);
+    }
+
+    #[test]
+    fn rule_fired_events() {
+        let mut world = test_world(include_str!("../../../demo/assets/dialog.trl"));
+
+        world.spawn(Identity::new("clippy")).observe(
+            |fired: On<RuleFired>, mut responses: ResMut<Responses>| {
+                let group = fired.response_group.unwrap();
+                responses.0.push(format!("{} {group}", fired.rule));
+            },
+        );
+        world.flush();
+
+        world.write_message(RequestResponse::to_name("clippy", "idle"));
+        world.write_message(RequestResponse::to_name("clippy", "dance"));
+        manage_responses(&mut world);
+
+        assert_eq!(world.resource::<Responses>().0, ["Demo ClippyIdle"]);
+    }
+
+    #[test]
+    fn load_with_custom_partitions() {
+        let load = LoadResponseEngine::default().with_partitions(["mood"]);
+        assert_eq!(load.partitions(), ["mood"]);
+        assert!(
+            LoadResponseEngine::default()
+                .clear_partitions()
+                .partitions()
+                .is_empty()
+        );
+
+        let mut app = App::new();
+        app.add_plugins((
+            TaskPoolPlugin::default(),
+            AssetPlugin::default(),
+            TrillPlugin::default(),
+        ));
+        app.world_mut().write_message(
+            load.add_source_string(
+                "moods".to_string(),
+                r#"
+            (rule Cheer ((mood == happy)) (Cheer))
+            (rule Sulk ((mood == sad)) (Sulk))
+            (response Cheer (line "yay"))
+            (response Sulk (line "hmph"))
+            "#
+                .to_string(),
+            ),
+        );
+
+        for _ in 0..100 {
+            app.update();
+            if matches!(
+                app.world().resource::<EngineState>(),
+                EngineState::Loaded(_)
+            ) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let EngineState::Loaded(engine) = app.world().resource::<EngineState>() else {
+            panic!("engine failed to load");
+        };
+        // Each mood gets a partition of its own
+        assert_eq!(engine.partition_rules().len(), 2);
+    }
+
+    #[test]
+    fn class_counts_follow_registry() {
+        let mut app = App::new();
+        app.add_plugins((
+            TaskPoolPlugin::default(),
+            AssetPlugin::default(),
+            TrillPlugin::default().track_class_count("guard"),
+        ));
+        let prop = app
+            .world()
+            .resource::<TrackedClasses>()
+            .prop_name("guard")
+            .unwrap();
+        assert_eq!(prop, "guard_count");
+
+        app.update();
+        assert_eq!(app.world().get_prop::<f32>(prop), 0.0);
+
+        let guards: Vec<_> = (0..3)
+            .map(|_| app.world_mut().spawn(Class::new("guard")).id())
+            .collect();
+        app.world_mut().spawn(Class::new("thief"));
+        app.update();
+        assert_eq!(app.world().get_prop::<f32>(prop), 3.0);
+
+        app.world_mut().despawn(guards[0]);
+        app.update();
+        assert_eq!(app.world().get_prop::<f32>(prop), 2.0);
+    }
+
+    #[test]
+    fn compiles_in_background() {
+        let mut app = App::new();
+        app.add_plugins((
+            TaskPoolPlugin::default(),
+            AssetPlugin::default(),
+            TrillPlugin::default(),
+        ));
+        app.world_mut().write_message(
+            LoadResponseEngine::default().add_source_string(
+                "idle".to_string(),
+                r#"
+            (rule Idle ((concept == idle)) (Idle))
+            (response Idle (line "hello"))
+            "#
+                .to_string(),
+            ),
+        );
+
+        // Once the file is available, compiling starts without finishing in
+        // the same frame
+        for _ in 0..100 {
+            app.update();
+            if !matches!(
+                app.world().resource::<EngineState>(),
+                EngineState::Loading { .. }
+            ) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(matches!(
+            app.world().resource::<EngineState>(),
+            EngineState::Compiling(_)
+        ));
+
+        for _ in 0..100 {
+            app.update();
+            if !matches!(
+                app.world().resource::<EngineState>(),
+                EngineState::Compiling(_)
+            ) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(matches!(
+            app.world().resource::<EngineState>(),
+            EngineState::Loaded(_)
+        ));
+    }
+
+    #[test]
+    fn load_files_sharing_a_stem() {
+        let mut app = App::new();
+        app.add_plugins((
+            TaskPoolPlugin::default(),
+            AssetPlugin::default(),
+            TrillPlugin::default(),
+        ));
+        app.world_mut().write_message(
+            LoadResponseEngine::default()
+                .add_source_path("guard/dialog.trill")
+                .add_source_path("pirate/dialog.trill"),
+        );
+
+        for _ in 0..100 {
+            app.update();
+            if matches!(
+                app.world().resource::<EngineState>(),
+                EngineState::Loaded(_) | EngineState::LoadFailed
+            ) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let EngineState::Loaded(engine) = app.world().resource::<EngineState>() else {
+            panic!("engine failed to load");
+        };
+        // Both files are compiled as separate modules
+        let mut rules: Vec<_> = engine.partition_rules().into_iter().flatten().collect();
+        rules.sort();
+        assert_eq!(rules, ["GuardGreet", "PirateGreet"]);
+    }
+
+    #[test]
+    fn missing_file_fails_to_load() {
+        let mut app = App::new();
+        app.add_plugins((
+            TaskPoolPlugin::default(),
+            AssetPlugin::default(),
+            TrillPlugin::default(),
+        ));
+        app.world_mut()
+            .write_message(LoadResponseEngine::default().add_source_path("does_not_exist.trill"));
+
+        // Loading happens in the background, so give it some time to fail
+        for _ in 0..100 {
+            app.update();
+            if matches!(
+                app.world().resource::<EngineState>(),
+                EngineState::LoadFailed
+            ) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(matches!(
+            app.world().resource::<EngineState>(),
+            EngineState::LoadFailed
+        ));
+    }
+
+    #[test]
+    fn validate_on_load_rejects_invalid_file() {
+        let mut app = App::new();
+        app.add_plugins((
+            TaskPoolPlugin::default(),
+            AssetPlugin::default(),
+            TrillPlugin::default(),
+        ));
+        let handle: Handle<TrillFile> = app
+            .world()
+            .resource::<AssetServer>()
+            .load_with_settings("invalid.trill", |settings: &mut TrillFileSettings| {
+                settings.validate_on_load = true
+            });
+
+        // Loading happens in the background, so give it some time to fail
+        let mut load_state = None;
+        for _ in 0..100 {
+            app.update();
+            load_state = app
+                .world()
+                .resource::<AssetServer>()
+                .get_load_state(&handle);
+            if matches!(load_state, Some(LoadState::Failed(_))) {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let Some(LoadState::Failed(error)) = load_state else {
+            panic!("expected load to fail, got {load_state:?}");
+        };
+        assert!(error.to_string().contains("failed to compile"));
+    }
+
+    #[test]
+    fn responses_as_messages() {
+        let (engine, _) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module(
+                "script.trl",
+                r#"
+                (criterion ConceptIdle (concept == idle))
+                (rule Idle (ConceptIdle) (Idle))
+                (response Idle (line "hello"))
+            "#,
+            )
+            .compile();
+
+        let mut app = App::new();
+        app.add_plugins((
+            TaskPoolPlugin::default(),
+            AssetPlugin::default(),
+            TrillPlugin::default().emit_as_message(true),
+        ));
+        app.insert_resource(EngineState::Loaded(engine.unwrap()));
+        app.init_resource::<Responses>();
+        app.add_systems(
+            Last,
+            |mut reader: MessageReader<Response>, mut responses: ResMut<Responses>| {
+                for response in reader.read() {
+                    responses.0.push(response.get("line").unwrap().to_string());
+                }
+            },
+        );
+
+        let first = app.world_mut().spawn_empty().id();
+        let second = app.world_mut().spawn_empty().id();
+        app.world_mut()
+            .write_message(RequestResponse::new(first, "idle"));
+        app.world_mut()
+            .write_message(RequestResponse::new(second, "idle"));
+        app.update();
+
+        assert_eq!(app.world().resource::<Responses>().0, ["hello", "hello"]);
+    }
+
+    #[test]
+    fn response_field_handlers() {
+        let (engine, _) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module(
+                "script.trl",
+                r#"
+                (criterion ConceptIdle (concept == idle))
+                (rule Idle (ConceptIdle) (Idle))
+                (response Idle (line "hello" anim "wave"))
+            "#,
+            )
+            .compile();
+
+        let mut app = App::new();
+        app.add_plugins((
+            TaskPoolPlugin::default(),
+            AssetPlugin::default(),
+            TrillPlugin::default().on_field(
+                "anim",
+                |field: In<ResponseField>, mut responses: ResMut<Responses>| {
+                    responses.0.push(field.value.clone());
+                },
+            ),
+        ));
+        app.insert_resource(EngineState::Loaded(engine.unwrap()));
+        app.init_resource::<Responses>();
+
+        let entity = app.world_mut().spawn_empty().id();
+        app.world_mut()
+            .write_message(RequestResponse::new(entity, "idle"));
+        app.update();
+
+        assert_eq!(app.world().resource::<Responses>().0, ["wave"]);
+    }
+
+    #[test]
+    fn field_handlers_use_world_resources() {
+        let (engine, _) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module(
+                "script.trl",
+                r#"
+                (criterion ConceptIdle (concept == idle))
+                (criterion ConceptReply (concept == reply))
+                (rule Idle (ConceptIdle) (Idle))
+                (rule Reply (ConceptReply) (Reply))
+                (response Idle (line "hello" next "reply"))
+                (response Reply (line "hello yourself"))
+            "#,
+            )
+            .compile();
+
+        let mut app = App::new();
+        app.add_plugins((
+            TaskPoolPlugin::default(),
+            AssetPlugin::default(),
+            TrillPlugin::default()
+                .on_field(
+                    "next",
+                    |field: In<ResponseField>,
+                     world_props: Res<Props>,
+                     registry: Res<Registry>,
+                     mut requests: MessageWriter<RequestResponse>| {
+                        assert_eq!(world_props.get::<f32>("weather"), 1.0);
+                        assert_eq!(registry.lookup_name("clippy").ok(), Some(field.entity));
+                        requests.write(RequestResponse::new(field.entity, &field.value));
+                    },
+                )
+                .on_field(
+                    "line",
+                    |field: In<ResponseField>, mut responses: ResMut<Responses>| {
+                        responses.0.push(field.value.clone());
+                    },
+                ),
+        ));
+        app.insert_resource(EngineState::Loaded(engine.unwrap()));
+        app.init_resource::<Responses>();
+        app.world_mut().props_mut().set("weather", 1.0);
+
+        let entity = app.world_mut().spawn(Identity::new("clippy")).id();
+        app.world_mut()
+            .write_message(RequestResponse::new(entity, "idle"));
+        app.update();
+        app.update();
+
+        assert_eq!(
+            app.world().resource::<Responses>().0,
+            ["hello", "hello yourself"]
+        );
+    }
+
+    #[test]
+    fn strict_response_fields() {
+        let (engine, _) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module(
+                "script.trl",
+                r#"
+                (criterion ConceptIdle (concept == idle))
+                (rule Idle (ConceptIdle) (Idle))
+                (response Idle (line "hello" lien "hello" anim "wave" prop:mood "happy"))
+            "#,
+            )
+            .compile();
+
+        let mut app = App::new();
+        app.add_plugins((
+            TaskPoolPlugin::default(),
+            AssetPlugin::default(),
+            TrillPlugin::default()
+                .strict_fields(true)
+                .on_field("line", |_: In<ResponseField>| {}),
+        ));
+        app.insert_resource(EngineState::Loaded(engine.unwrap()));
+        app.world_mut()
+            .resource_mut::<ResponseFieldHandlers>()
+            .claim("anim");
+
+        let entity = app.world_mut().spawn_empty().id();
+        for _ in 0..2 {
+            app.world_mut()
+                .write_message(RequestResponse::new(entity, "idle"));
+            app.world_mut()
+                .write_message(RequestResponse::new(entity, "idle"));
+            app.update();
+        }
+
+        // Only the typo is reported, and only once
+        let handlers = app.world().resource::<ResponseFieldHandlers>();
+        assert_eq!(
+            handlers.unhandled_fields().iter().collect::<Vec<_>>(),
+            [&Ustr::from("lien")]
+        );
+    }
+
+    #[test]
+    fn rules_notify_prop_watchers() {
+        let (engine, _) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module(
+                "script.trl",
+                r#"
+                (criterion ConceptGreet (concept == greet))
+                (rule Greet (ConceptGreet) (Greet) greeted :+ 1 $greetings :+ 1 unwatched := true)
+                (response Greet (line "hello" prop:mood "happy"))
+            "#,
+            )
+            .compile();
+
+        let mut app = App::new();
+        app.add_plugins((
+            TaskPoolPlugin::default(),
+            AssetPlugin::default(),
+            TrillPlugin::default(),
+        ));
+        app.insert_resource(EngineState::Loaded(engine.unwrap()));
+        app.init_resource::<Responses>();
+        let record = |change: In<PropChange>, mut responses: ResMut<Responses>| {
+            let owner = if change.entity.is_some() { "" } else { "$" };
+            let new = change.new.as_ref().map(ToString::to_string);
+            responses.0.push(format!("{owner}{} {new:?}", change.name));
+        };
+        for name in ["greeted", "greetings", "mood"] {
+            app.world_mut().watch_prop(name, record);
+        }
+
+        let entity = app.world_mut().spawn_empty().id();
+        for _ in 0..2 {
+            app.world_mut()
+                .write_message(RequestResponse::new(entity, "greet"));
+            app.update();
+        }
+
+        // The mood doesn't change the second time, so it isn't reported
+        let mut changes = app.world().resource::<Responses>().0.clone();
+        changes.sort();
+        assert_eq!(
+            changes,
+            [
+                "$greetings Some(\"1\")",
+                "$greetings Some(\"2\")",
+                "greeted Some(\"1\")",
+                "greeted Some(\"2\")",
+                "mood Some(\"happy\")",
+            ]
+        );
+    }
+
+    #[test]
+    fn fill_response_templates() {
+        let mut world = test_world(
+            r#"
+            (criterion ConceptGreet (concept == greet))
+            (rule Greet (ConceptGreet) (Greet) ?mood := cheerful)
+            (response Greet (line "Hello, ${player_name}! I have ${gold} gold, worth $$${gold}${missing}. (${mood})"))
+        "#,
+        );
+        world.insert_resource(Props::new().with("player_name", "Ada"));
+
+        let entity = world.spawn(Props::new().with("gold", 12.0)).id();
+        let response = world.respond(entity, "greet").unwrap();
+        assert_eq!(
+            response.get("line"),
+            Some("Hello, Ada! I have 12 gold, worth $12. (cheerful)")
+        );
+    }
+
+    #[test]
+    fn respond_immediately() {
+        let mut world = test_world(
+            r#"
+            (criterion ConceptGreet (concept == greet))
+            (criterion Unmet (met == false))
+            (rule Introduce (ConceptGreet Unmet) (Introduce) met := true)
+            (rule Greet (ConceptGreet) (Greet))
+            (response Introduce (line "nice to meet you"))
+            (response Greet (line "hello again"))
+        "#,
+        );
+
+        let entity = world.spawn(Props::new().with("met", false)).id();
+        let response = world.respond(entity, "greet").unwrap();
+        assert_eq!(response.entity(), entity);
+        assert_eq!(response.get("line"), Some("nice to meet you"));
+
+        // The instructions were applied, so the next response differs
+        let response = world.respond(entity, "greet").unwrap();
+        assert_eq!(response.get("line"), Some("hello again"));
+
+        assert!(world.respond(entity, "wave").is_none());
+    }
+
+    #[test]
+    fn response_prop_fields() {
+        let mut world = test_world(
+            r#"
+            (criterion ConceptTaunt (concept == taunt))
+            (rule Taunt (ConceptTaunt) (Taunt))
+            (response Taunt (line "grr" prop:mood "angry" prop:anger "3" prop:hostile "true"))
+        "#,
+        );
+
+        let entity = world.spawn_empty().id();
+        world.write_message(RequestResponse::new(entity, "taunt"));
+        manage_responses(&mut world);
+
+        let props = world.entity(entity).get::<Props>().unwrap();
+        assert_eq!(props["mood"], "angry");
+        assert_eq!(props["anger"], 3.0);
+        assert_eq!(props["hostile"], true);
+    }
 }
@@ -1,5 +1,8 @@
 use core::f32;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Write;
 use std::hash::BuildHasherDefault;
 use std::hash::Hash;
 use std::hash::Hasher;
@@ -7,18 +10,25 @@ use std::hash::Hasher;
 use bevy_mod_props::Props;
 use bevy_mod_props::Value;
 use itertools::Itertools;
+use rand::Rng;
 use rand::rngs::ThreadRng;
 use rand::seq::IndexedRandom;
 use rand::seq::SliceRandom;
 use ustr::Ustr;
 use ustr::UstrMap;
+use ustr::UstrSet;
 
+use crate::Delivery;
+use crate::InstructionTarget;
 use crate::Operation;
 use crate::ResponseEngineCompiler;
 
 pub(crate) struct Encoder {
     next_float: f32,
     encodings: UstrMap<f32>,
+    // Strings values of ordinal variables are instead encoded as their position
+    // in the ordinal declaration, so they can be compared with ranges.
+    ordinals: UstrMap<UstrMap<f32>>,
 }
 
 impl Default for Encoder {
@@ -26,6 +36,7 @@ impl Default for Encoder {
         Encoder {
             next_float: f32::MIN,
             encodings: UstrMap::default(),
+            ordinals: UstrMap::default(),
         }
     }
 }
@@ -40,40 +51,100 @@ impl Encoder {
         *encoding
     }
 
-    pub fn encode(&mut self, value: Value) -> f32 {
+    pub fn add_ordinal(&mut self, variable: Ustr, values: &[Ustr]) {
+        let encodings = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (*value, i as f32))
+            .collect();
+        self.ordinals.insert(variable, encodings);
+    }
+
+    // Returns the number of values declared for an ordinal variable
+    pub fn ordinal_len(&self, variable: Ustr) -> Option<usize> {
+        self.ordinals.get(&variable).map(UstrMap::len)
+    }
+
+    pub fn encode_ordinal(&self, variable: Ustr, ustr: Ustr) -> Option<f32> {
+        self.ordinals.get(&variable)?.get(&ustr).copied()
+    }
+
+    pub fn encode_str(&mut self, variable: Ustr, ustr: Ustr) -> f32 {
+        match self.encode_ordinal(variable, ustr) {
+            Some(encoding) => encoding,
+            None => self.encode_ustr(ustr),
+        }
+    }
+
+    pub fn encode(&mut self, variable: Ustr, value: Value) -> f32 {
         match value {
             Value::Bool(false) => 0.0,
             Value::Bool(true) => 1.0,
             Value::Num(num) => num,
-            Value::Str(ustr) => self.encode_ustr(ustr),
+            Value::Str(ustr) => self.encode_str(variable, ustr),
+            // Owned strings are never interned here. If no matching string
+            // has been interned then no criterion can mention it, so encode it
+            // as a value that fails every comparison.
+            Value::StrOwned(str) => match Ustr::from_existing(&str) {
+                Some(ustr) => self.encode_str(variable, ustr),
+                None => f32::NAN,
+            },
         }
     }
 }
 
+/// A source of facts that rules can be matched against.
+///
+/// This is implemented for [`Props`], but any data structure that can list
+/// its variables and their values can be used to query a [`ResponseEngine`]
+/// with [`ResponseEngine::find_best_response_for`].
+pub trait Facts {
+    /// Iterates over every variable and its value. The order does not matter,
+    /// but if a variable is listed more than once only one of its values will
+    /// be used.
+    fn iter(&self) -> impl Iterator<Item = (Ustr, Value)>;
+}
+
+impl Facts for Props {
+    fn iter(&self) -> impl Iterator<Item = (Ustr, Value)> {
+        Props::iter(self).map(|(name, value)| (*name, value.clone()))
+    }
+}
+
 #[derive(Debug)]
 struct Query {
     scanners: Vec<Scanner>,
+    // Memoized criterion results, indexed by criterion. The props can't change
+    // during a query, so each criterion only needs to be checked once.
+    criteria_results: Vec<Option<bool>>,
+    // Every variable looked up during the query, whether or not it was set.
+    // Only recorded when explaining a match.
+    accessed: Option<UstrSet>,
+    // Every lookup made during the query, in order
+    #[cfg(test)]
+    scans: Vec<Ustr>,
 }
 
 impl Query {
-    fn build<'q, I>(props_list: I, encoder: &mut Encoder) -> Query
+    fn build<I>(scanners: I, num_criteria: usize) -> Query
     where
-        I: IntoIterator<Item = &'q Props>,
+        I: IntoIterator<Item = Scanner>,
     {
-        let scanners = props_list
-            .into_iter()
-            .map(|s| {
-                let items = s
-                    .iter()
-                    .map(|(name, value)| (*name, encoder.encode(*value)))
-                    .collect::<Vec<_>>();
-                Scanner::new(items)
-            })
-            .collect();
-        Query { scanners }
+        Query {
+            scanners: scanners.into_iter().collect(),
+            criteria_results: vec![None; num_criteria],
+            accessed: None,
+            #[cfg(test)]
+            scans: Vec::new(),
+        }
     }
 
     fn scan_to(&mut self, var_name: Ustr) -> Option<f32> {
+        if let Some(accessed) = &mut self.accessed {
+            accessed.insert(var_name);
+        }
+        #[cfg(test)]
+        self.scans.push(var_name);
         self.scanners.iter_mut().find_map(|s| s.scan_to(var_name))
     }
 
@@ -89,7 +160,14 @@ struct Scanner {
 }
 
 impl Scanner {
-    fn new(items: Vec<(Ustr, f32)>) -> Scanner {
+    fn new(facts: &impl Facts, encoder: &mut Encoder) -> Scanner {
+        let mut items: Vec<_> = facts
+            .iter()
+            .map(|(name, value)| (name, encoder.encode(name, value)))
+            .collect();
+        // Scanning requires the items to be sorted by variable. Sorting is
+        // stable, and props are already sorted, so this is cheap for them.
+        items.sort_by_key(|(name, _)| *name);
         Scanner { items, cursor: 0 }
     }
 
@@ -116,40 +194,710 @@ impl Scanner {
     }
 }
 
+/// Looks up several properties from a [`Props`] in a single pass.
+///
+/// This is the same lookup the engine uses when matching rules. Keys must be
+/// requested in ascending order; each lookup resumes from where the last one
+/// stopped, so scanning many keys costs no more than iterating the props
+/// once. Requesting a key smaller than a previous one will miss, even if it
+/// is present.
+pub struct FactScanner<'a> {
+    items: std::iter::Peekable<std::collections::btree_map::Iter<'a, Ustr, Value>>,
+}
+
+impl<'a> FactScanner<'a> {
+    pub fn new(props: &'a Props) -> FactScanner<'a> {
+        FactScanner {
+            items: props.iter().peekable(),
+        }
+    }
+
+    /// Returns the value of a property, or `None` if it is not set.
+    pub fn scan_to(&mut self, key: impl Into<Ustr>) -> Option<Value> {
+        let key = key.into();
+        while let Some((name, value)) = self.items.peek() {
+            if **name == key {
+                return Some((*value).clone());
+            } else if **name > key {
+                return None;
+            }
+            self.items.next();
+        }
+        None
+    }
+}
+
+/// Matches requests against compiled rules, and selects responses.
+///
+/// The engine is `Send` and `Sync`, so it can be stored in a bevy resource or
+/// shared between threads behind a lock like `Arc<Mutex<ResponseEngine>>`.
+/// Finding a response updates the state of response groups and rules, so it
+/// needs exclusive access.
 pub struct ResponseEngine {
     pub(crate) criteria: Vec<EngineCriterion>,
     pub(crate) rules: RulePartitions, // rules grouped into partitions, then sorted by importance
     pub(crate) response_groups: Vec<EngineResponseGroup>,
     // Converts interned strings to floating point values
     pub(crate) encoder: Encoder,
+    // The response field responses are weighted by
+    pub(crate) weight_field: Ustr,
+    /// The most matched partition variables a query may have before the
+    /// engine stops checking every combination of them. Checking all
+    /// partitions that might contain a matching rule takes `2^n` lookups for
+    /// `n` matched partition variables; past this limit, only the partition
+    /// matching all of them and the unpartitioned rules are checked.
+    ///
+    /// This is a safety valve, not an optimization: when it kicks in, rules
+    /// that only test some of the matched partition variables can be missed.
+    /// Defaults to [`DEFAULT_MAX_PARTITION_POWERSET`].
+    pub max_partition_powerset: usize,
+    pub(crate) partition_fallback_logged: bool,
+    pub(crate) clock: f32,
 }
 
+/// The default value of [`ResponseEngine::max_partition_powerset`].
+pub const DEFAULT_MAX_PARTITION_POWERSET: usize = 16;
+
 impl ResponseEngine {
     pub fn build() -> ResponseEngineCompiler {
         ResponseEngineCompiler::new()
     }
 
+    /// Returns the variables actually used to partition rules, sorted by name.
+    /// Requested partition variables that no criterion tests for exact
+    /// equality are not included (see [`CompileWarning::UnusedPartitionVariable`]).
+    ///
+    /// [`CompileWarning::UnusedPartitionVariable`]: crate::CompileWarning::UnusedPartitionVariable
+    pub fn partition_variables(&self) -> &[Ustr] {
+        &self.rules.vars
+    }
+
+    /// Returns the number of partitions rules have been grouped into.
+    pub fn num_partitions(&self) -> usize {
+        self.rules.partitions.len()
+    }
+
+    /// Returns the names of the rules in each partition, in the order they
+    /// are checked when matching. The partitions themselves are listed in an
+    /// arbitrary, but fixed, order.
+    pub fn partition_rules(&self) -> Vec<Vec<Ustr>> {
+        self.rules
+            .sorted()
+            .into_iter()
+            .map(|(_, rules)| rules.iter().map(|rule| rule.name).collect())
+            .collect()
+    }
+
+    /// Finds the best matching rule, applies its instructions, and returns a
+    /// response from one of its response groups.
+    ///
+    /// Returns `None` if no rule matches, but also if the matching rule has
+    /// no responses left to give, or is a silent rule with no response groups
+    /// at all. Its instructions are applied either way; use
+    /// [`ResponseEngine::find_best_match`] to tell these cases apart, or to
+    /// read the request-local variables the rule set.
     pub fn find_best_response<'q>(
         &mut self,
-        request_props: &'q Props,
-        mut charicter_props: &'q mut Props,
-        mut world_props: &'q mut Props,
+        request_props: &'q impl Facts,
+        charicter_props: &'q mut Props,
+        world_props: &'q mut Props,
         rng: &mut ThreadRng,
     ) -> Option<&UstrMap<String>> {
-        let query = Query::build(
-            [request_props, charicter_props, world_props],
-            &mut self.encoder,
+        let query = self.build_query(request_props, charicter_props, world_props);
+        self.find_response(
+            query,
+            request_props,
+            Some((charicter_props, world_props)),
+            None,
+            rng,
+            false,
+        )
+        .response
+    }
+
+    /// Like [`ResponseEngine::find_best_response`], but also reports which
+    /// rule fired and which response group the response came from. A rule
+    /// may fire without producing a response, if its response groups have run
+    /// out of responses.
+    pub fn find_best_match<'q>(
+        &mut self,
+        request_props: &'q impl Facts,
+        charicter_props: &'q mut Props,
+        world_props: &'q mut Props,
+        rng: &mut ThreadRng,
+    ) -> ResponseMatch<'_> {
+        let query = self.build_query(request_props, charicter_props, world_props);
+        self.find_response(
+            query,
+            request_props,
+            Some((charicter_props, world_props)),
+            None,
+            rng,
+            false,
+        )
+    }
+
+    /// Like [`ResponseEngine::find_best_match`], but selects the response
+    /// using the given delivery instead of the one the response group was
+    /// written with. Passing `None` behaves exactly like `find_best_match`.
+    ///
+    /// A forced delivery keeps its own place in each response group, separate
+    /// from the group's normal progress. Forcing `list` a few times and then
+    /// going back to normal requests leaves a `shuffle` group exactly where it
+    /// was, and forcing `list` again later picks up where the last forced
+    /// request left off. Forced selections never disable `once`-style rules.
+    pub fn find_best_match_with_delivery<'q>(
+        &mut self,
+        request_props: &'q impl Facts,
+        charicter_props: &'q mut Props,
+        world_props: &'q mut Props,
+        delivery: Option<Delivery>,
+        rng: &mut ThreadRng,
+    ) -> ResponseMatch<'_> {
+        let query = self.build_query(request_props, charicter_props, world_props);
+        self.find_response(
+            query,
+            request_props,
+            Some((charicter_props, world_props)),
+            delivery,
+            rng,
+            false,
+        )
+    }
+
+    /// Finds the rule that best matches a request, without firing it. The
+    /// rule can be fired later with [`ResponseEngine::fire_rule`], which
+    /// lets callers reuse one match for several identical requests.
+    ///
+    /// When several rules match equally well one of them is picked at random,
+    /// so reusing the handle also reuses that choice.
+    pub fn find_best_rule(
+        &mut self,
+        request_props: &impl Facts,
+        charicter_props: &Props,
+        world_props: &Props,
+        rng: &mut ThreadRng,
+    ) -> Option<RuleHandle> {
+        let mut query = self.build_query(request_props, charicter_props, world_props);
+        self.find_best_matching_rule(&mut query, rng)
+            .map(|(key, index)| RuleHandle { key, index })
+    }
+
+    /// Fires a rule found by [`ResponseEngine::find_best_rule`], as if it had
+    /// just been matched: its instructions are applied to the props, and a
+    /// response is selected from its response groups (advancing them, like
+    /// any other request). The rule's criteria are not checked again, but the
+    /// request is needed for the starting values of request-local variables.
+    ///
+    /// Returns `None` without doing anything if the rule has been disabled
+    /// since it was found, or if the handle doesn't belong to this engine.
+    pub fn fire_rule(
+        &mut self,
+        rule: RuleHandle,
+        request_props: &impl Facts,
+        charicter_props: &mut Props,
+        world_props: &mut Props,
+        delivery: Option<Delivery>,
+        rng: &mut ThreadRng,
+    ) -> Option<ResponseMatch<'_>> {
+        if !self.is_rule_enabled(rule) {
+            return None;
+        }
+        let found = Some((rule.key, rule.index));
+        Some(self.fire(
+            found,
+            request_props,
+            Some((charicter_props, world_props)),
+            delivery,
+            rng,
+        ))
+    }
+
+    /// Returns true if a rule found by [`ResponseEngine::find_best_rule`] can
+    /// still fire. Rules are disabled by `once`-style response groups, and by
+    /// [`ResponseEngine::set_rule_enabled`].
+    pub fn is_rule_enabled(&self, rule: RuleHandle) -> bool {
+        self.rules
+            .get_partition(&rule.key)
+            .get(rule.index)
+            .is_some_and(|rule| rule.enabled)
+    }
+
+    /// Like [`ResponseEngine::find_best_match`], but also reports which
+    /// criteria of the winning rule were checked, and against what values.
+    pub fn find_best_response_explained<'q>(
+        &mut self,
+        request_props: &'q impl Facts,
+        charicter_props: &'q mut Props,
+        world_props: &'q mut Props,
+        rng: &mut ThreadRng,
+    ) -> ResponseMatch<'_> {
+        let query = self.build_query(request_props, charicter_props, world_props);
+        self.find_response(
+            query,
+            request_props,
+            Some((charicter_props, world_props)),
+            None,
+            rng,
+            true,
+        )
+    }
+
+    /// Finds the best response for a single, arbitrary source of facts. This
+    /// doesn't require using [`Props`] at all.
+    ///
+    /// Since there are no character or world props to write to, the
+    /// instructions of the matched rule are not applied. Everything else
+    /// (response selection, and disabling `once` rules) works as normal.
+    pub fn find_best_response_for(
+        &mut self,
+        facts: &impl Facts,
+        rng: &mut ThreadRng,
+    ) -> Option<&UstrMap<String>> {
+        let scanner = Scanner::new(facts, &mut self.encoder);
+        let query = Query::build([scanner], self.criteria.len());
+        self.find_response(query, facts, None, None, rng, false)
+            .response
+    }
+
+    /// Prepares the engine to answer queries like the given samples, so that
+    /// the first real query isn't slower than the rest. This encodes the
+    /// strings that appear in the samples and looks up the partitions they
+    /// would be matched against, without matching any rules or modifying any
+    /// state that affects the results.
+    ///
+    /// Warming up is entirely optional; a cold engine produces exactly the
+    /// same responses. It's most useful on level load, with props similar to
+    /// those that will be queried shortly after.
+    pub fn warmup(&mut self, sample_props: &[&Props]) {
+        for props in sample_props {
+            let scanner = Scanner::new(*props, &mut self.encoder);
+            let mut query = Query::build([scanner], self.criteria.len());
+            let (keys, _) = self
+                .rules
+                .get_partition_keys_for_query(&mut query, self.max_partition_powerset);
+            for key in keys {
+                std::hint::black_box(self.rules.get_partition(&key));
+            }
+        }
+    }
+
+    /// Returns the instructions the named rule would apply if it fired, as
+    /// `(variable, target, operation)` in the order they would be applied.
+    /// Returns an empty list if there is no rule with that name.
+    pub fn preview_instructions(
+        &self,
+        rule: impl Into<Ustr>,
+    ) -> Vec<(Ustr, InstructionTarget, Operation)> {
+        let name = rule.into();
+        self.rules
+            .sorted()
+            .into_iter()
+            .flat_map(|(_, rules)| rules)
+            .find(|rule| rule.name == name)
+            .map(|rule| rule.instructions.clone())
+            .unwrap_or_default()
+    }
+
+    /// Adds the criteria, rules and response groups of another engine to this
+    /// one, as if they had been compiled together. This allows content that
+    /// was compiled separately, like dialog for each DLC, to be combined at
+    /// runtime.
+    ///
+    /// The engines must not both define a rule or response group with the
+    /// same name, declare the same ordinal variable with different values, or
+    /// use different weight fields. An ordinal declared only by the other
+    /// engine must also not be compared against strings by this engine's
+    /// criteria, since they were compiled as plain strings. If there are any
+    /// such conflicts, they are all returned and this engine is left
+    /// unchanged. See [`MergeError`].
+    ///
+    /// The state of this engine, like its clock and which rules are disabled,
+    /// is kept, and the state of the other engine's rules and response groups
+    /// is carried over.
+    pub fn merge(&mut self, other: ResponseEngine) -> Result<(), Vec<MergeError>> {
+        let mut errors = Vec::new();
+        let rule_names: UstrSet = self
+            .rules
+            .partitions
+            .values()
+            .flatten()
+            .map(|rule| rule.name)
+            .collect();
+        let mut duplicate_rules: Vec<_> = other
+            .rules
+            .partitions
+            .values()
+            .flatten()
+            .map(|rule| rule.name)
+            .filter(|name| rule_names.contains(name))
+            .collect();
+        duplicate_rules.sort();
+        errors.extend(
+            duplicate_rules
+                .into_iter()
+                .map(|rule_name| MergeError::DuplicateRule { rule_name }),
         );
+        let mut conflicting_ordinals: Vec<_> = other
+            .encoder
+            .ordinals
+            .iter()
+            .filter(|(variable, values)| {
+                self.encoder
+                    .ordinals
+                    .get(*variable)
+                    .is_some_and(|existing| existing != *values)
+            })
+            .map(|(variable, _)| *variable)
+            .collect();
+        conflicting_ordinals.sort();
+        errors.extend(
+            conflicting_ordinals
+                .into_iter()
+                .map(|variable_name| MergeError::ConflictingOrdinal { variable_name }),
+        );
+        let mut undeclared_ordinals: Vec<_> = self
+            .criteria
+            .iter()
+            .filter(|criterion| {
+                criterion.string.is_some()
+                    && !self.encoder.ordinals.contains_key(&criterion.variable)
+                    && other.encoder.ordinals.contains_key(&criterion.variable)
+            })
+            .map(|criterion| criterion.variable)
+            .collect();
+        undeclared_ordinals.sort();
+        undeclared_ordinals.dedup();
+        errors.extend(
+            undeclared_ordinals
+                .into_iter()
+                .map(|variable_name| MergeError::UndeclaredOrdinal { variable_name }),
+        );
+        let group_names: UstrSet = self
+            .response_groups
+            .iter()
+            .map(|group| group.name)
+            .collect();
+        let mut duplicate_groups: Vec<_> = other
+            .response_groups
+            .iter()
+            .map(|group| group.name)
+            .filter(|name| group_names.contains(name))
+            .collect();
+        duplicate_groups.sort();
+        errors.extend(
+            duplicate_groups
+                .into_iter()
+                .map(|group_name| MergeError::DuplicateResponseGroup { group_name }),
+        );
+        if self.weight_field != other.weight_field {
+            errors.push(MergeError::ConflictingWeightField {
+                this: self.weight_field,
+                other: other.weight_field,
+            });
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        for (variable, values) in other.encoder.ordinals {
+            self.encoder.ordinals.entry(variable).or_insert(values);
+        }
+
+        // Strings are encoded differently by each engine, so re-encode the
+        // other engine's string equalities with this engine's encoder
+        let criteria_offset = self.criteria.len();
+        for mut criterion in other.criteria {
+            if let Some(string) = criterion.string {
+                let encoding = self.encoder.encode_str(criterion.variable, string);
+                criterion.min = encoding;
+                criterion.max = encoding;
+            }
+            self.criteria.push(criterion);
+        }
+
+        let response_groups_offset = self.response_groups.len();
+        self.response_groups.extend(other.response_groups);
+
+        // Partition keys only depend on the rule's own partition criteria,
+        // so existing rules can stay where they are
+        for &var in &other.rules.vars {
+            if let Err(i) = self.rules.vars.binary_search(&var) {
+                self.rules.vars.insert(i, var);
+            }
+        }
+        let mut changed_partitions = HashSet::new();
+        // Rules are appended to partitions in a fixed order, so ties between
+        // rules from different partitions of the other engine are always
+        // broken the same way
+        let other_rules = other.rules.into_sorted();
+        for mut rule in other_rules.into_iter().flat_map(|(_, rules)| rules) {
+            for i in rule
+                .criteria
+                .iter_mut()
+                .chain(rule.any_criteria.iter_mut().flatten())
+                .chain(rule.partition_criteria.iter_mut())
+            {
+                *i += criteria_offset;
+            }
+            for (i, _) in &mut rule.response_groups {
+                *i += response_groups_offset;
+            }
+            let mut assignments: Vec<_> = rule
+                .partition_criteria
+                .iter()
+                .map(|i| (self.criteria[*i].variable, self.criteria[*i].min))
+                .collect();
+            assignments.sort_by_key(|(var, _)| *var);
+            let key = self.rules.get_partition_key_for_assignments(&assignments);
+            self.rules.partitions.entry(key).or_default().push(rule);
+            changed_partitions.insert(key);
+        }
+
+        // Restore the ordering of partitions by priority, then by score
+        for key in changed_partitions {
+            if let Some(partition) = self.rules.partitions.get_mut(&key) {
+                partition.sort_by(|ra, rb| {
+                    rb.priority
+                        .total_cmp(&ra.priority)
+                        .then(rb.score.total_cmp(&ra.score))
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the current time, as seen by instructions like `last_seen := now`.
+    ///
+    /// The engine has no clock of its own, so this must be called before
+    /// querying whenever time has passed (usually once per frame). The time
+    /// is kept on the engine rather than passed to each query so that every
+    /// way of finding or firing a rule sees the same time, without all of
+    /// them taking it as an argument. It can be measured in any unit, but
+    /// should never decrease, so that timestamps written into props can be
+    /// compared with later ones. It starts at zero.
+    pub fn set_clock(&mut self, now: f32) {
+        self.clock = now;
+    }
+
+    /// Returns the current time, as last set by [`ResponseEngine::set_clock`].
+    pub fn clock(&self) -> f32 {
+        self.clock
+    }
+
+    /// Enables or disables the named rule. Disabled rules are skipped when
+    /// matching, as if their criteria never match. Rules are also disabled
+    /// automatically when a `deplete` or `list` response group runs out of
+    /// responses; re-enabling such a rule lets it match again, but the
+    /// exhausted group will not provide any more responses.
+    ///
+    /// Returns false if there is no rule with that name.
+    pub fn set_rule_enabled(&mut self, rule: impl Into<Ustr>, enabled: bool) -> bool {
+        let name = rule.into();
+        match self
+            .rules
+            .partitions
+            .values_mut()
+            .flatten()
+            .find(|rule| rule.name == name)
+        {
+            Some(rule) => {
+                rule.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets a single field of a response, such as its `line`, without
+    /// recompiling anything. This is meant for iterating on response text
+    /// while the game is running. Responses are indexed in the order they
+    /// were defined, after any included groups are flattened.
+    ///
+    /// Returns false if there is no response group with that name, or it has
+    /// no response at that index.
+    pub fn set_response_field(
+        &mut self,
+        group: impl Into<Ustr>,
+        index: usize,
+        field: impl Into<Ustr>,
+        value: String,
+    ) -> bool {
+        let name = group.into();
+        let response = self
+            .response_groups
+            .iter_mut()
+            .find(|group| group.name == name)
+            .and_then(|group| group.responses.get_mut(index));
+        match response {
+            Some(response) => {
+                response.insert(field.into(), value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces all the responses of the named response group, keeping its
+    /// delivery. Like when compiling, `weight` and `chance` fields set the
+    /// weight and chance of a response, but here missing or invalid values
+    /// are treated as 1. The weight is read from the field set with
+    /// [`ResponseEngineCompiler::with_weight_field`], if the engine was
+    /// compiled with one.
+    ///
+    /// If the number of responses is unchanged, the group keeps its place
+    /// (for example, which responses a `deplete` group has used up).
+    /// Otherwise it starts over as if freshly compiled.
+    ///
+    /// Returns false if there is no response group with that name.
+    pub fn replace_response_group(
+        &mut self,
+        group: impl Into<Ustr>,
+        responses: Vec<UstrMap<String>>,
+    ) -> bool {
+        let name = group.into();
+        let Some(group) = self
+            .response_groups
+            .iter_mut()
+            .find(|group| group.name == name)
+        else {
+            return false;
+        };
+
+        let chance_ustr = Ustr::from("chance");
+        let mut chances = Vec::with_capacity(responses.len());
+        let (weights, responses): (Vec<_>, Vec<_>) = responses
+            .into_iter()
+            .map(|mut properties| {
+                let chance = properties
+                    .remove(&chance_ustr)
+                    .and_then(|string| string.parse::<f32>().ok())
+                    .filter(|chance| (0.0..=1.0).contains(chance))
+                    .unwrap_or(1.0);
+                chances.push(chance);
+                let weight = properties
+                    .remove(&self.weight_field)
+                    .and_then(|string| string.parse::<f32>().ok())
+                    .filter(|weight| weight.is_finite() && *weight >= 0.0)
+                    .unwrap_or(1.0);
+                (weight, properties)
+            })
+            .unzip();
+        // If nothing has any weight, treat everything as equally likely
+        let weights = if weights.iter().all(|weight| *weight == 0.0) {
+            vec![1.0; weights.len()]
+        } else {
+            weights
+        };
+        for (_, dispatcher) in &mut group.forced_dispatchers {
+            dispatcher.set_weights(weights.clone());
+        }
+        group.dispatcher.set_weights(weights.clone());
+        group.weights = weights;
+        group.chances = chances;
+        group.responses = responses;
+        true
+    }
+
+    /// Finds the rule that would fire for a request, without firing it. The
+    /// props are not modified and no response is selected, so response groups
+    /// don't advance and depleted rules are not disabled.
+    ///
+    /// Ties between equally ranked rules are still broken randomly, so the
+    /// preview may not match a subsequent call to
+    /// [`ResponseEngine::find_best_response`].
+    pub fn preview_best_rule(
+        &mut self,
+        request_props: &impl Facts,
+        charicter_props: &Props,
+        world_props: &Props,
+        rng: &mut ThreadRng,
+    ) -> Option<RulePreview> {
+        let mut query = self.build_query(request_props, charicter_props, world_props);
+        let (key, index) = self.find_best_matching_rule(&mut query, rng)?;
+        let rule = self.rules.get_rule(&key, index);
+        Some(RulePreview {
+            rule: rule.name,
+            instructions: rule.instructions.clone(),
+        })
+    }
+
+    fn build_query(
+        &mut self,
+        request_props: &impl Facts,
+        charicter_props: &Props,
+        world_props: &Props,
+    ) -> Query {
+        let scanners = [
+            Scanner::new(request_props, &mut self.encoder),
+            Scanner::new(charicter_props, &mut self.encoder),
+            Scanner::new(world_props, &mut self.encoder),
+        ];
+        Query::build(scanners, self.criteria.len())
+    }
+
+    fn find_response(
+        &mut self,
+        mut query: Query,
+        request: &impl Facts,
+        targets: Option<(&mut Props, &mut Props)>,
+        delivery: Option<Delivery>,
+        rng: &mut ThreadRng,
+        explain: bool,
+    ) -> ResponseMatch<'_> {
+        if explain {
+            query.accessed = Some(UstrSet::default());
+        }
+        let found = self.find_best_matching_rule(&mut query, rng);
+        let criteria = match found {
+            Some((key, index)) if explain => {
+                self.explain_rule_criteria(&mut query, self.rules.get_rule(&key, index))
+            }
+            _ => Vec::new(),
+        };
+        let accessed_variables = query
+            .accessed
+            .map(|accessed| accessed.into_iter().sorted().collect())
+            .unwrap_or_default();
 
+        let mut response_match = self.fire(found, request, targets, delivery, rng);
+        response_match.criteria = criteria;
+        response_match.accessed_variables = accessed_variables;
+        response_match
+    }
+
+    // Runs the instructions of a matched rule, and selects a response from its
+    // response groups. Request-local variables start from their values in the
+    // request, if it has them.
+    fn fire(
+        &mut self,
+        found: Option<(PartitionKey, usize)>,
+        request: &impl Facts,
+        mut targets: Option<(&mut Props, &mut Props)>,
+        delivery: Option<Delivery>,
+        rng: &mut ThreadRng,
+    ) -> ResponseMatch<'_> {
+        let mut rule_name = None;
         let mut response = None;
-        if let Some((key, index)) = self.find_best_matching_rule(query, rng) {
+        let mut request_props = Props::new();
+        if let Some((key, index)) = found {
             let rule = self.rules.get_rule_mut(&key, index);
+            rule_name = Some(rule.name);
 
-            for (var, (global, op)) in &rule.instructions {
-                let props = if *global {
-                    &mut world_props
-                } else {
-                    &mut charicter_props
+            for (var, target, op) in &rule.instructions {
+                let props: &mut Props = match (target, &mut targets) {
+                    (InstructionTarget::Request, _) => {
+                        if !request_props.contains_key(*var)
+                            && let Some((_, value)) = request.iter().find(|(name, _)| name == var)
+                        {
+                            request_props.set(*var, value);
+                        }
+                        &mut request_props
+                    }
+                    (_, None) => continue,
+                    (InstructionTarget::Character, Some((charicter_props, _))) => charicter_props,
+                    (InstructionTarget::World, Some((_, world_props))) => world_props,
                 };
                 let value = props.get(*var);
                 match (value, *op) {
@@ -159,56 +907,95 @@ impl ResponseEngine {
                     (_, Operation::BoolToggle) => props.set(*var, true),
                     (_, Operation::NumSet(num)) => props.set(*var, num),
                     (_, Operation::NumAdd(num)) => props.set(*var, num),
-                    (_, Operation::StrSet(ustr)) => props.set(*var, self.encoder.encode_ustr(ustr)),
+                    (value, Operation::NumAddClamped { delta, min, max }) => {
+                        let value = match value {
+                            Value::Num(value) => value + delta,
+                            _ => delta,
+                        };
+                        props.set(*var, value.clamp(min, max))
+                    }
+                    (_, Operation::StrSet(ustr)) => props.set(*var, ustr),
+                    (_, Operation::NumSetNow) => props.set(*var, self.clock),
                 }
             }
 
             // Query for a response from each response group, in a random order
-            let mut group_indicies = rule.response_groups.clone();
-            group_indicies.shuffle(rng);
-            for group_index in group_indicies {
+            // weighted by the rule. Once only groups without any weight are
+            // left, they are tried in a uniformly random order.
+            let mut candidates = rule.response_groups.clone();
+            candidates.shuffle(rng);
+            while !candidates.is_empty() {
+                let i = choose_position(candidates.len(), |i| candidates[i].1, rng).unwrap_or(0);
+                let (group_index, _) = candidates.swap_remove(i);
                 let group = &mut self.response_groups[group_index];
-                if let Some(response_index) = group.dispatcher.next(rng) {
+                let Some(response_index) = group.select(delivery, rng) else {
+                    continue;
+                };
+                // Running out disables the rule even if the last response
+                // fails its chance roll
+                if !group.is_forced(delivery) && group.dispatcher.disable_rule() {
+                    rule.enabled = false;
+                }
+                if group.roll_chance(response_index, rng) {
                     response = Some((group_index, response_index));
-
-                    if group.dispatcher.disable_rule() {
-                        rule.enabled = false;
-                    }
-
                     break;
                 }
             }
         }
-        response.map(|(g, i)| &self.response_groups[g].responses[i])
+        ResponseMatch {
+            rule: rule_name,
+            response_group: response.map(|(g, _)| self.response_groups[g].name),
+            response: response.map(|(g, i)| &self.response_groups[g].responses[i]),
+            criteria: Vec::new(),
+            accessed_variables: Vec::new(),
+            request_props,
+        }
     }
 
     fn find_best_matching_rule(
         &mut self,
-        mut query: Query,
+        query: &mut Query,
         rng: &mut ThreadRng,
     ) -> Option<(PartitionKey, usize)> {
-        let mut best_score = 0.0;
+        let mut best_rank = None;
         let mut best_rules = Vec::new();
 
-        for key in self.rules.get_partition_keys_for_query(&mut query) {
+        let (keys, fell_back) = self
+            .rules
+            .get_partition_keys_for_query(query, self.max_partition_powerset);
+        if fell_back && !self.partition_fallback_logged {
+            self.partition_fallback_logged = true;
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                "query matched more than {} partition variables; only checking exact and unpartitioned rules",
+                self.max_partition_powerset
+            );
+        }
+
+        for key in keys {
             let partition = self.rules.get_partition(&key);
             for (i, rule) in partition.iter().enumerate() {
-                // First, check the score. Rules are stored by decreasing score,
-                // so once we encounter a rule that's worse than the best thing
-                // we've found so far, we can stop.
-                if rule.score < best_score {
+                if !rule.enabled {
+                    continue;
+                }
+                // First, check the rank. Rules are stored by decreasing
+                // priority and then by decreasing score, so once we encounter a
+                // rule that's worse than the best thing we've found so far, we
+                // can stop.
+                let rank = (rule.priority, rule.score);
+                if best_rank.is_some_and(|best_rank| rank < best_rank) {
                     break;
                 }
-                // If it scores better or equal to our current best, check to
+                // If it ranks better or equal to our current best, check to
                 // see if the criteria match.
-                if self.match_rule_criteria(&mut query, rule) {
-                    if rule.score > best_score {
-                        // If the criteria are a match and it scores better, throw out what we have.
-                        best_score = rule.score;
+                if self.match_rule_criteria(query, rule) {
+                    if best_rank.is_none_or(|best_rank| rank > best_rank) {
+                        // If the criteria are a match and it ranks better, throw out what we have.
+                        best_rank = Some(rank);
                         best_rules.clear();
                         best_rules.push((key, i));
                     } else {
-                        // Otherwise the score must be equal, and we include it in the list.
+                        // Otherwise the rank must be equal, and we include it in the list.
                         best_rules.push((key, i));
                     }
                 }
@@ -222,25 +1009,200 @@ impl ResponseEngine {
     fn match_rule_criteria(&self, query: &mut Query, rule: &EngineRule) -> bool {
         query.reset();
         for criterion_index in &rule.criteria {
-            let criterion = &self.criteria[*criterion_index];
-            if let Some(value) = query.scan_to(criterion.variable) {
-                if criterion.min <= value && value <= criterion.max {
-                } else {
-                    return false;
-                }
-            } else {
+            if !self.match_criterion(query, *criterion_index) {
+                return false;
+            }
+        }
+        for group in &rule.any_criteria {
+            query.reset();
+            if !group.iter().any(|i| self.match_criterion(query, *i)) {
                 return false;
             }
         }
         true
     }
+
+    // Checks a single criterion. Repeated calls (between resets of the query)
+    // should check criteria of increasing variable order.
+    fn match_criterion(&self, query: &mut Query, criterion_index: usize) -> bool {
+        match query.criteria_results[criterion_index] {
+            Some(matched) => matched,
+            None => {
+                let criterion = &self.criteria[criterion_index];
+                let matched = query
+                    .scan_to(criterion.variable)
+                    .is_some_and(|value| criterion.min <= value && value <= criterion.max);
+                query.criteria_results[criterion_index] = Some(matched);
+                matched
+            }
+        }
+    }
+
+    // Collects every criterion of a rule (including the ones used to partition
+    // it, and the members of `any` groups that matched) along with the values
+    // they were checked against.
+    fn explain_rule_criteria(&self, query: &mut Query, rule: &EngineRule) -> Vec<CriterionMatch> {
+        let mut criteria_indices: Vec<_> = rule
+            .criteria
+            .iter()
+            .chain(&rule.partition_criteria)
+            .copied()
+            .map(|i| (i, false))
+            .chain(rule.any_criteria.iter().flatten().map(|i| (*i, true)))
+            .collect();
+        criteria_indices.sort_by_key(|(i, _)| self.criteria[*i].variable);
+
+        query.reset();
+        criteria_indices
+            .into_iter()
+            .filter_map(|(i, optional)| {
+                let criterion = &self.criteria[i];
+                let value = query.scan_to(criterion.variable)?;
+                let matched = criterion.min <= value && value <= criterion.max;
+                if optional && !matched {
+                    return None;
+                }
+                Some(CriterionMatch {
+                    variable: criterion.variable,
+                    value,
+                    min: criterion.min,
+                    max: criterion.max,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Replaces each `${variable}` in a response field with the value of that
+/// variable, formatted with `Display`. The scopes are searched in order, so
+/// earlier scopes shadow later ones; variables that aren't set in any scope
+/// are replaced with nothing. `$$` stands for a single `$`, and any other `$`
+/// (including one starting an unterminated `${`) is left as it is.
+///
+/// ```rust
+/// # use bevy_mod_props::Props;
+/// # use trill_core::engine::fill_template;
+/// let character = Props::new().with("name", "clippy").with("gold", 5.0);
+/// let world = Props::new().with("name", "world");
+/// let filled = fill_template("${name} has ${gold}$$ and ${debt}$$ of debt", &[&character, &world]);
+/// assert_eq!(filled, "clippy has 5$ and $ of debt");
+/// ```
+pub fn fill_template<'a>(template: &'a str, scopes: &[&Props]) -> Cow<'a, str> {
+    if !template.contains('$') {
+        return Cow::Borrowed(template);
+    }
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find('$') {
+        filled.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            filled.push('$');
+            rest = after;
+        } else if let Some(end) = rest.strip_prefix('{').and_then(|inner| inner.find('}')) {
+            let name = &rest[1..end + 1];
+            if let Some(value) = scopes.iter().find_map(|props| props.get_value(name)) {
+                // Writing to a string can't fail
+                let _ = write!(filled, "{value}");
+            }
+            rest = &rest[end + 2..];
+        } else {
+            filled.push('$');
+        }
+    }
+    filled.push_str(rest);
+    Cow::Owned(filled)
+}
+
+/// The result of [`ResponseEngine::find_best_match`] and
+/// [`ResponseEngine::find_best_response_explained`].
+#[derive(Debug)]
+pub struct ResponseMatch<'a> {
+    /// The name of the rule that fired, if any rule matched.
+    pub rule: Option<Ustr>,
+    /// The name of the response group the response was selected from. This
+    /// is `None` if no rule matched, or if the rule's response groups had no
+    /// responses left to give.
+    pub response_group: Option<Ustr>,
+    /// The selected response, if any.
+    pub response: Option<&'a UstrMap<String>>,
+    /// The criteria of the winning rule, sorted by variable name. For groups of
+    /// criteria where any member may match, only the members that matched are
+    /// included. Empty when no rule matched, and always empty for
+    /// [`ResponseEngine::find_best_match`].
+    pub criteria: Vec<CriterionMatch>,
+    /// Every variable the engine looked up while matching, sorted by name.
+    /// This includes variables that weren't set on any of the queried props,
+    /// which makes it useful for spotting typos in fact names. Always empty
+    /// for [`ResponseEngine::find_best_match`].
+    pub accessed_variables: Vec<Ustr>,
+    /// The request-local variables set by the rule's instructions (written
+    /// `?var` in scripts). These start out with their values in the request,
+    /// so `?times :+ 1` counts on from the request's `times`, but only the
+    /// variables the rule sets are included here, and they aren't written to
+    /// any other props.
+    pub request_props: Props,
+}
+
+/// A rule matched by [`ResponseEngine::find_best_rule`].
+///
+/// Handles are only meaningful for the engine that produced them, and only
+/// until its rules are changed (for example by merging in another engine).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RuleHandle {
+    key: PartitionKey,
+    index: usize,
+}
+
+/// A conflict that prevents two engines from being merged. See
+/// [`ResponseEngine::merge`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeError {
+    /// Both engines define a rule with this name.
+    DuplicateRule { rule_name: Ustr },
+    /// Both engines declare this ordinal variable, with different values.
+    ConflictingOrdinal { variable_name: Ustr },
+    /// Only the other engine declares this ordinal variable, but this engine
+    /// has criteria comparing it against strings, which were compiled as
+    /// plain strings rather than positions.
+    UndeclaredOrdinal { variable_name: Ustr },
+    /// Both engines define a response group with this name.
+    DuplicateResponseGroup { group_name: Ustr },
+    /// The engines read response weights from differently named fields.
+    ConflictingWeightField { this: Ustr, other: Ustr },
+}
+
+/// The result of [`ResponseEngine::preview_best_rule`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RulePreview {
+    /// The name of the rule that would fire.
+    pub rule: Ustr,
+    /// The instructions the rule would apply. See
+    /// [`ResponseEngine::preview_instructions`].
+    pub instructions: Vec<(Ustr, InstructionTarget, Operation)>,
+}
+
+/// A single criterion checked while matching a rule.
+///
+/// All values are in the engine's encoded form: booleans are `0.0` or `1.0`,
+/// numbers are unchanged, and strings are encoded as opaque floats.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CriterionMatch {
+    pub variable: Ustr,
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
 }
 
 #[derive(Debug)]
 pub(crate) struct EngineRule {
+    pub name: Ustr,
     pub criteria: Vec<usize>, // Sorted by variable name (increasing)
-    pub response_groups: Vec<usize>,
-    pub instructions: UstrMap<(bool, Operation)>,
+    pub any_criteria: Vec<Vec<usize>>, // Each group sorted by variable name (increasing)
+    pub partition_criteria: Vec<usize>, // Criteria encoded in the rule's partition key
+    pub response_groups: Vec<(usize, f32)>, // Indices and weights
+    pub instructions: Vec<(Ustr, InstructionTarget, Operation)>, // Sorted by variable name (increasing)
+    pub priority: f32,
     pub score: f32,
     pub enabled: bool,
 }
@@ -250,22 +1212,78 @@ pub(crate) struct EngineCriterion {
     pub variable: Ustr,
     pub min: f32,
     pub max: f32,
+    // The string compared against, for string equalities. String encodings
+    // are specific to an engine, so this is needed to re-encode the criterion
+    // when merging engines.
+    pub string: Option<Ustr>,
 }
 
 #[derive(Debug)]
 pub(crate) struct EngineResponseGroup {
+    pub name: Ustr,
+    pub delivery: Delivery,
     pub dispatcher: ResponseDispatcher,
+    // Dispatchers for deliveries forced by individual requests, created when
+    // first needed. They are kept apart from `dispatcher` so that forcing a
+    // delivery doesn't disturb the group's own progress.
+    pub forced_dispatchers: Vec<(Delivery, ResponseDispatcher)>,
+    pub weights: Vec<f32>,
+    // The chance of each response actually being given when it is selected,
+    // from zero to one
+    pub chances: Vec<f32>,
     pub responses: Vec<UstrMap<String>>,
 }
 
+impl EngineResponseGroup {
+    // Returns true if selecting with this delivery doesn't use the group's
+    // own dispatcher
+    fn is_forced(&self, delivery: Option<Delivery>) -> bool {
+        delivery.is_some_and(|delivery| delivery != self.delivery)
+    }
+
+    // Rolls whether a selected response is actually given. A response that
+    // fails its roll has still been selected, so it is used up just like one
+    // that was given (for example, a `deplete` group won't offer it again).
+    fn roll_chance(&self, index: usize, rng: &mut ThreadRng) -> bool {
+        let chance = self.chances.get(index).copied().unwrap_or(1.0);
+        chance >= 1.0 || rng.random::<f32>() < chance
+    }
+
+    fn select(&mut self, delivery: Option<Delivery>, rng: &mut ThreadRng) -> Option<usize> {
+        let Some(delivery) = delivery.filter(|_| self.is_forced(delivery)) else {
+            return self.dispatcher.next(rng);
+        };
+        let index = match self
+            .forced_dispatchers
+            .iter()
+            .position(|(forced, _)| *forced == delivery)
+        {
+            Some(index) => index,
+            None => {
+                let dispatcher = ResponseDispatcher::new(delivery, self.weights.clone());
+                self.forced_dispatchers.push((delivery, dispatcher));
+                self.forced_dispatchers.len() - 1
+            }
+        };
+        self.forced_dispatchers[index].1.next(rng)
+    }
+}
+
 #[derive(Debug)]
 pub enum ResponseDispatcher {
     Shuffle {
         weights: Vec<f32>,
+        // The responses not yet used this round
         candidates: Vec<usize>,
+        // The previously selected response
+        last: Option<usize>,
     },
     Random {
         weights: Vec<f32>,
+        // The previously selected response
+        last: Option<usize>,
+        // When set, the previous response is never selected twice in a row
+        no_repeat: bool,
     },
     Deplete {
         weights: Vec<f32>,
@@ -282,48 +1300,87 @@ pub enum ResponseDispatcher {
 }
 
 impl ResponseDispatcher {
+    pub(crate) fn new(delivery: Delivery, weights: Vec<f32>) -> ResponseDispatcher {
+        let len = weights.len();
+        match delivery {
+            Delivery::Shuffle => ResponseDispatcher::Shuffle {
+                weights,
+                candidates: (0..len).collect(),
+                last: None,
+            },
+            Delivery::Random => ResponseDispatcher::Random {
+                weights,
+                last: None,
+                no_repeat: false,
+            },
+            Delivery::RandomNoRepeat => ResponseDispatcher::Random {
+                weights,
+                last: None,
+                no_repeat: true,
+            },
+            Delivery::Deplete => ResponseDispatcher::Deplete {
+                weights,
+                candidates: (0..len).collect(),
+            },
+            Delivery::Loop => ResponseDispatcher::Loop { len, index: 0 },
+            Delivery::List => ResponseDispatcher::List { len, index: 0 },
+        }
+    }
+
     fn next(&mut self, rng: &mut ThreadRng) -> Option<usize> {
         match self {
             ResponseDispatcher::Shuffle {
                 weights,
                 candidates,
+                last,
             } => {
-                if weights.len() == 1 {
-                    return Some(0);
-                }
-                let candidate_indicies: Vec<_> = (0..candidates.len()).collect();
-                let i = candidate_indicies
-                    .choose_weighted(rng, |i| weights[candidates[*i]])
-                    .ok()?;
-                let i = candidates.remove(*i);
-                if candidates.len() == 0 {
+                // Start the next round once every response with any weight
+                // has been used
+                if !candidates.iter().any(|i| weights[*i] > 0.0) {
                     *candidates = (0..weights.len()).collect();
-                    let _ = candidates.remove(i);
                 }
+                // Don't start a round with the response that ended the
+                // previous one, unless there is nothing else to give
+                let eligible: Vec<_> = candidates
+                    .iter()
+                    .copied()
+                    .filter(|i| Some(*i) != *last)
+                    .collect();
+                let i = choose_candidate(weights, &eligible, rng)
+                    .map(|i| eligible[i])
+                    .or_else(|| {
+                        choose_candidate(weights, candidates, rng).map(|i| candidates[i])
+                    })?;
+                candidates.retain(|candidate| *candidate != i);
+                *last = Some(i);
                 Some(i)
             }
-            ResponseDispatcher::Random { weights } => {
-                if weights.len() == 1 {
-                    return Some(0);
-                }
-                let candidates: Vec<_> = (0..weights.len()).collect();
-                candidates
-                    .choose_weighted(rng, |i| weights[*i])
-                    .ok()
-                    .copied()
+            ResponseDispatcher::Random {
+                weights,
+                last,
+                no_repeat,
+            } => {
+                let candidates: Vec<_> = (0..weights.len())
+                    .filter(|i| !*no_repeat || Some(*i) != *last)
+                    .collect();
+                let i = choose_candidate(weights, &candidates, rng).map(|i| candidates[i]);
+                // If the previous response was the only one with any weight,
+                // it's better to repeat it than to say nothing
+                let i = if *no_repeat { i.or(*last) } else { i };
+                *last = i;
+                i
             }
             ResponseDispatcher::Deplete {
                 weights,
                 candidates,
             } => {
-                let candidate_indicies: Vec<_> = (0..candidates.len()).collect();
-                let i = candidate_indicies
-                    .choose_weighted(rng, |i| weights[candidates[*i]])
-                    .ok()?;
-                let i = candidates.remove(*i);
-                Some(i)
+                let i = choose_candidate(weights, candidates, rng)?;
+                Some(candidates.remove(i))
             }
             ResponseDispatcher::Loop { len, index } => {
+                if *len == 0 {
+                    return None;
+                }
                 let i = *index;
                 *index = (*index + 1) % *len;
                 Some(i)
@@ -340,21 +1397,103 @@ impl ResponseDispatcher {
         }
     }
 
+    // Replaces the weights of the responses. If the number of responses has
+    // changed, progress through them is reset.
+    fn set_weights(&mut self, new_weights: Vec<f32>) {
+        let new_len = new_weights.len();
+        match self {
+            ResponseDispatcher::Shuffle {
+                weights,
+                candidates,
+                last,
+            } => {
+                if weights.len() != new_len {
+                    *candidates = (0..new_len).collect();
+                    *last = None;
+                }
+                *weights = new_weights;
+            }
+            ResponseDispatcher::Deplete {
+                weights,
+                candidates,
+            } => {
+                if weights.len() != new_len {
+                    *candidates = (0..new_len).collect();
+                }
+                *weights = new_weights;
+            }
+            ResponseDispatcher::Random { weights, last, .. } => {
+                if weights.len() != new_len {
+                    *last = None;
+                }
+                *weights = new_weights;
+            }
+            ResponseDispatcher::Loop { len, index } | ResponseDispatcher::List { len, index } => {
+                if *len != new_len {
+                    *len = new_len;
+                    *index = 0;
+                }
+            }
+        }
+    }
+
     fn disable_rule(&self) -> bool {
         match self {
             // These dispatchers will never run out of items
             ResponseDispatcher::Shuffle { .. }
             | ResponseDispatcher::Loop { .. }
             | ResponseDispatcher::Random { .. } => false,
-            // Disable deplete when the candidate list is empty
-            ResponseDispatcher::Deplete { candidates, .. } => candidates.is_empty(),
+            // Disable deplete when no candidate with any weight is left, since
+            // the rest will never be picked
+            ResponseDispatcher::Deplete {
+                weights,
+                candidates,
+            } => candidates.iter().all(|i| weights[*i] <= 0.0),
             // Diable list when we reach the end of the list
             ResponseDispatcher::List { len, index } => *len == *index,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+// Picks one of the candidate responses at random in proportion to its weight,
+// and returns its position in `candidates`. Candidates without any weight are
+// never picked, even when there is only one.
+fn choose_candidate(weights: &[f32], candidates: &[usize], rng: &mut ThreadRng) -> Option<usize> {
+    choose_position(candidates.len(), |i| weights[candidates[i]], rng)
+}
+
+// Picks a position below `len` at random in proportion to its weight, without
+// allocating. Returns `None` if nothing has any weight.
+fn choose_position(
+    len: usize,
+    weight: impl Fn(usize) -> f32,
+    rng: &mut ThreadRng,
+) -> Option<usize> {
+    let total: f32 = (0..len).map(&weight).sum();
+    if !(total > 0.0 && total.is_finite()) {
+        return None;
+    }
+    let mut target = rng.random_range(0.0..total);
+    let mut last = None;
+    for i in 0..len {
+        let weight = weight(i);
+        if weight > 0.0 {
+            if target < weight {
+                return Some(i);
+            }
+            target -= weight;
+            last = Some(i);
+        }
+    }
+    // Rounding can leave a little of the target over
+    last
+}
+
+// Identifies a partition by hashing the partition variables it tests and
+// their encoded values, with a fixed seed. Keys are therefore the same across
+// runs and processes, as long as the strings involved are encoded the same
+// way, and ordering partitions by key gives a reproducible order.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub(crate) struct PartitionKey(u64);
 
 #[derive(Debug)]
@@ -364,8 +1503,34 @@ pub(crate) struct RulePartitions {
 }
 
 impl RulePartitions {
-    // Returns the keys to all partitions that might contain relevant rules
-    fn get_partition_keys_for_query(&self, query: &mut Query) -> Vec<PartitionKey> {
+    // Returns the partitions in order of key. Anything that depends on the
+    // order of partitions should go through this rather than iterating the
+    // map directly.
+    fn sorted(&self) -> Vec<(PartitionKey, &[EngineRule])> {
+        let mut partitions: Vec<_> = self
+            .partitions
+            .iter()
+            .map(|(key, rules)| (*key, rules.as_slice()))
+            .collect();
+        partitions.sort_unstable_by_key(|(key, _)| *key);
+        partitions
+    }
+
+    // Like `sorted`, but takes ownership of the partitions
+    fn into_sorted(self) -> Vec<(PartitionKey, Vec<EngineRule>)> {
+        let mut partitions: Vec<_> = self.partitions.into_iter().collect();
+        partitions.sort_unstable_by_key(|(key, _)| *key);
+        partitions
+    }
+
+    // Returns the keys to all partitions that might contain relevant rules,
+    // and whether there were too many matched variables to check them all (in
+    // which case only the full and empty keys are returned)
+    fn get_partition_keys_for_query(
+        &self,
+        query: &mut Query,
+        max_powerset: usize,
+    ) -> (Vec<PartitionKey>, bool) {
         query.reset();
         let mut assignments = Vec::with_capacity(self.vars.len());
         for var in &self.vars {
@@ -374,11 +1539,20 @@ impl RulePartitions {
             }
         }
 
-        assignments
+        if assignments.len() > max_powerset {
+            let keys = vec![
+                self.get_partition_key_for_assignments(&assignments),
+                self.get_partition_key_for_assignments(&[]),
+            ];
+            return (keys, true);
+        }
+
+        let keys = assignments
             .into_iter()
             .powerset()
             .map(|assignments| self.get_partition_key_for_assignments(&assignments))
-            .collect()
+            .collect();
+        (keys, false)
     }
 
     // Returns the key for this set of variable assignments
@@ -399,6 +1573,10 @@ impl RulePartitions {
         self.partitions.get(key).map(Vec::as_slice).unwrap_or(&[])
     }
 
+    fn get_rule(&self, key: &PartitionKey, rule_index: usize) -> &EngineRule {
+        &self.partitions[key][rule_index]
+    }
+
     fn get_rule_mut(&mut self, key: &PartitionKey, rule_index: usize) -> &mut EngineRule {
         &mut self.partitions.get_mut(key).unwrap()[rule_index]
     }
@@ -426,3 +1604,131 @@ impl Hasher for IdentityHasher {
         self.hash = i;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bevy_mod_props::Props;
+    use ustr::Ustr;
+
+    use super::ResponseEngine;
+    use crate::{Criterion, Delivery, Predicate, ResponseEngineCompiler, ResponseGroup, Rule};
+
+    #[test]
+    fn shared_criteria_scanned_once() {
+        let mut compiler = ResponseEngineCompiler::new();
+        let criterion = |variable: &str, predicate| Criterion {
+            variable: Ustr::from(variable),
+            predicate,
+            weight: 1.0,
+        };
+        compiler.with_criterion("Shared", criterion("shared", Predicate::BoolEqual(true)));
+        // Every rule shares one criterion, and fails on a criterion of its own
+        for i in 0..20 {
+            let unmet = Ustr::from(&format!("Unmet{i}"));
+            compiler.with_criterion(unmet, criterion("unmet", Predicate::NumEqual(i as f32)));
+            let rule = Rule {
+                criteria: vec![Ustr::from("Shared"), unmet],
+                any_criteria: vec![],
+                response_groups: vec![],
+                response_group_weights: Default::default(),
+                instructions: vec![],
+                priority: 0.0,
+            };
+            compiler.with_rule(format!("Rule{i}"), rule);
+        }
+        let mut engine = compiler.build().unwrap();
+
+        let request = Props::new().with("shared", true).with("unmet", -1.0);
+        let mut query = engine.build_query(&request, &Props::new(), &Props::new());
+        assert!(
+            engine
+                .find_best_matching_rule(&mut query, &mut rand::rng())
+                .is_none()
+        );
+
+        let count = |variable: &str| query.scans.iter().filter(|v| **v == variable).count();
+        assert_eq!(count("shared"), 1);
+        assert_eq!(count("unmet"), 20);
+    }
+
+    #[test]
+    fn empty_loop_group() {
+        let mut compiler = ResponseEngineCompiler::new();
+        compiler.with_response_group("Empty", ResponseGroup::builder(Delivery::Loop).build());
+        compiler.with_response_group(
+            "Hum",
+            ResponseGroup::builder(Delivery::Loop)
+                .response([("line", "hmm")])
+                .build(),
+        );
+        for (rule, group, priority) in [("Empty", "Empty", 1.0), ("Hum", "Hum", 0.0)] {
+            let rule_def = Rule {
+                criteria: vec![],
+                any_criteria: vec![],
+                response_groups: vec![Ustr::from(group)],
+                response_group_weights: Default::default(),
+                instructions: vec![],
+                priority,
+            };
+            compiler.with_rule(rule, rule_def);
+        }
+        let mut engine = compiler.build().unwrap();
+        let respond = |engine: &mut ResponseEngine| {
+            engine
+                .find_best_response_for(&Props::new(), &mut rand::rng())
+                .map(|response| response[&Ustr::from("line")].clone())
+        };
+
+        // An empty looping group says nothing rather than panicking
+        assert_eq!(respond(&mut engine), None);
+        assert_eq!(respond(&mut engine), None);
+
+        // The same goes for a group emptied after compiling
+        assert!(engine.set_rule_enabled("Empty", false));
+        assert_eq!(respond(&mut engine).unwrap(), "hmm");
+        assert!(engine.replace_response_group("Hum", vec![]));
+        assert_eq!(respond(&mut engine), None);
+        assert_eq!(respond(&mut engine), None);
+    }
+
+    #[test]
+    fn builder_weights_with_custom_field() {
+        let mut compiler = ResponseEngineCompiler::new();
+        compiler.with_weight_field("probability");
+        compiler.with_response_group(
+            "Never",
+            ResponseGroup::builder(Delivery::Random)
+                .weighted_response(0.0, [("line", "never")])
+                .build(),
+        );
+        // The builder's weight wins over the field, and is kept when the
+        // response is included in another group
+        compiler.with_response_group(
+            "Greet",
+            ResponseGroup::builder(Delivery::Random)
+                .weighted_response(1.0, [("line", "hello"), ("probability", "0")])
+                .include("Never")
+                .build(),
+        );
+        compiler.with_rule(
+            "Greet",
+            Rule {
+                criteria: vec![],
+                any_criteria: vec![],
+                response_groups: vec![Ustr::from("Greet")],
+                response_group_weights: Default::default(),
+                instructions: vec![],
+                priority: 0.0,
+            },
+        );
+        let mut engine = compiler.build().unwrap();
+
+        for _ in 0..50 {
+            let response = engine
+                .find_best_response_for(&Props::new(), &mut rand::rng())
+                .unwrap();
+            assert_eq!(response[&Ustr::from("line")], "hello");
+            assert!(!response.contains_key(&Ustr::from("probability")));
+        }
+    }
+}
@@ -3,6 +3,7 @@ pub mod engine;
 use core::fmt;
 use std::collections::HashMap;
 
+use engine::DEFAULT_MAX_PARTITION_POWERSET;
 use engine::Encoder;
 use ustr::Ustr;
 
@@ -22,21 +23,65 @@ pub struct Criterion {
     pub weight: f32,
 }
 
+/// Controls how much each criterion contributes to the score of a rule.
+///
+/// By default, a criterion contributes exactly its weight. With
+/// [`CriterionSpecificity::RangeWidth`], criteria that accept a narrower range
+/// of values contribute more:
+///
+/// ```text
+/// contribution = weight * (1 + 1 / (1 + width))
+/// ```
+///
+/// where `width` is `max - min` of the accepted range. Exact equalities
+/// (including boolean and string comparisons) have a width of zero and
+/// contribute double their weight, while unbounded ranges have infinite width
+/// and contribute just their weight. For example a criterion accepting
+/// `0..=1` contributes one and a half times its weight.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum CriterionSpecificity {
+    /// Every criterion contributes its weight, regardless of what it accepts.
+    #[default]
+    Ignore,
+    /// Criteria contribute more the narrower the range of values they accept.
+    RangeWidth,
+}
+
+impl CriterionSpecificity {
+    fn factor(self, min: f32, max: f32) -> f32 {
+        match self {
+            CriterionSpecificity::Ignore => 1.0,
+            CriterionSpecificity::RangeWidth => 1.0 + 1.0 / (1.0 + (max - min)),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Predicate {
     BoolEqual(bool),
     NumEqual(f32),
+    /// A range of numbers, `(start, end)`, open where a bound is `None`. Both
+    /// bounds are inclusive. In scripts the start of a range is always
+    /// inclusive and only the end can be exclusive: `a..b` is compiled as
+    /// `a..=b.next_down()`. A range whose start is after its end, like `0..0`,
+    /// never matches.
     NumRange(Option<f32>, Option<f32>),
     StrEqual(Ustr),
+    // A range over the values of an ordinal variable, in declaration order
+    OrdinalRange {
+        start: Option<Ustr>,
+        end: Option<Ustr>,
+        inclusive: bool,
+    },
 }
 
 impl Criterion {
-    fn build(self, name: Ustr, ctx: &mut Context) -> EngineCriterion {
+    fn build(self, name: Ustr, equality_epsilon: f32, ctx: &mut Context) -> EngineCriterion {
         // Generate some rudimentary type info
         let infered_type = match self.predicate {
             Predicate::BoolEqual(_) => Type::Bool,
             Predicate::NumEqual(_) | Predicate::NumRange(_, _) => Type::Num,
-            Predicate::StrEqual(_) => Type::Str,
+            Predicate::StrEqual(_) | Predicate::OrdinalRange { .. } => Type::Str,
         };
         let usage = VariableUsage {
             infered_type,
@@ -52,20 +97,60 @@ impl Criterion {
         let (min, max) = match self.predicate {
             crate::Predicate::BoolEqual(false) => (0.0, 0.0),
             crate::Predicate::BoolEqual(true) => (1.0, 1.0),
-            crate::Predicate::NumEqual(num) => (num, num),
+            crate::Predicate::NumEqual(num) => (num - equality_epsilon, num + equality_epsilon),
             crate::Predicate::NumRange(min, max) => (
                 min.unwrap_or(f32::NEG_INFINITY),
                 max.unwrap_or(f32::INFINITY),
             ),
             crate::Predicate::StrEqual(ustr) => {
-                let encoding = ctx.encoder.encode_ustr(ustr);
+                let encoding = ctx.encoder.encode_str(self.variable, ustr);
                 (encoding, encoding)
             }
+            crate::Predicate::OrdinalRange {
+                start,
+                end,
+                inclusive,
+            } => {
+                let mut encode_bound = |value: Ustr| {
+                    let encoding = ctx.encoder.encode_ordinal(self.variable, value);
+                    if encoding.is_none() {
+                        ctx.errors.push(CompileError::UnknownOrdinalValue {
+                            variable_name: self.variable,
+                            value,
+                            in_criterion: name,
+                        });
+                    }
+                    // A NaN bound never matches anything
+                    encoding.unwrap_or(f32::NAN)
+                };
+                let min = start.map(&mut encode_bound).unwrap_or(0.0);
+                let max = match end {
+                    Some(end) if inclusive => encode_bound(end),
+                    Some(end) => encode_bound(end) - 1.0,
+                    None => {
+                        let len = ctx.encoder.ordinal_len(self.variable).unwrap_or(0);
+                        len as f32 - 1.0
+                    }
+                };
+                (min, max)
+            }
+        };
+        // Empty ranges like `0..0` never match anyway, but are all given the
+        // same bounds (which no value, not even an infinity, falls between)
+        let (min, max) = if min > max {
+            (f32::INFINITY, f32::NEG_INFINITY)
+        } else {
+            (min, max)
+        };
+        let string = match self.predicate {
+            Predicate::StrEqual(ustr) => Some(ustr),
+            _ => None,
         };
         EngineCriterion {
             variable: self.variable,
             min,
             max,
+            string,
         }
     }
 }
@@ -73,24 +158,49 @@ impl Criterion {
 #[derive(Debug)]
 pub struct Rule {
     pub criteria: Vec<Ustr>,
+    // Groups of criteria where only one member of each group has to match.
+    // A group contributes the largest weight of its members to the score.
+    pub any_criteria: Vec<Vec<Ustr>>,
+    // May be empty, for "silent rules" that only run their instructions.
+    // These match like any other rule, but never produce a response.
     pub response_groups: Vec<Ustr>,
+    // How likely each response group is to be picked, relative to the rule's
+    // other groups. Groups without a weight have a weight of one. Groups with
+    // a weight of zero are only used when no other group has a response. A
+    // group listed more than once takes its weights here in order.
+    pub response_group_weights: Vec<(Ustr, f32)>,
     pub instructions: Vec<Instruction>,
+    // Rules with a higher priority always win over rules with a lower
+    // priority, regardless of score. Defaults to zero.
+    pub priority: f32,
 }
 
 #[derive(Debug)]
 pub struct Instruction {
     pub variable: Ustr,
-    pub global: bool,
+    pub target: InstructionTarget,
     pub operation: Operation,
 }
 
-#[derive(Copy, Clone, Debug)]
+// The props an instruction writes to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InstructionTarget {
+    Character, // The props of the responding character, written `var` in scripts
+    World,     // The world props, written `$var`
+    Request,   // Props local to the request, written `?var`. Never persisted.
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Operation {
     BoolSet(bool),
     BoolToggle,
     NumSet(f32),
     NumAdd(f32),
+    // Adds to a number, then clamps the result to an inclusive range
+    NumAddClamped { delta: f32, min: f32, max: f32 },
     StrSet(Ustr),
+    // Sets a number to the engine's clock, see `ResponseEngine::set_clock`
+    NumSetNow,
 }
 
 impl Rule {
@@ -103,11 +213,13 @@ impl Rule {
         response_groups_index: &UstrMap<usize>,
     ) -> (EngineRule, Vec<(Ustr, f32)>) {
         // Generate some rudimentary type info
-        let mut instructions = UstrMap::default();
         for instruction in &self.instructions {
             let infered_type = match instruction.operation {
                 Operation::BoolSet(_) | Operation::BoolToggle => Type::Bool,
-                Operation::NumSet(_) | Operation::NumAdd(_) => Type::Num,
+                Operation::NumSet(_)
+                | Operation::NumAdd(_)
+                | Operation::NumAddClamped { .. }
+                | Operation::NumSetNow => Type::Num,
                 Operation::StrSet(_) => Type::Str,
             };
             let usage = VariableUsage {
@@ -120,17 +232,29 @@ impl Rule {
                 ctx.variable_usages
                     .insert(instruction.variable, vec![usage]);
             }
-            instructions.insert(
-                instruction.variable,
-                (instruction.global, instruction.operation),
-            );
         }
+        // Sort by variable name, so instructions are always applied in the
+        // same order. The sort is stable, so instructions for the same
+        // variable are applied in the order they were written.
+        let mut instructions: Vec<_> = self
+            .instructions
+            .iter()
+            .map(|instruction| {
+                (
+                    instruction.variable,
+                    instruction.target,
+                    instruction.operation,
+                )
+            })
+            .collect();
+        instructions.sort_by_key(|(variable, _, _)| *variable);
 
         // Finalize
         let mut score = 0.0;
         let mut criteria = Vec::new();
         let mut response_groups = Vec::new();
         let mut partition_key = Vec::new();
+        let mut partition_criteria = Vec::new();
         let mut used_variables = UstrSet::default();
         let mut repeated_variables = UstrSet::default();
 
@@ -141,6 +265,7 @@ impl Rule {
                     score += weight;
                     if *partition {
                         partition_key.push((criterion.variable, criterion.min));
+                        partition_criteria.push(*i);
                     } else {
                         criteria.push(*i);
                     }
@@ -161,9 +286,38 @@ impl Rule {
             }
         }
 
+        let mut any_criteria = Vec::new();
+        for group in self.any_criteria {
+            let mut members = Vec::new();
+            let mut max_weight = None;
+            for criterion_name in group {
+                if let Some((i, weight, _)) = criteria_index.get(&criterion_name) {
+                    members.push(*i);
+                    max_weight = Some(f32::max(max_weight.unwrap_or(*weight), *weight));
+                } else {
+                    ctx.errors.push(CompileError::MissingCriterion {
+                        criterion_name,
+                        in_rule: name,
+                    });
+                }
+            }
+            score += max_weight.unwrap_or(0.0);
+            members.sort_by_key(|i| all_criteria[*i].variable);
+            any_criteria.push(members);
+        }
+
+        let mut listings = UstrMap::<usize>::default();
         for response_group in self.response_groups {
             if let Some(i) = response_groups_index.get(&response_group) {
-                response_groups.push(*i);
+                let listing = listings.entry(response_group).or_default();
+                let weight = self
+                    .response_group_weights
+                    .iter()
+                    .filter(|(group, _)| *group == response_group)
+                    .nth(*listing)
+                    .map_or(1.0, |(_, weight)| *weight);
+                *listing += 1;
+                response_groups.push((*i, weight));
             } else {
                 ctx.errors.push(CompileError::MissingResponseGroup {
                     group_name: response_group,
@@ -176,9 +330,13 @@ impl Rule {
         partition_key.sort_by_key(|(var, _)| *var);
 
         let engine = EngineRule {
+            name,
             criteria,
+            any_criteria,
+            partition_criteria,
             response_groups,
             instructions,
+            priority: self.priority,
             score,
             enabled: true,
         };
@@ -187,33 +345,161 @@ impl Rule {
     }
 }
 
+/// A group of responses, one of which is selected each time a rule using the
+/// group fires.
+///
+/// Each response may have a `weight` property, which controls how likely the
+/// random delivery modes are to pick it (the default weight is 1). Weights
+/// must be finite and non-negative, otherwise the group will fail to compile.
+/// A response with a weight of zero is never picked, unless every response in
+/// the group has a weight of zero, in which case they are all equally likely.
 #[derive(Debug)]
 pub struct ResponseGroup {
     pub delivery: Delivery,
     pub responses: Vec<UstrMap<String>>,
+    // Weights given in code, by the index of their response, which take
+    // precedence over the weight field. May be shorter than `responses`.
+    pub weights: Vec<Option<f32>>,
+    // Names of other response groups whose responses are appended to this
+    // group's own responses (in order) when compiled.
+    pub includes: Vec<Ustr>,
 }
 
-#[derive(Debug)]
+/// How a response group picks its next response.
+///
+/// The random modes pick responses in proportion to their weights, and never
+/// pick a response with no weight (unless no response in the group has any,
+/// in which case they are all equally likely). A group with only one response
+/// behaves the same in every mode as it would with several: it's the only
+/// choice, so `shuffle`, `random` and `random_no_repeat` give it every time,
+/// and `deplete` and `list` give it once.
+///
+/// A response with a `chance` field is picked as usual, and then only given
+/// if its chance roll succeeds. A failed roll still uses the response up, so
+/// `deplete` and `list` won't offer it again, and their rule is disabled once
+/// the group runs out either way.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Delivery {
-    Shuffle, // Random order, uses each response once before repeating
-    Random,  // Random order, no restrictions on repetition
-    Deplete, // Random order, never repeats a response
-    Loop,    // Sequential order, repeats cylically
-    List,    // Sequential order, never repeats a response
+    /// Random order without replacement: every response is used once before
+    /// any response repeats. Then the responses are shuffled again, and the
+    /// last response of one round is never the first of the next (so no
+    /// response is given twice in a row). Never runs out.
+    Shuffle,
+    /// Random order with replacement: every pick is independent of the ones
+    /// before it, so the same response may be given several times in a row.
+    /// Also available as [`Delivery::WeightedRandom`]. Never runs out.
+    Random,
+    /// Like [`Delivery::Random`], except the previous response is never given
+    /// twice in a row, unless it is the only response with any weight. Never
+    /// runs out.
+    RandomNoRepeat,
+    /// Random order without replacement, like one round of
+    /// [`Delivery::Shuffle`]: each response is given at most once, and then
+    /// the group runs out. Responses without any weight are never given, so
+    /// the group runs out once only they are left.
+    Deplete,
+    /// Sequential order, starting over from the first response after the
+    /// last. Weights are ignored. Never runs out.
+    Loop,
+    /// Sequential order, giving each response once, and then the group runs
+    /// out. Weights are ignored.
+    List,
+}
+
+impl Delivery {
+    /// Another name for [`Delivery::Random`], which spells out that responses
+    /// are picked by weight, with replacement. In scripts this is the
+    /// `weighted_random` keyword.
+    #[allow(non_upper_case_globals)]
+    pub const WeightedRandom: Delivery = Delivery::Random;
 }
 
 impl ResponseGroup {
-    fn build(self, name: Ustr, ctx: &mut Context) -> EngineResponseGroup {
-        let weight_ustr = Ustr::from("weight");
+    /// Starts building a response group in code, for callers that don't use
+    /// scripts.
+    ///
+    /// ```rust
+    /// # use trill_core::*;
+    /// # use bevy_mod_props::Props;
+    /// # use ustr::Ustr;
+    /// let group = ResponseGroup::builder(Delivery::Shuffle)
+    ///     .response([("line", "What are you looking at, punk?")])
+    ///     .response([("line", "Hey you, get going.")])
+    ///     .response([("line", "What you staring at me for?")])
+    ///     .response([("line", "Do I know you?")])
+    ///     .weighted_response(0.5, [("line", "You waiting for somebody or something?")])
+    ///     .build();
+    ///
+    /// let mut compiler = ResponseEngineCompiler::new();
+    /// compiler.with_response_group("CitizenTalkStare", group);
+    /// compiler.with_rule(
+    ///     "CitizenTalkStare",
+    ///     Rule {
+    ///         criteria: vec![],
+    ///         any_criteria: vec![],
+    ///         response_groups: vec![Ustr::from("CitizenTalkStare")],
+    ///         response_group_weights: Default::default(),
+    ///         instructions: vec![],
+    ///         priority: 0.0,
+    ///     },
+    /// );
+    /// let (engine, report) = compiler.finish();
+    /// assert!(report.errors.is_empty());
+    ///
+    /// let mut engine = engine.unwrap();
+    /// let response = engine.find_best_response_for(&Props::new(), &mut rand::rng());
+    /// assert!(response.unwrap().contains_key(&Ustr::from("line")));
+    /// ```
+    pub fn builder(delivery: Delivery) -> ResponseGroupBuilder {
+        ResponseGroupBuilder {
+            group: ResponseGroup {
+                delivery,
+                responses: Vec::new(),
+                weights: Vec::new(),
+                includes: Vec::new(),
+            },
+        }
+    }
+
+    fn build(self, name: Ustr, weight_field: Ustr, ctx: &mut Context) -> EngineResponseGroup {
+        let chance_ustr = Ustr::from("chance");
+        let mut chances = Vec::with_capacity(self.responses.len());
+        let mut explicit_weights = self.weights.into_iter();
         let (weights, responses): (Vec<_>, Vec<_>) = self
             .responses
             .into_iter()
             .map(|mut properties| {
-                let weight = properties
-                    .remove(&weight_ustr)
+                let chance = properties
+                    .remove(&chance_ustr)
                     .and_then(|string| match string.parse::<f32>() {
-                        Ok(w) => Some(w),
-                        Err(_) => {
+                        Ok(chance) if (0.0..=1.0).contains(&chance) => Some(chance),
+                        _ => {
+                            ctx.errors.push(CompileError::InvalidChance {
+                                string,
+                                in_response_group: name,
+                            });
+                            None
+                        }
+                    })
+                    .unwrap_or(1.0);
+                chances.push(chance);
+                // Weights given in code are checked the same way as fields
+                let field = properties.remove(&weight_field);
+                let weight = match explicit_weights.next().flatten() {
+                    Some(weight) => Some(weight.to_string()),
+                    None => field,
+                };
+                let weight = weight
+                    .and_then(|string| match string.parse::<f32>() {
+                        Ok(w) if w < 0.0 => {
+                            ctx.errors.push(CompileError::NegativeWeight {
+                                weight: w,
+                                in_response_group: name,
+                            });
+                            None
+                        }
+                        Ok(w) if w.is_finite() => Some(w),
+                        _ => {
                             let error = CompileError::InvalidWeightString {
                                 string,
                                 in_response_group: name,
@@ -226,43 +512,101 @@ impl ResponseGroup {
                 (weight, properties)
             })
             .unzip();
-        let dispatcher = match self.delivery {
-            Delivery::Shuffle => ResponseDispatcher::Shuffle {
-                weights,
-                candidates: (0..responses.len()).collect(),
-            },
-            Delivery::Random => ResponseDispatcher::Random { weights },
-            Delivery::Deplete => ResponseDispatcher::Deplete {
-                weights,
-                candidates: (0..responses.len()).collect(),
-            },
-            Delivery::Loop => ResponseDispatcher::Loop {
-                len: responses.len(),
-                index: 0,
-            },
-            Delivery::List => ResponseDispatcher::List {
-                len: responses.len(),
-                index: 0,
-            },
+        // If nothing has any weight, treat everything as equally likely
+        let weights = if weights.iter().all(|weight| *weight == 0.0) {
+            vec![1.0; weights.len()]
+        } else {
+            weights
         };
+        let dispatcher = ResponseDispatcher::new(self.delivery, weights.clone());
         EngineResponseGroup {
+            name,
+            delivery: self.delivery,
             dispatcher,
+            forced_dispatchers: Vec::new(),
+            weights,
+            chances,
             responses,
         }
     }
 }
 
+/// Builds a [`ResponseGroup`] one response at a time. See
+/// [`ResponseGroup::builder`].
+#[derive(Debug)]
+pub struct ResponseGroupBuilder {
+    group: ResponseGroup,
+}
+
+impl ResponseGroupBuilder {
+    /// Adds a response with the given fields. A `weight` field is treated
+    /// the same as in scripts, and must parse as a non-negative number.
+    pub fn response<K, V>(mut self, fields: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<Ustr>,
+        V: ToString,
+    {
+        let response = fields
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.to_string()))
+            .collect();
+        self.group.responses.push(response);
+        self
+    }
+
+    /// Adds a response with the given fields and weight. Like weights in
+    /// scripts, it must be finite and non-negative, otherwise the group will
+    /// fail to compile.
+    ///
+    /// The weight is kept apart from the fields, so it applies whichever
+    /// field the compiler reads weights from (see
+    /// [`ResponseEngineCompiler::with_weight_field`]), and takes precedence
+    /// over that field if the response has it too.
+    pub fn weighted_response<K, V>(
+        self,
+        weight: f32,
+        fields: impl IntoIterator<Item = (K, V)>,
+    ) -> Self
+    where
+        K: Into<Ustr>,
+        V: ToString,
+    {
+        let mut builder = self.response(fields);
+        let index = builder.group.responses.len() - 1;
+        builder.group.weights.resize(index, None);
+        builder.group.weights.push(Some(weight));
+        builder
+    }
+
+    /// Appends the responses of another response group, as if they were part
+    /// of this one.
+    pub fn include(mut self, group: impl Into<Ustr>) -> Self {
+        self.group.includes.push(group.into());
+        self
+    }
+
+    pub fn build(self) -> ResponseGroup {
+        self.group
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ResponseEngineCompiler {
     partition_variables: UstrSet,
+    ordinals: UstrMap<Vec<Ustr>>,
+    criterion_specificity: CriterionSpecificity,
+    strict_types: bool,
+    equality_epsilon: f32,
+    weight_field: Option<Ustr>,
     criteria: UstrMap<Criterion>,
     rules: UstrMap<Rule>,
     response_groups: UstrMap<ResponseGroup>,
 }
 
-#[derive(Default)]
+#[derive(Default, Debug)]
 pub struct CompilerReport {
     pub errors: Vec<CompileError>,
+    pub warnings: Vec<CompileWarning>,
 }
 
 #[derive(Debug)]
@@ -275,6 +619,15 @@ pub enum CompileError {
         string: String,
         in_response_group: Ustr,
     },
+    NegativeWeight {
+        weight: f32,
+        in_response_group: Ustr,
+    },
+    // A `chance` field that isn't a number from zero to one
+    InvalidChance {
+        string: String,
+        in_response_group: Ustr,
+    },
     MissingCriterion {
         criterion_name: Ustr,
         in_rule: Ustr,
@@ -287,12 +640,146 @@ pub enum CompileError {
         criterion_name: Ustr,
         in_rule: Ustr,
     },
+    UnknownOrdinalValue {
+        variable_name: Ustr,
+        value: Ustr,
+        in_criterion: Ustr,
+    },
+    MissingIncludedResponseGroup {
+        group_name: Ustr,
+        in_response_group: Ustr,
+    },
+    CyclicResponseGroupInclude {
+        group_name: Ustr,
+        in_response_group: Ustr,
+    },
+}
+
+impl fmt::Display for CompilerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.errors.as_slice() {
+            [] => write!(f, "compiled without errors")?,
+            [error] => write!(f, "compile error: {}", error)?,
+            errors => {
+                write!(f, "{} compile errors:", errors.len())?;
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CompilerReport {}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::IndeterminateVariableType {
+                variable_name,
+                usages,
+            } => {
+                write!(f, "found conflicting types for variable {}", variable_name)?;
+                let types: Vec<_> = usages.iter().map(|u| u.infered_type.to_string()).collect();
+                if !types.is_empty() {
+                    write!(f, " (used as {})", types.join(", "))?;
+                }
+                Ok(())
+            }
+            CompileError::InvalidWeightString {
+                string,
+                in_response_group,
+            } => write!(
+                f,
+                "invalid weight string \"{}\" in response group {}",
+                string, in_response_group
+            ),
+            CompileError::NegativeWeight {
+                weight,
+                in_response_group,
+            } => write!(
+                f,
+                "negative weight {} in response group {}",
+                weight, in_response_group
+            ),
+            CompileError::InvalidChance {
+                string,
+                in_response_group,
+            } => write!(
+                f,
+                "invalid chance \"{}\" in response group {}",
+                string, in_response_group
+            ),
+            CompileError::MissingCriterion {
+                criterion_name,
+                in_rule,
+            } => write!(
+                f,
+                "unable to find criterion definition {} referenced in rule {}",
+                criterion_name, in_rule
+            ),
+            CompileError::MissingResponseGroup {
+                group_name,
+                in_rule,
+            } => write!(
+                f,
+                "unable to find response group definition {} referenced in rule {}",
+                group_name, in_rule
+            ),
+            CompileError::RepeatedVariable {
+                criterion_name,
+                in_rule,
+            } => write!(
+                f,
+                "criterion {} tests a variable already used in rule {}",
+                criterion_name, in_rule
+            ),
+            CompileError::UnknownOrdinalValue {
+                variable_name,
+                value,
+                in_criterion,
+            } => write!(
+                f,
+                "{} is not an ordinal value of variable {} (used in criterion {})",
+                value, variable_name, in_criterion
+            ),
+            CompileError::MissingIncludedResponseGroup {
+                group_name,
+                in_response_group,
+            } => write!(
+                f,
+                "unable to find response group definition {} included in response group {}",
+                group_name, in_response_group
+            ),
+            CompileError::CyclicResponseGroupInclude {
+                group_name,
+                in_response_group,
+            } => write!(
+                f,
+                "response group {} includes {}, which eventually includes it back",
+                in_response_group, group_name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+#[derive(Debug)]
+pub enum CompileWarning {
+    // A partition variable that no criterion tests for exact equality, and so
+    // does not actually partition any rules.
+    UnusedPartitionVariable { variable_name: Ustr },
 }
 
 #[derive(Debug)]
 pub enum VariableLocation {
     Criterion(Ustr),
     Rule(Ustr),
+    // The declaration of an ordinal variable, named after the variable. Only
+    // used with strict types.
+    Ordinal(Ustr),
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -321,6 +808,7 @@ pub struct VariableUsage {
 #[derive(Default)]
 struct Context {
     errors: Vec<CompileError>,
+    warnings: Vec<CompileWarning>,
     encoder: Encoder,
     // Map from names to types and call-sites
     variable_usages: UstrMap<Vec<VariableUsage>>,
@@ -335,6 +823,51 @@ impl ResponseEngineCompiler {
         self.partition_variables.insert(variable.into());
     }
 
+    /// Declares a variable as an ordinal: a string variable whose values have
+    /// a defined order (the order they are given here), so that criteria can
+    /// test whether it falls within a range of values.
+    pub fn with_ordinal<I>(&mut self, variable: impl Into<Ustr>, values: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<Ustr>,
+    {
+        let values = values.into_iter().map(Into::into).collect();
+        self.ordinals.insert(variable.into(), values);
+    }
+
+    /// Sets how criteria contribute to the score of rules. See
+    /// [`CriterionSpecificity`].
+    pub fn with_criterion_specificity(&mut self, specificity: CriterionSpecificity) {
+        self.criterion_specificity = specificity;
+    }
+
+    /// Enables strict type checking. Ordinal variables then count as strings,
+    /// so a criterion like `(rank == 3)` on an ordinal `rank` is an error
+    /// rather than being compared against the position of a value.
+    pub fn strict_types(&mut self, strict: bool) {
+        self.strict_types = strict;
+    }
+
+    /// Widens numeric equality criteria like `(x == 0.3)` to also match
+    /// values within `epsilon` of the number, so that results of float
+    /// arithmetic like `0.1 + 0.2` still match. Defaults to zero, which only
+    /// matches the exact number.
+    ///
+    /// A criterion widened this way is no longer an exact equality, so it
+    /// can't be used to partition rules even if its variable is a partition
+    /// variable. Boolean and string equalities are not affected.
+    pub fn with_equality_epsilon(&mut self, epsilon: f32) {
+        self.equality_epsilon = epsilon.abs();
+    }
+
+    /// Sets the name of the response field that holds the weight of a
+    /// response. Defaults to `weight`. This field is parsed when compiling,
+    /// and is stripped from the responses the engine returns, so it can't
+    /// be read by the game.
+    pub fn with_weight_field(&mut self, field: impl Into<Ustr>) {
+        self.weight_field = Some(field.into());
+    }
+
     pub fn with_criterion(&mut self, name: impl Into<Ustr>, criterion: Criterion) {
         self.criteria.insert(name.into(), criterion);
     }
@@ -347,29 +880,86 @@ impl ResponseEngineCompiler {
         self.response_groups.insert(name.into(), response_group);
     }
 
+    /// Compiles the engine, failing if there were any errors. Warnings are
+    /// discarded on success; use [`finish`](Self::finish) to inspect them.
+    pub fn build(self) -> Result<ResponseEngine, CompilerReport> {
+        match self.finish() {
+            (Some(engine), _) => Ok(engine),
+            (None, report) => Err(report),
+        }
+    }
+
     pub fn finish(self) -> (Option<ResponseEngine>, CompilerReport) {
         let mut ctx = Context::default();
 
+        // Ordinals must be known before any of their values are encoded
+        for (variable, values) in &self.ordinals {
+            ctx.encoder.add_ordinal(*variable, values);
+            if self.strict_types {
+                let usage = VariableUsage {
+                    infered_type: Type::Str,
+                    location: VariableLocation::Ordinal(*variable),
+                };
+                ctx.variable_usages
+                    .entry(*variable)
+                    .or_default()
+                    .push(usage);
+            }
+        }
+
         // Compile criteria
         let mut criteria = Vec::new();
         let mut criteria_index = UstrMap::default();
-        for (i, (name, criterion)) in self.criteria.into_iter().enumerate() {
+        let mut used_partition_variables = UstrSet::default();
+        // Definitions are processed in order of name, so that compiling the
+        // same definitions always assigns the same indices and breaks ties
+        // between rules the same way
+        for (i, (name, criterion)) in sorted_by_name(self.criteria).into_iter().enumerate() {
             let weight = criterion.weight;
-            let criterion = criterion.build(name, &mut ctx);
+            let criterion = criterion.build(name, self.equality_epsilon, &mut ctx);
+            let weight = weight
+                * self
+                    .criterion_specificity
+                    .factor(criterion.min, criterion.max);
             // If this the criterion is an exact equalitry and the variable is
             // in the partitions list, it can be used to group rules into
             // partitions.
             let partition = criterion.min == criterion.max
                 && self.partition_variables.contains(&criterion.variable);
+            if partition {
+                used_partition_variables.insert(criterion.variable);
+            }
             criteria.push(criterion);
             criteria_index.insert(name, (i, weight, partition));
         }
 
+        // Flatten included response groups
+        let weight_field = self.weight_field.unwrap_or_else(|| Ustr::from("weight"));
+        let mut flattened = UstrMap::default();
+        let mut response_group_names: Vec<_> = self.response_groups.keys().copied().collect();
+        response_group_names.sort_unstable();
+        for name in response_group_names {
+            flatten_response_group(
+                name,
+                &self.response_groups,
+                &mut flattened,
+                &mut Vec::new(),
+                &mut ctx,
+            );
+        }
+
         // Compile response groups
         let mut response_groups = Vec::new();
         let mut response_group_index = UstrMap::default();
-        for (i, (name, response_group)) in self.response_groups.into_iter().enumerate() {
-            let response_group = response_group.build(name, &mut ctx);
+        for (i, (name, mut response_group)) in
+            sorted_by_name(self.response_groups).into_iter().enumerate()
+        {
+            (response_group.responses, response_group.weights) = flattened
+                .remove(&name)
+                .unwrap_or_default()
+                .into_iter()
+                .unzip();
+            let response_group = response_group.build(name, weight_field, &mut ctx);
             response_groups.push(response_group);
             response_group_index.insert(name, i);
         }
@@ -377,12 +967,24 @@ impl ResponseEngineCompiler {
         let mut partition_variables: Vec<_> = self.partition_variables.into_iter().collect();
         partition_variables.sort();
 
+        // Warn about partition variables that won't partition anything, and
+        // drop them so they don't inflate the number of partitions searched
+        partition_variables.retain(|variable_name| {
+            let used = used_partition_variables.contains(variable_name);
+            if !used {
+                ctx.warnings.push(CompileWarning::UnusedPartitionVariable {
+                    variable_name: *variable_name,
+                });
+            }
+            used
+        });
+
         // Compile rules and group into partitions
         let mut rules = RulePartitions {
             vars: partition_variables,
             partitions: HashMap::default(),
         };
-        for (name, rule) in self.rules.into_iter() {
+        for (name, rule) in sorted_by_name(self.rules) {
             let (rule, assignments) = rule.build(
                 name,
                 &mut ctx,
@@ -394,17 +996,25 @@ impl ResponseEngineCompiler {
             rules.partitions.entry(key).or_default().push(rule);
         }
 
-        // Sort rule partitions by score
+        // Sort rule partitions by priority, then by score, keeping rules that
+        // tie in order of name
         for partition in rules.partitions.values_mut() {
-            partition.sort_unstable_by(|ra, rb| rb.score.total_cmp(&ra.score));
+            partition.sort_by(|ra, rb| {
+                rb.priority
+                    .total_cmp(&ra.priority)
+                    .then(rb.score.total_cmp(&ra.score))
+            });
         }
 
-        // Rudimentary type-checking
-        for (variable_name, usages) in ctx.variable_usages {
+        // Rudimentary type-checking, in a fixed order so errors are reported
+        // the same way every time
+        let mut variable_usages: Vec<_> = ctx.variable_usages.into_iter().collect();
+        variable_usages.sort_unstable_by_key(|(variable_name, _)| *variable_name);
+        for (variable_name, usages) in variable_usages {
             // Check that each variable has a single type
             let coherent = usages
-                .windows(2)
-                .all(|w| w[0].infered_type == w[1].infered_type);
+                .iter()
+                .all(|usage| usage.infered_type == usages[0].infered_type);
             if !coherent {
                 ctx.errors.push(CompileError::IndeterminateVariableType {
                     variable_name,
@@ -419,11 +1029,84 @@ impl ResponseEngineCompiler {
                 rules,
                 response_groups,
                 encoder: ctx.encoder,
+                weight_field,
+                max_partition_powerset: DEFAULT_MAX_PARTITION_POWERSET,
+                partition_fallback_logged: false,
+                clock: 0.0,
             };
 
-            (Some(engine), CompilerReport { errors: ctx.errors })
+            let report = CompilerReport {
+                errors: ctx.errors,
+                warnings: ctx.warnings,
+            };
+            (Some(engine), report)
         } else {
-            (None, CompilerReport { errors: ctx.errors })
+            let report = CompilerReport {
+                errors: ctx.errors,
+                warnings: ctx.warnings,
+            };
+            (None, report)
         }
     }
 }
+
+// Collects the definitions in a map, sorted by name
+fn sorted_by_name<T>(map: UstrMap<T>) -> Vec<(Ustr, T)> {
+    let mut definitions: Vec<_> = map.into_iter().collect();
+    definitions.sort_unstable_by_key(|(name, _)| *name);
+    definitions
+}
+
+// Collects the responses of a response group, along with the responses of all
+// the groups it includes (recursively), each with any weight given in code.
+// Results are cached in `flattened`, and
+// `visiting` holds the chain of includes currently being resolved, which is
+// used to detect cycles.
+fn flatten_response_group(
+    name: Ustr,
+    response_groups: &UstrMap<ResponseGroup>,
+    flattened: &mut UstrMap<Vec<(UstrMap<String>, Option<f32>)>>,
+    visiting: &mut Vec<Ustr>,
+    ctx: &mut Context,
+) {
+    if flattened.contains_key(&name) {
+        return;
+    }
+    let Some(response_group) = response_groups.get(&name) else {
+        return;
+    };
+
+    visiting.push(name);
+    let weights = response_group
+        .weights
+        .iter()
+        .copied()
+        .chain(std::iter::repeat(None));
+    let mut responses: Vec<_> = response_group
+        .responses
+        .iter()
+        .cloned()
+        .zip(weights)
+        .collect();
+    for include in &response_group.includes {
+        if visiting.contains(include) {
+            ctx.errors.push(CompileError::CyclicResponseGroupInclude {
+                group_name: *include,
+                in_response_group: name,
+            });
+            continue;
+        }
+        if !response_groups.contains_key(include) {
+            ctx.errors.push(CompileError::MissingIncludedResponseGroup {
+                group_name: *include,
+                in_response_group: name,
+            });
+            continue;
+        }
+        flatten_response_group(*include, response_groups, flattened, visiting, ctx);
+        responses.extend(flattened[include].iter().cloned());
+    }
+    visiting.pop();
+
+    flattened.insert(name, responses);
+}
@@ -10,7 +10,7 @@ use codespan_reporting::{
     },
 };
 use logos::Span;
-use trill_core::{CompileError, VariableLocation};
+use trill_core::{CompileError, CompileWarning, VariableLocation};
 use ustr::{Ustr, UstrMap};
 
 use crate::lexer::Token;
@@ -45,10 +45,12 @@ impl<T, E> AddSpan for Result<T, E> {
 }
 
 #[derive(Default, Debug, PartialEq, Eq, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum LexicalError {
     NumericError {
         error: ParseFloatError,
     },
+    InvalidDigitSeparator,
     #[default]
     LexicalError,
 }
@@ -64,6 +66,27 @@ pub enum ParseError {
     LexError {
         error: LexicalError,
     },
+    // An error inside a definition, like `rule` or `response`, along with the
+    // name of the definition
+    InDefinition {
+        kind: Ustr,
+        name: Ustr,
+        error: Box<ParseError>,
+    },
+}
+
+impl Spanned<ParseError> {
+    // Records that this error occured inside the named definition
+    pub fn in_definition(self, kind: Ustr, name: Ustr) -> Self {
+        Spanned {
+            error: ParseError::InDefinition {
+                kind,
+                name,
+                error: Box::new(self.error),
+            },
+            span: self.span,
+        }
+    }
 }
 
 impl AddSpan for ParseError {
@@ -74,6 +97,8 @@ impl AddSpan for ParseError {
     }
 }
 
+/// Where a definition appears in the source. The `file_id` can be used with
+/// [`ScriptReport::files`] to get the name and source of the file.
 #[derive(Debug)]
 pub struct Location {
     pub file_id: usize,
@@ -83,73 +108,174 @@ pub struct Location {
 #[derive(Debug)]
 pub struct ScriptReport {
     pub compile_errors: Vec<CompileError>,
+    pub compile_warnings: Vec<CompileWarning>,
     pub parse_errors: Vec<(usize, Spanned<ParseError>)>,
     pub files: SimpleFiles<Ustr, String>,
     pub criterion_locations: UstrMap<Location>,
     pub rule_locations: UstrMap<Location>,
     pub response_group_locations: UstrMap<Location>,
+    pub ordinal_locations: UstrMap<Location>,
+    // The criteria (including members of `any` groups) and response groups
+    // each rule refers to, by name
+    pub rule_criteria: UstrMap<Vec<Ustr>>,
+    pub rule_response_groups: UstrMap<Vec<Ustr>>,
 }
 
 impl ScriptReport {
+    /// Iterates over the names and locations of every criterion that was
+    /// defined, in source order. This includes criteria from files that
+    /// failed to compile, up to the point parsing stopped, but not anonymous
+    /// criteria written inline in rules.
+    pub fn criteria(&self) -> impl Iterator<Item = (Ustr, &Location)> {
+        in_source_order(&self.criterion_locations).filter(|(name, _)| !name.contains('#'))
+    }
+
+    /// Iterates over the names and locations of every rule that was defined,
+    /// in source order.
+    pub fn rules(&self) -> impl Iterator<Item = (Ustr, &Location)> {
+        in_source_order(&self.rule_locations)
+    }
+
+    /// Iterates over the names and locations of every response group that was
+    /// defined, in source order.
+    pub fn response_groups(&self) -> impl Iterator<Item = (Ustr, &Location)> {
+        in_source_order(&self.response_group_locations)
+    }
+
+    /// Exports the structure of the scripts as a GraphViz DOT graph. Each
+    /// rule, criterion and response group is a node, and each rule has edges
+    /// to the criteria it tests and the response groups it responds from.
+    ///
+    /// Node ids are prefixed by their kind, like `"rule:Greet"`, since a rule
+    /// and a response group often share a name.
+    pub fn export_graph(&self) -> String {
+        let mut dot = String::from("digraph trill {\n");
+        for (name, _) in self.rules() {
+            dot += &format!("    \"rule:{name}\" [label=\"{name}\", shape=box];\n");
+        }
+        for (name, _) in in_source_order(&self.criterion_locations) {
+            dot += &format!("    \"criterion:{name}\" [label=\"{name}\", shape=ellipse];\n");
+        }
+        for (name, _) in self.response_groups() {
+            dot += &format!("    \"response:{name}\" [label=\"{name}\", shape=note];\n");
+        }
+        for (rule, _) in self.rules() {
+            for criterion in self.rule_criteria.get(&rule).into_iter().flatten() {
+                dot += &format!("    \"rule:{rule}\" -> \"criterion:{criterion}\";\n");
+            }
+            for group in self.rule_response_groups.get(&rule).into_iter().flatten() {
+                dot += &format!("    \"rule:{rule}\" -> \"response:{group}\";\n");
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+
+    /// Prints all errors and warnings to stderr as colored, annotated source
+    /// snippets. This is intended for command line tools; games should
+    /// generally prefer [`ScriptReport::log`].
     pub fn print(self) {
         let writer = StandardStream::stderr(ColorChoice::Always);
         let config = codespan_reporting::term::Config::default();
 
-        for (file_id, Spanned { error, span }) in self.parse_errors {
-            let diagnostic = match error {
-                ParseError::UnexpectedEof => Diagnostic::error()
-                    .with_message("encountered unexpected end of file while parsing")
-                    .with_label(
-                        Label::primary(file_id, span).with_message("file ends abruptly here"),
-                    ),
-                ParseError::UnexpectedToken {
-                    token,
-                    expected,
-                    hint,
-                } => {
-                    let diagnostic = Diagnostic::error()
-                        .with_message("encountered unexpected token while parsing")
-                        .with_label(
-                            Label::primary(file_id, span)
-                                .with_message(format!("expected {}, found {}", expected, token)),
-                        );
-
-                    if let Some(hint) = hint {
-                        diagnostic.with_note(hint)
-                    } else {
-                        diagnostic
-                    }
-                }
-                ParseError::LexError { error } => match error {
-                    LexicalError::NumericError { error } => Diagnostic::error()
-                        .with_message("failed to prase float literal")
-                        .with_label(
-                            Label::primary(file_id, span).with_message(format!("{}", error)),
-                        ),
-                    LexicalError::LexicalError => Diagnostic::error()
-                        .with_message(format!("lexical error in file {}", file_id)),
-                },
-            };
-
+        for diagnostic in self.diagnostics() {
             term::emit_to_write_style(&mut writer.lock(), &config, &self.files, &diagnostic)
                 .unwrap();
         }
+    }
+
+    /// Renders all errors and warnings as plain, uncolored annotated source
+    /// snippets. This is the same output as [`ScriptReport::print`], for
+    /// when the report needs to be passed along as a string.
+    pub fn render(&self) -> String {
+        let config = codespan_reporting::term::Config::default();
+
+        let mut rendered = String::new();
+        for diagnostic in self.diagnostics() {
+            term::emit_to_string(&mut rendered, &config, &self.files, &diagnostic).unwrap();
+        }
+        rendered
+    }
+
+    /// Emits each error and warning as a `tracing` event, so that they end up
+    /// in the same log pipeline as the rest of the application. Each event
+    /// records the file, line, column and byte span of the diagnostic (when it
+    /// has a location) as structured fields.
+    #[cfg(feature = "tracing")]
+    pub fn log(self) {
+        use codespan_reporting::{diagnostic::Severity, files::Files};
+
+        for diagnostic in self.diagnostics() {
+            let mut message = diagnostic.message;
+            let label = diagnostic.labels.first();
+            if let Some(label) = label
+                && !label.message.is_empty()
+            {
+                message = format!("{message}: {}", label.message);
+            }
+            for note in diagnostic.notes {
+                message = format!("{message} (note: {note})");
+            }
+
+            let location = label.and_then(|label| {
+                let file = self.files.name(label.file_id).ok()?;
+                let location = self.files.location(label.file_id, label.range.start).ok()?;
+                Some((file, location, label.range.clone()))
+            });
+
+            match (diagnostic.severity, location) {
+                (Severity::Bug | Severity::Error, Some((file, location, span))) => tracing::error!(
+                    file = %file,
+                    line = location.line_number,
+                    column = location.column_number,
+                    span = ?span,
+                    "{message}"
+                ),
+                (Severity::Bug | Severity::Error, None) => tracing::error!("{message}"),
+                (_, Some((file, location, span))) => tracing::warn!(
+                    file = %file,
+                    line = location.line_number,
+                    column = location.column_number,
+                    span = ?span,
+                    "{message}"
+                ),
+                (_, None) => tracing::warn!("{message}"),
+            }
+        }
+    }
 
-        for compile_error in self.compile_errors {
+    fn diagnostics(&self) -> Vec<Diagnostic<usize>> {
+        let mut diagnostics = Vec::new();
+
+        for (file_id, Spanned { error, span }) in self.parse_errors.iter().cloned() {
+            let diagnostic = parse_error_diagnostic(file_id, span, error);
+            diagnostics.push(diagnostic);
+        }
+
+        for compile_error in &self.compile_errors {
             let diagnostic = match compile_error {
                 CompileError::IndeterminateVariableType {
                     variable_name,
                     usages,
                 } => {
-                    let labels = usages.into_iter().map(|useage| {
+                    let labels = usages.iter().map(|useage| {
                         let location = match useage.location {
                             VariableLocation::Criterion(ustr) => {
                                 self.criterion_locations.get(&ustr).unwrap()
                             }
                             VariableLocation::Rule(ustr) => self.rule_locations.get(&ustr).unwrap(),
+                            VariableLocation::Ordinal(ustr) => {
+                                self.ordinal_locations.get(&ustr).unwrap()
+                            }
+                        };
+                        let message = match useage.location {
+                            VariableLocation::Ordinal(_) => {
+                                format!("declared as an ordinal {} here", useage.infered_type)
+                            }
+                            _ => format!("used as {} here", useage.infered_type),
                         };
                         Label::secondary(location.file_id, location.span.clone())
-                            .with_message(format!("used as {} here", useage.infered_type))
+                            .with_message(message)
                     });
                     Diagnostic::error()
                         .with_message(format!(
@@ -164,7 +290,7 @@ impl ScriptReport {
                 } => {
                     let location = self
                         .response_group_locations
-                        .get(&in_response_group)
+                        .get(in_response_group)
                         .unwrap();
                     Diagnostic::error()
                         .with_message("invalid weight string")
@@ -174,11 +300,44 @@ impl ScriptReport {
                             ),
                         )
                 }
+                CompileError::NegativeWeight {
+                    weight,
+                    in_response_group,
+                } => {
+                    let location = self
+                        .response_group_locations
+                        .get(in_response_group)
+                        .unwrap();
+                    Diagnostic::error()
+                        .with_message("negative response weight")
+                        .with_label(
+                            Label::primary(location.file_id, location.span.clone())
+                                .with_message(format!("weight {} is less than zero", weight)),
+                        )
+                        .with_note("weights must be zero or greater")
+                }
+                CompileError::InvalidChance {
+                    string,
+                    in_response_group,
+                } => {
+                    let location = self
+                        .response_group_locations
+                        .get(in_response_group)
+                        .unwrap();
+                    Diagnostic::error()
+                        .with_message("invalid response chance")
+                        .with_label(
+                            Label::primary(location.file_id, location.span.clone()).with_message(
+                                format!("unable to understand chance \"{}\"", string),
+                            ),
+                        )
+                        .with_note("chances must be numbers from 0 to 1")
+                }
                 CompileError::MissingCriterion {
                     criterion_name,
                     in_rule,
                 } => {
-                    let location = self.rule_locations.get(&in_rule).unwrap();
+                    let location = self.rule_locations.get(in_rule).unwrap();
                     Diagnostic::error()
                         .with_message(format!(
                             "unable to fine criteria defintion {}",
@@ -193,7 +352,7 @@ impl ScriptReport {
                     group_name,
                     in_rule,
                 } => {
-                    let location = self.rule_locations.get(&in_rule).unwrap();
+                    let location = self.rule_locations.get(in_rule).unwrap();
                     Diagnostic::error()
                         .with_message(format!(
                             "unable to fine response group defintion {}",
@@ -208,7 +367,7 @@ impl ScriptReport {
                     criterion_name,
                     in_rule,
                 } => {
-                    let location = self.rule_locations.get(&in_rule).unwrap();
+                    let location = self.rule_locations.get(in_rule).unwrap();
                     Diagnostic::error()
                         .with_message(format!("variable used twice within the same rule",))
                         .with_label(
@@ -220,10 +379,134 @@ impl ScriptReport {
                             ),
                         )
                 }
+                CompileError::UnknownOrdinalValue {
+                    variable_name,
+                    value,
+                    in_criterion,
+                } => {
+                    let location = self.criterion_locations.get(in_criterion).unwrap();
+                    Diagnostic::error()
+                        .with_message(format!(
+                            "{} is not an ordinal value of variable {}",
+                            value, variable_name
+                        ))
+                        .with_label(
+                            Label::primary(location.file_id, location.span.clone())
+                                .with_message(format!("used in criterion {}", in_criterion)),
+                        )
+                        .with_note(format!(
+                            "ranges of strings require an ordinal declaration, like (ordinal {} (a b c))",
+                            variable_name
+                        ))
+                }
+                CompileError::MissingIncludedResponseGroup {
+                    group_name,
+                    in_response_group,
+                } => {
+                    let location = self
+                        .response_group_locations
+                        .get(in_response_group)
+                        .unwrap();
+                    Diagnostic::error()
+                        .with_message(format!(
+                            "unable to find response group definition {}",
+                            group_name
+                        ))
+                        .with_label(
+                            Label::primary(location.file_id, location.span.clone()).with_message(
+                                format!("included in response group {}", in_response_group),
+                            ),
+                        )
+                }
+                CompileError::CyclicResponseGroupInclude {
+                    group_name,
+                    in_response_group,
+                } => {
+                    let location = self
+                        .response_group_locations
+                        .get(in_response_group)
+                        .unwrap();
+                    Diagnostic::error()
+                        .with_message("response groups include each other in a cycle")
+                        .with_label(
+                            Label::primary(location.file_id, location.span.clone()).with_message(
+                                format!(
+                                    "response group {} includes {}, which eventually includes it back",
+                                    in_response_group, group_name
+                                ),
+                            ),
+                        )
+                }
             };
 
-            term::emit_to_write_style(&mut writer.lock(), &config, &self.files, &diagnostic)
-                .unwrap();
+            diagnostics.push(diagnostic);
+        }
+
+        for compile_warning in &self.compile_warnings {
+            let diagnostic = match compile_warning {
+                CompileWarning::UnusedPartitionVariable { variable_name } => Diagnostic::warning()
+                    .with_message(format!(
+                        "partition variable {} is never tested for equality by any criterion",
+                        variable_name
+                    ))
+                    .with_note("this variable will not be used to partition any rules"),
+            };
+
+            diagnostics.push(diagnostic);
+        }
+
+        diagnostics
+    }
+}
+
+fn parse_error_diagnostic(file_id: usize, span: Span, error: ParseError) -> Diagnostic<usize> {
+    match error {
+        ParseError::UnexpectedEof => Diagnostic::error()
+            .with_message("encountered unexpected end of file while parsing")
+            .with_label(Label::primary(file_id, span).with_message("file ends abruptly here")),
+        ParseError::UnexpectedToken {
+            token,
+            expected,
+            hint,
+        } => {
+            let diagnostic = Diagnostic::error()
+                .with_message("encountered unexpected token while parsing")
+                .with_label(
+                    Label::primary(file_id, span)
+                        .with_message(format!("expected {}, found {}", expected, token)),
+                );
+
+            if let Some(hint) = hint {
+                diagnostic.with_note(hint)
+            } else {
+                diagnostic
+            }
         }
+        ParseError::InDefinition { kind, name, error } => {
+            parse_error_diagnostic(file_id, span, *error).with_note(format!("in {kind} {name}"))
+        }
+        ParseError::LexError { error } => match error {
+            LexicalError::NumericError { error } => Diagnostic::error()
+                .with_message("failed to prase float literal")
+                .with_label(Label::primary(file_id, span).with_message(format!("{}", error))),
+            LexicalError::InvalidDigitSeparator => Diagnostic::error()
+                .with_message("failed to parse numeric literal")
+                .with_label(
+                    Label::primary(file_id, span)
+                        .with_message("underscores must be placed between two digits"),
+                ),
+            LexicalError::LexicalError => {
+                Diagnostic::error().with_message(format!("lexical error in file {}", file_id))
+            }
+        },
     }
 }
+
+fn in_source_order(locations: &UstrMap<Location>) -> impl Iterator<Item = (Ustr, &Location)> {
+    let mut locations: Vec<_> = locations
+        .iter()
+        .map(|(name, location)| (*name, location))
+        .collect();
+    locations.sort_by_key(|(_, location)| (location.file_id, location.span.start));
+    locations.into_iter()
+}
@@ -2,6 +2,7 @@ use std::fmt;
 
 use logos::Lexer;
 use logos::Logos;
+use logos::Span;
 use ustr::Ustr;
 
 use crate::error::LexicalError;
@@ -11,12 +12,27 @@ use crate::error::Spanned;
 #[logos(skip r"[ \t\n\f]+")]
 #[logos(error(Spanned<LexicalError>, callback = parse_error))]
 pub enum Token {
-    #[regex("[a-zA-Z][a-zA-Z0-9_$]*", |lex| Ustr::from(lex.slice()))]
+    // Symbols start with any unicode letter. Whether a symbol is an identifier
+    // or a variable name is decided by the unicode case of its first letter,
+    // so symbols starting with a letter that has no case (as in many non-latin
+    // scripts) are neither. Symbols may be namespaced with colons (like
+    // `prop:mood`), but only response fields accept such symbols.
+    #[regex(r"\p{L}[\p{L}0-9_$]*(?::\p{L}[\p{L}0-9_$]*)*", |lex| Ustr::from(lex.slice()))]
     Symbol(Ustr),
 
-    #[regex(r"-?(?:0|[1-9]\d*)(?:\.\d+)?(?:[eE][+-]?\d+)?", parse_numeric)]
+    // Underscores are matched loosely here, and validated in `parse_numeric`
+    // so malformed literals are reported as a single error. As in rust,
+    // leading zeros are allowed and don't mean octal, so `007` is seven.
+    // Negative numbers are put together by `Tokens`, since whether a `-` is a
+    // sign depends on what comes before it.
+    #[regex(r"\d[\d_]*(?:\.\d[\d_]*)?(?:[eE][+-]?\d[\d_]*)?", parse_numeric)]
     Number(f32),
 
+    // Longer symbols like `trueness` still lex as symbols
+    #[token("true", |_| true)]
+    #[token("false", |_| false)]
+    Bool(bool),
+
     #[regex(r#""(?:[^"]|\\")*""#, parse_string)]
     String(String),
 
@@ -47,12 +63,19 @@ pub enum Token {
 
     #[token("$")]
     DollarSign,
+
+    #[token("?")]
+    QuestionMark,
+
+    #[token("-")]
+    Minus,
 }
 
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Token::Symbol(ustr) => write!(f, "symbol '{}'", ustr),
+            Token::Bool(bool) => write!(f, "boolean '{}'", bool),
             Token::Number(num) => write!(f, "number '{}'", num),
             Token::String(string) => write!(f, "string literal \"{}\"", string),
             Token::ParenOpen => write!(f, "an open parnehtisis"),
@@ -65,15 +88,98 @@ impl fmt::Display for Token {
             Token::Range(false) => write!(f, "the .. specifier"),
             Token::Range(true) => write!(f, "the ..= specifier"),
             Token::DollarSign => write!(f, "the $ variable modifier"),
+            Token::QuestionMark => write!(f, "the ? variable modifier"),
+            Token::Minus => write!(f, "the - operator"),
+        }
+    }
+}
+
+/// The tokens of a source file. This wraps the generated lexer to decide what
+/// each `-` means.
+///
+/// A `-` directly followed by a number (without whitespace between them) is
+/// the sign of a negative number literal, unless it directly follows a value:
+/// a number, boolean, string, symbol or closing parenthesis. Every other `-`
+/// is a [`Token::Minus`]. So `-5`, `(x in -5..-1)` and `x -5` contain negative
+/// literals, while `5 - 3`, `5-3` and `(f x)-1` contain a minus.
+pub struct Tokens<'src> {
+    lexer: Lexer<'src, Token>,
+    span: Span,
+    // Where the previous token ended, if it was a value
+    value_end: Option<usize>,
+}
+
+impl<'src> Tokens<'src> {
+    pub fn new(source: &'src str) -> Tokens<'src> {
+        Tokens {
+            lexer: Lexer::new(source),
+            span: 0..0,
+            value_end: None,
+        }
+    }
+
+    /// Returns the span of the most recent token.
+    pub fn span(&self) -> Span {
+        self.span.clone()
+    }
+}
+
+impl<'src> Iterator for Tokens<'src> {
+    type Item = Result<Token, Spanned<LexicalError>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut token = self.lexer.next()?;
+        self.span = self.lexer.span();
+
+        if token == Ok(Token::Minus) && self.value_end != Some(self.span.start) {
+            let mut ahead = self.lexer.clone();
+            if let Some(Ok(Token::Number(num))) = ahead.next()
+                && ahead.span().start == self.span.end
+            {
+                token = Ok(Token::Number(-num));
+                self.span.end = ahead.span().end;
+                self.lexer = ahead;
+            }
         }
+
+        self.value_end = match token {
+            Ok(
+                Token::Number(_)
+                | Token::Bool(_)
+                | Token::String(_)
+                | Token::Symbol(_)
+                | Token::ParenClose,
+            ) => Some(self.span.end),
+            _ => None,
+        };
+        Some(token)
     }
 }
 
 fn parse_numeric(lexer: &mut Lexer<Token>) -> Result<f32, Spanned<LexicalError>> {
-    lexer.slice().parse::<f32>().map_err(|error| Spanned {
-        error: LexicalError::NumericError { error },
-        span: lexer.span(),
-    })
+    // Underscores may be used to group digits, but only one at a time, and
+    // only between two digits
+    let slice = lexer.slice();
+    let is_digit = |i: Option<usize>| {
+        i.and_then(|i| slice.as_bytes().get(i))
+            .is_some_and(u8::is_ascii_digit)
+    };
+    for (i, byte) in slice.bytes().enumerate() {
+        if byte == b'_' && !(is_digit(i.checked_sub(1)) && is_digit(Some(i + 1))) {
+            return Err(Spanned {
+                error: LexicalError::InvalidDigitSeparator,
+                span: lexer.span(),
+            });
+        }
+    }
+
+    slice
+        .replace('_', "")
+        .parse::<f32>()
+        .map_err(|error| Spanned {
+            error: LexicalError::NumericError { error },
+            span: lexer.span(),
+        })
 }
 
 fn parse_string(lexer: &mut Lexer<Token>) -> Result<String, Spanned<LexicalError>> {
@@ -5,20 +5,27 @@ mod parser;
 use std::fmt::Debug;
 
 use codespan_reporting::files::SimpleFiles;
-use error::Location;
-use error::ScriptReport;
+pub use error::Location;
+pub use error::ScriptReport;
 use parser::Definition;
 use parser::Parser;
 use ustr::Ustr;
 use ustr::UstrMap;
+use ustr::UstrSet;
 
+use trill_core::CriterionSpecificity;
 use trill_core::ResponseEngineCompiler;
 use trill_core::engine::ResponseEngine;
 
 #[derive(Debug, Default)]
 pub struct ScriptCompiler {
     partition_variables: Vec<Ustr>,
+    criterion_specificity: CriterionSpecificity,
+    strict_types: bool,
+    equality_epsilon: f32,
+    weight_field: Option<Ustr>,
     files: SimpleFiles<Ustr, String>,
+    module_names: UstrSet,
 }
 
 impl ScriptCompiler {
@@ -26,8 +33,21 @@ impl ScriptCompiler {
         ScriptCompiler::default()
     }
 
-    pub fn add_module(&mut self, name: impl Into<Ustr>, source: impl ToString) {
-        self.files.add(name.into(), source.to_string());
+    /// Adds a module to be compiled, and returns the name it was added under.
+    /// Module names are used to point at definitions in error messages, so
+    /// they are kept unique: if a module with the same name was already
+    /// added, a number is appended, as in `dialog (2)`.
+    pub fn add_module(&mut self, name: impl Into<Ustr>, source: impl ToString) -> Ustr {
+        let requested = name.into();
+        let mut name = requested;
+        let mut n = 2;
+        while self.module_names.contains(&name) {
+            name = Ustr::from(&format!("{requested} ({n})"));
+            n += 1;
+        }
+        self.module_names.insert(name);
+        self.files.add(name, source.to_string());
+        name
     }
 
     pub fn with_module(mut self, name: impl Into<Ustr>, source: impl ToString) -> Self {
@@ -44,14 +64,65 @@ impl ScriptCompiler {
         self
     }
 
+    pub fn set_criterion_specificity(&mut self, specificity: CriterionSpecificity) {
+        self.criterion_specificity = specificity;
+    }
+
+    pub fn with_criterion_specificity(mut self, specificity: CriterionSpecificity) -> Self {
+        self.set_criterion_specificity(specificity);
+        self
+    }
+
+    /// Enables strict type checking. See
+    /// [`ResponseEngineCompiler::strict_types`].
+    pub fn set_strict_types(&mut self, strict: bool) {
+        self.strict_types = strict;
+    }
+
+    pub fn with_strict_types(mut self, strict: bool) -> Self {
+        self.set_strict_types(strict);
+        self
+    }
+
+    /// Lets numeric equality criteria match nearby values. See
+    /// [`ResponseEngineCompiler::with_equality_epsilon`].
+    pub fn set_equality_epsilon(&mut self, epsilon: f32) {
+        self.equality_epsilon = epsilon;
+    }
+
+    pub fn with_equality_epsilon(mut self, epsilon: f32) -> Self {
+        self.set_equality_epsilon(epsilon);
+        self
+    }
+
+    /// Reads response weights from a field other than `weight`. See
+    /// [`ResponseEngineCompiler::with_weight_field`].
+    pub fn set_weight_field(&mut self, field: impl Into<Ustr>) {
+        self.weight_field = Some(field.into());
+    }
+
+    pub fn with_weight_field(mut self, field: impl Into<Ustr>) -> Self {
+        self.set_weight_field(field);
+        self
+    }
+
     pub fn compile(self) -> (Option<ResponseEngine>, ScriptReport) {
         // First parse all the sources
         let mut compiler = ResponseEngineCompiler::new();
+        compiler.with_criterion_specificity(self.criterion_specificity);
+        compiler.strict_types(self.strict_types);
+        compiler.with_equality_epsilon(self.equality_epsilon);
+        if let Some(weight_field) = self.weight_field {
+            compiler.with_weight_field(weight_field);
+        }
         let mut parse_errors = Vec::default();
 
         let mut criterion_locations = UstrMap::default();
         let mut rule_locations = UstrMap::default();
         let mut response_group_locations = UstrMap::default();
+        let mut ordinal_locations = UstrMap::default();
+        let mut rule_criteria = UstrMap::default();
+        let mut rule_response_groups = UstrMap::default();
 
         let mut i = 0;
         while let Ok(file) = self.files.get(i) {
@@ -63,8 +134,29 @@ impl ScriptCompiler {
                         criterion_locations.insert(name, Location { file_id: i, span });
                         compiler.with_criterion(name, criterion);
                     }
-                    Ok(Some((Definition::Rule { name, rule }, span))) => {
+                    Ok(Some((
+                        Definition::Rule {
+                            name,
+                            rule,
+                            inline_criteria,
+                        },
+                        span,
+                    ))) => {
+                        for inline in inline_criteria {
+                            let location = Location {
+                                file_id: i,
+                                span: inline.span,
+                            };
+                            criterion_locations.insert(inline.name, location);
+                            compiler.with_criterion(inline.name, inline.criterion);
+                        }
                         rule_locations.insert(name, Location { file_id: i, span });
+                        let criteria = rule
+                            .criteria
+                            .iter()
+                            .chain(rule.any_criteria.iter().flatten());
+                        rule_criteria.insert(name, criteria.copied().collect());
+                        rule_response_groups.insert(name, rule.response_groups.clone());
                         compiler.with_rule(name, rule);
                     }
                     Ok(Some((
@@ -77,6 +169,10 @@ impl ScriptCompiler {
                         response_group_locations.insert(name, Location { file_id: i, span });
                         compiler.with_response_group(name, response_group);
                     }
+                    Ok(Some((Definition::Ordinal { variable, values }, span))) => {
+                        ordinal_locations.insert(variable, Location { file_id: i, span });
+                        compiler.with_ordinal(variable, values);
+                    }
                     Err(error) => {
                         parse_errors.push((i, error));
                         break;
@@ -88,11 +184,15 @@ impl ScriptCompiler {
 
         let mut report = ScriptReport {
             compile_errors: Vec::new(),
+            compile_warnings: Vec::new(),
             parse_errors,
             files: self.files,
             criterion_locations,
             rule_locations,
             response_group_locations,
+            ordinal_locations,
+            rule_criteria,
+            rule_response_groups,
         };
 
         if !report.parse_errors.is_empty() {
@@ -105,6 +205,7 @@ impl ScriptCompiler {
 
         let (engine, compiler_report) = compiler.finish();
         report.compile_errors = compiler_report.errors;
+        report.compile_warnings = compiler_report.warnings;
 
         (engine, report)
     }
@@ -112,8 +213,12 @@ impl ScriptCompiler {
 
 #[cfg(test)]
 mod test {
-    use trill_core::engine::StatementSet;
-    use ustr::Ustr;
+    use bevy_mod_props::Props;
+    use trill_core::{
+        CompileError, CompileWarning, CriterionSpecificity, InstructionTarget, Operation,
+        engine::ResponseEngine,
+    };
+    use ustr::{Ustr, UstrMap};
 
     use crate::ScriptCompiler;
 
@@ -176,20 +281,1944 @@ mod test {
 
         let mut engine = engine.unwrap();
 
-        let actor = StatementSet::new()
+        let mut actor = Props::new()
             .with("distance_to_player", 20.0)
             .with("class_name", "citizen")
             .with("target_name", "miles")
             .with("npc_state", "idle");
 
-        let query = StatementSet::new().with("concept", "talk_stare");
+        let query = Props::new().with("concept", "talk_stare");
 
-        let query = [&actor, &query];
+        let mut world = Props::new();
         let mut rng = rand::rng();
-        let resp = engine.find_best_response(query, &mut rng).unwrap();
+        let resp = engine
+            .find_best_response(&query, &mut actor, &mut world, &mut rng)
+            .unwrap();
 
         let line = resp.get(&Ustr::from("line")).unwrap();
 
         assert_eq!(line, "Oh hi! I'm Miles");
     }
+
+    #[test]
+    fn export_graph() {
+        let script = r#"
+            (criterion ConceptTalkStare (concept == talk_stare) weight 5)
+            (criterion IsCitizen (class_name == citizen))
+            (criterion NPCIdle (npc_state == idle))
+
+            (rule CitizenTalkStare (ConceptTalkStare IsCitizen NPCIdle) (CitizenTalkStare))
+
+            (response CitizenTalkStare shuffle
+                (line "What are you looking at, punk?")
+                (line "Do I know you?"))
+        "#;
+        let (_, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+
+        let dot = report.export_graph();
+        assert!(dot.starts_with("digraph trill {"));
+        assert!(dot.contains(r#""rule:CitizenTalkStare" [label="CitizenTalkStare", shape=box];"#));
+        for criterion in ["ConceptTalkStare", "IsCitizen", "NPCIdle"] {
+            let edge = format!(r#""rule:CitizenTalkStare" -> "criterion:{criterion}";"#);
+            assert!(dot.contains(&edge), "{dot}");
+        }
+        assert!(dot.contains(r#""rule:CitizenTalkStare" -> "response:CitizenTalkStare";"#));
+    }
+
+    #[test]
+    fn rule_priority_beats_score() {
+        let script = r#"
+            (criterion ConceptGreet (concept == greet))
+            (criterion IsCitizen (class_name == citizen) weight 5)
+            (criterion NPCIdle (npc_state == idle) weight 5)
+
+            (rule Specific (ConceptGreet IsCitizen NPCIdle) (Specific))
+            (rule Urgent (ConceptGreet) (Urgent) (priority 100))
+
+            (response Specific (line "specific"))
+            (response Urgent (line "urgent"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+
+        report.print();
+
+        let mut engine = engine.unwrap();
+
+        let mut actor = Props::new()
+            .with("class_name", "citizen")
+            .with("npc_state", "idle");
+        let query = Props::new().with("concept", "greet");
+        let mut world = Props::new();
+        let mut rng = rand::rng();
+        let resp = engine
+            .find_best_response(&query, &mut actor, &mut world, &mut rng)
+            .unwrap();
+
+        assert_eq!(resp.get(&Ustr::from("line")).unwrap(), "urgent");
+    }
+
+    #[test]
+    fn explain_demo_criteria() {
+        let script = include_str!("../../../demo/assets/dialog.trl");
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_partition_variable("name")
+            .with_module("dialog.trl", script)
+            .compile();
+
+        report.print();
+
+        let mut engine = engine.unwrap();
+
+        let query = Props::new().with("concept", "idle").with("name", "clippy");
+        let mut actor = Props::new();
+        let mut world = Props::new();
+        let mut rng = rand::rng();
+        let explained =
+            engine.find_best_response_explained(&query, &mut actor, &mut world, &mut rng);
+
+        assert!(explained.response.is_some());
+        let variables: Vec<_> = explained
+            .criteria
+            .iter()
+            .map(|criterion| criterion.variable.as_str())
+            .collect();
+        assert_eq!(variables, ["concept", "name"]);
+        for criterion in explained.criteria {
+            assert_eq!(criterion.value, criterion.min);
+            assert_eq!(criterion.value, criterion.max);
+        }
+    }
+
+    #[test]
+    fn random_no_repeat_never_repeats() {
+        let script = r#"
+            (rule Idle () (Idle))
+            (response Idle random_no_repeat
+                (line "a")
+                (line "b")
+                (line "c"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+
+        report.print();
+
+        let mut engine = engine.unwrap();
+
+        let query = Props::new();
+        let mut actor = Props::new();
+        let mut world = Props::new();
+        let mut rng = rand::rng();
+        let mut last = None;
+        for _ in 0..100 {
+            let line = engine
+                .find_best_response(&query, &mut actor, &mut world, &mut rng)
+                .unwrap()
+                .get(&Ustr::from("line"))
+                .cloned();
+            assert!(line.is_some());
+            assert_ne!(line, last);
+            last = line;
+        }
+    }
+
+    #[test]
+    fn delivery_repetition() {
+        // Draws from a group with the given delivery and responses, with
+        // `None` once the group runs out
+        fn draw(delivery: &str, lines: &[&str], count: usize) -> Vec<Option<String>> {
+            let responses: String = lines.iter().map(|l| format!("(line \"{l}\")")).collect();
+            let script = format!("(rule Idle () (Idle)) (response Idle {delivery} {responses})");
+            let (engine, report) = ScriptCompiler::new()
+                .with_module("script.trl", &script)
+                .compile();
+            report.print();
+            let mut engine = engine.unwrap();
+
+            let mut rng = rand::rng();
+            (0..count)
+                .map(|_| {
+                    let response = engine.find_best_response(
+                        &Props::new(),
+                        &mut Props::new(),
+                        &mut Props::new(),
+                        &mut rng,
+                    )?;
+                    Some(response[&Ustr::from("line")].clone())
+                })
+                .collect()
+        }
+        let lines = |lines: &[&str]| -> Vec<Option<String>> {
+            lines.iter().map(|line| Some(line.to_string())).collect()
+        };
+
+        // A single response is the only choice in every mode
+        for delivery in [
+            "shuffle",
+            "random",
+            "weighted_random",
+            "random_no_repeat",
+            "loop",
+        ] {
+            assert_eq!(draw(delivery, &["a"], 4), lines(&["a"; 4]), "{delivery}");
+        }
+        for delivery in ["deplete", "list"] {
+            assert_eq!(draw(delivery, &["a"], 3), [Some("a".into()), None, None]);
+        }
+
+        let abc = ["a", "b", "c"];
+        let sorted = |mut lines: Vec<Option<String>>| {
+            lines.sort();
+            lines
+        };
+
+        // Shuffle uses every response once before repeating, and never gives
+        // the same response twice in a row
+        let shuffled = draw("shuffle", &abc, 30);
+        assert_eq!(sorted(shuffled[..3].to_vec()), lines(&abc));
+        assert!(shuffled.iter().all(|line| line.is_some()));
+        assert!(shuffled.windows(2).all(|pair| pair[0] != pair[1]));
+
+        // Random picks with replacement, and eventually repeats itself
+        for delivery in ["random", "weighted_random"] {
+            let random = draw(delivery, &abc, 100);
+            assert!(random.iter().all(|line| line.is_some()));
+            assert!(random.windows(2).any(|pair| pair[0] == pair[1]));
+            let mut seen = sorted(random);
+            seen.dedup();
+            assert_eq!(seen, lines(&abc));
+        }
+
+        let no_repeat = draw("random_no_repeat", &abc, 100);
+        assert!(no_repeat.iter().all(|line| line.is_some()));
+        assert!(no_repeat.windows(2).all(|pair| pair[0] != pair[1]));
+
+        // Deplete gives each response once in any order, then runs out
+        let depleted = draw("deplete", &abc, 5);
+        assert_eq!(sorted(depleted[..3].to_vec()), lines(&abc));
+        assert_eq!(depleted[3..], [None, None]);
+
+        assert_eq!(
+            draw("loop", &abc, 7),
+            lines(&["a", "b", "c", "a", "b", "c", "a"])
+        );
+        let mut listed = lines(&abc);
+        listed.push(None);
+        assert_eq!(draw("list", &abc, 4), listed);
+    }
+
+    #[test]
+    fn shuffle_rounds() {
+        let script = r#"
+            (rule Idle () (Idle))
+            (response Idle shuffle
+                (line "a")
+                (line "b")
+                (line "c")
+                (line "d"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let mut rng = rand::rng();
+        for _ in 0..20 {
+            let mut lines = Vec::new();
+            for _ in 0..8 {
+                let response = engine
+                    .find_best_response(
+                        &Props::new(),
+                        &mut Props::new(),
+                        &mut Props::new(),
+                        &mut rng,
+                    )
+                    .unwrap();
+                lines.push(response[&Ustr::from("line")].clone());
+            }
+
+            // Each round uses every response once
+            for round in lines.chunks(4) {
+                let mut round = round.to_vec();
+                round.sort();
+                assert_eq!(round, ["a", "b", "c", "d"]);
+            }
+            // Including across the boundary between rounds
+            assert!(lines.windows(2).all(|pair| pair[0] != pair[1]));
+        }
+    }
+
+    #[test]
+    fn warn_unused_partition_variable() {
+        let script = r#"
+            (criterion ConceptIdle (concept == idle))
+            (rule Idle (ConceptIdle) (Idle))
+            (response Idle (line "..."))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_partition_variable("mood")
+            .with_module("script.trl", script)
+            .compile();
+
+        assert!(engine.is_some());
+        assert!(matches!(
+            report.compile_warnings.as_slice(),
+            [CompileWarning::UnusedPartitionVariable { variable_name }] if variable_name == "mood"
+        ));
+    }
+
+    #[test]
+    fn effective_partition_variables() {
+        let script = r#"
+            (criterion ConceptIdle (concept == idle))
+            (criterion ConceptHurt (concept == hurt))
+            (criterion Calm (mood in 0..5))
+            (rule Idle (ConceptIdle Calm) (Idle))
+            (rule Hurt (ConceptHurt) (Hurt))
+            (response Idle (line "..."))
+            (response Hurt (line "ow"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_partition_variable("mood")
+            .with_module("script.trl", script)
+            .compile();
+        let mut engine = engine.unwrap();
+
+        // mood is only tested against a range, so it can't partition anything
+        assert!(matches!(
+            report.compile_warnings.as_slice(),
+            [CompileWarning::UnusedPartitionVariable { variable_name }] if variable_name == "mood"
+        ));
+        assert_eq!(engine.partition_variables(), [Ustr::from("concept")]);
+        assert_eq!(engine.num_partitions(), 2);
+
+        // Matching still works with the pruned partitioning
+        let request = Props::new().with("concept", "idle").with("mood", 1.0);
+        let response = engine.find_best_response(
+            &request,
+            &mut Props::new(),
+            &mut Props::new(),
+            &mut rand::rng(),
+        );
+        assert_eq!(response.unwrap().get(&Ustr::from("line")).unwrap(), "...");
+    }
+
+    #[test]
+    fn partition_powerset_fallback() {
+        let script = r#"
+            (criterion ConceptIdle (concept == idle))
+            (criterion NameBob (name == bob))
+            (rule Exact (ConceptIdle NameBob) (Exact))
+            (rule Partial (ConceptIdle) (Partial))
+            (rule Anything () (Anything))
+            (response Exact (line "exact"))
+            (response Partial (line "partial"))
+            (response Anything (line "anything"))
+        "#;
+
+        let (engine, _) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_partition_variable("name")
+            .with_module("script.trl", script)
+            .compile();
+        let mut engine = engine.unwrap();
+
+        let respond = |engine: &mut ResponseEngine, name: &str| {
+            let request = Props::new().with("concept", "idle").with("name", name);
+            let response = engine.find_best_response(
+                &request,
+                &mut Props::new(),
+                &mut Props::new(),
+                &mut rand::rng(),
+            );
+            response
+                .unwrap()
+                .get(&Ustr::from("line"))
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(respond(&mut engine, "bob"), "exact");
+        assert_eq!(respond(&mut engine, "alice"), "partial");
+
+        // With the fallback, only the exact and unpartitioned rules are checked
+        engine.max_partition_powerset = 1;
+        assert_eq!(respond(&mut engine, "bob"), "exact");
+        assert_eq!(respond(&mut engine, "alice"), "anything");
+    }
+
+    #[test]
+    fn set_rule_enabled() {
+        let script = r#"
+            (criterion ConceptIdle (concept == idle))
+            (rule Secret (ConceptIdle) (Secret) (priority 1))
+            (rule Idle (ConceptIdle) (Idle))
+            (response Secret (line "psst"))
+            (response Idle (line "..."))
+        "#;
+
+        let (engine, _) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        let mut engine = engine.unwrap();
+
+        let request = Props::new().with("concept", "idle");
+        let respond = |engine: &mut ResponseEngine| {
+            let response = engine.find_best_response_for(&request, &mut rand::rng());
+            response
+                .unwrap()
+                .get(&Ustr::from("line"))
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(respond(&mut engine), "psst");
+        assert!(engine.set_rule_enabled("Secret", false));
+        assert_eq!(respond(&mut engine), "...");
+        assert!(engine.set_rule_enabled("Secret", true));
+        assert_eq!(respond(&mut engine), "psst");
+        assert!(!engine.set_rule_enabled("Missing", false));
+    }
+
+    #[test]
+    fn any_criteria() {
+        let script = r#"
+            (criterion ConceptAlarm (concept == alarm))
+            (criterion IsGuard (class == guard))
+            (criterion IsSoldier (class == soldier))
+            (rule Halt (ConceptAlarm (any IsGuard IsSoldier)) (Halt))
+            (rule Panic (ConceptAlarm) (Panic))
+            (response Halt (line "halt!"))
+            (response Panic (line "aaah!"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        assert!(report.parse_errors.is_empty());
+        let mut engine = engine.unwrap();
+
+        let mut respond = |class: &str| {
+            let request = Props::new().with("concept", "alarm").with("class", class);
+            let response = engine.find_best_response_for(&request, &mut rand::rng());
+            response
+                .unwrap()
+                .get(&Ustr::from("line"))
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(respond("guard"), "halt!");
+        assert_eq!(respond("soldier"), "halt!");
+        assert_eq!(respond("peasant"), "aaah!");
+    }
+
+    #[test]
+    fn set_now() {
+        let script = r#"
+            (criterion ConceptGreet (concept == greet))
+            (criterion GreetedRecently (greeting_time in 50..))
+            (rule Greet (ConceptGreet) (Greet) $greeting_time := now)
+            (rule GreetAgain (ConceptGreet GreetedRecently) (GreetAgain))
+            (response Greet (line "hello"))
+            (response GreetAgain (line "hello again"))
+        "#;
+
+        let (engine, _) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        let mut engine = engine.unwrap();
+        assert_eq!(
+            engine.preview_instructions("Greet"),
+            [(
+                Ustr::from("greeting_time"),
+                InstructionTarget::World,
+                Operation::NumSetNow
+            )]
+        );
+
+        let request = Props::new().with("concept", "greet");
+        let mut world = Props::new();
+        let respond = |engine: &mut ResponseEngine, world: &mut Props| {
+            let response =
+                engine.find_best_response(&request, &mut Props::new(), world, &mut rand::rng());
+            response
+                .unwrap()
+                .get(&Ustr::from("line"))
+                .unwrap()
+                .to_string()
+        };
+
+        engine.set_clock(60.0);
+        assert_eq!(respond(&mut engine, &mut world), "hello");
+        assert_eq!(world["greeting_time"], 60.0);
+        assert_eq!(respond(&mut engine, &mut world), "hello again");
+    }
+
+    #[test]
+    fn advance_clock() {
+        let script = r#"
+            (criterion ConceptTick (concept == tick))
+            (rule Tick (ConceptTick) () $last_tick := now)
+        "#;
+
+        let (engine, _) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        let mut engine = engine.unwrap();
+
+        // Each response sees the clock as it was last set
+        let request = Props::new().with("concept", "tick");
+        let mut world = Props::new();
+        for now in [1.0, 5.0, 12.5] {
+            engine.set_clock(now);
+            engine.find_best_response(&request, &mut Props::new(), &mut world, &mut rand::rng());
+            assert_eq!(world["last_tick"], now);
+            assert_eq!(engine.clock(), now);
+        }
+    }
+
+    #[test]
+    fn warmup_matches_cold() {
+        let script = r#"
+            (criterion ConceptGreet (concept == greet))
+            (criterion IsGuard (class == guard))
+            (criterion IsAngry (mood == angry))
+            (rule Greet (ConceptGreet) (Greet))
+            (rule GuardGreet (ConceptGreet IsGuard) (GuardGreet))
+            (rule AngryGuardGreet (ConceptGreet IsGuard IsAngry) (AngryGuardGreet))
+            (response Greet (line "hello"))
+            (response GuardGreet (line "halt"))
+            (response AngryGuardGreet (line "get lost"))
+        "#;
+        let compile = || {
+            let (engine, _) = ScriptCompiler::new()
+                .with_partition_variable("concept")
+                .with_partition_variable("class")
+                .with_module("script.trl", script)
+                .compile();
+            engine.unwrap()
+        };
+
+        let request = Props::new().with("concept", "greet");
+        let characters = [
+            Props::new(),
+            Props::new().with("class", "guard"),
+            Props::new().with("class", "guard").with("mood", "angry"),
+            Props::new().with("class", "citizen").with("mood", "angry"),
+        ];
+        let respond = |engine: &mut ResponseEngine| {
+            characters
+                .iter()
+                .map(|character| {
+                    let response = engine.find_best_response(
+                        &request,
+                        &mut character.clone(),
+                        &mut Props::new(),
+                        &mut rand::rng(),
+                    );
+                    response.map(|response| response[&Ustr::from("line")].clone())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut cold = compile();
+        let mut warm = compile();
+        let samples = [&request, &characters[2], &Props::new().with("mood", "calm")];
+        warm.warmup(&samples);
+        assert_eq!(respond(&mut warm), respond(&mut cold));
+        assert_eq!(
+            respond(&mut cold),
+            [
+                Some("hello".to_string()),
+                Some("halt".to_string()),
+                Some("get lost".to_string()),
+                Some("hello".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn inline_criteria() {
+        let script = r#"
+            (criterion IsCitizen (class == citizen))
+            (rule Idle ((concept == idle) IsCitizen) (Idle))
+            (rule Bored ((concept == idle) IsCitizen (boredom in 5..)) (Bored))
+            (response Idle (line "la la la"))
+            (response Bored (line "*yawn*"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        assert!(report.compile_warnings.is_empty());
+        let criteria: Vec<_> = report.criteria().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(criteria, ["IsCitizen"]);
+        let mut engine = engine.unwrap();
+
+        let request = Props::new().with("concept", "idle");
+        let mut respond = |character: Props| {
+            let response = engine.find_best_response(
+                &request,
+                &mut character.clone(),
+                &mut Props::new(),
+                &mut rand::rng(),
+            );
+            response.map(|response| response[&Ustr::from("line")].clone())
+        };
+
+        let citizen = Props::new().with("class", "citizen");
+        assert_eq!(respond(citizen.clone()).unwrap(), "la la la");
+        assert_eq!(respond(citizen.with("boredom", 7.0)).unwrap(), "*yawn*");
+        assert_eq!(respond(Props::new().with("class", "guard")), None);
+    }
+
+    #[test]
+    fn parse_error_definition_context() {
+        let script = r#"
+            (criterion ConceptIdle (concept == idle))
+            (rule Idle (ConceptIdle) (Idle) mood := )
+            (response Idle (line "la la la"))
+        "#;
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        assert!(engine.is_none());
+        assert!(report.render().contains("in rule Idle"));
+
+        let script = r#"(response Greet (line "hi" weight))"#;
+        let (_, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        assert!(report.render().contains("in response Greet"));
+    }
+
+    #[test]
+    fn merge_engines() {
+        use trill_core::engine::MergeError;
+
+        // Strings are introduced in a different order in each script, so
+        // they are encoded differently by each engine
+        let base = r#"
+            (criterion ConceptGreet (concept == greet))
+            (criterion IsGuard (class == guard))
+            (rule GuardGreet (ConceptGreet IsGuard) (GuardGreet))
+            (response GuardGreet (line "halt"))
+        "#;
+        let dlc = r#"
+            (criterion IsPirate (class == pirate))
+            (criterion ConceptFarewell (concept == farewell))
+            (criterion ConceptGreet (concept == greet))
+            (rule PirateGreet (ConceptGreet IsPirate) (PirateGreet))
+            (rule PirateFarewell (ConceptFarewell IsPirate) (PirateFarewell))
+            (response PirateGreet (line "ahoy"))
+            (response PirateFarewell (line "yarr"))
+        "#;
+        let compile = |script: &str| {
+            let (engine, _) = ScriptCompiler::new()
+                .with_partition_variable("concept")
+                .with_module("script.trl", script)
+                .compile();
+            engine.unwrap()
+        };
+
+        let mut engine = compile(base);
+        engine.merge(compile(dlc)).unwrap();
+
+        let mut respond = |concept: &str, class: &str| {
+            let response = engine.find_best_response(
+                &Props::new().with("concept", concept),
+                &mut Props::new().with("class", class),
+                &mut Props::new(),
+                &mut rand::rng(),
+            );
+            response.map(|response| response[&Ustr::from("line")].clone())
+        };
+        assert_eq!(respond("greet", "guard").unwrap(), "halt");
+        assert_eq!(respond("greet", "pirate").unwrap(), "ahoy");
+        assert_eq!(respond("farewell", "pirate").unwrap(), "yarr");
+        assert_eq!(respond("farewell", "guard"), None);
+
+        // Rules and response groups with the same name can't be merged
+        let mut engine = compile(base);
+        let errors = engine.merge(compile(base)).unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                MergeError::DuplicateRule {
+                    rule_name: Ustr::from("GuardGreet")
+                },
+                MergeError::DuplicateResponseGroup {
+                    group_name: Ustr::from("GuardGreet")
+                },
+            ]
+        );
+
+        // Criteria compiled before an ordinal was declared compare plain
+        // strings, so the other engine can't introduce the ordinal
+        let ranked = r#"
+            (ordinal class (peasant guard king))
+            (criterion IsKing (class == king))
+            (rule KingGreet (ConceptGreet IsKing) (KingGreet))
+            (criterion ConceptGreet (concept == greet))
+            (response KingGreet (line "your majesty"))
+        "#;
+        let mut engine = compile(base);
+        let errors = engine.merge(compile(ranked)).unwrap_err();
+        assert_eq!(
+            errors,
+            [MergeError::UndeclaredOrdinal {
+                variable_name: Ustr::from("class")
+            }]
+        );
+
+        // The other way around, the merged criteria are re-encoded
+        let mut engine = compile(ranked);
+        engine.merge(compile(base)).unwrap();
+        let response = engine.find_best_response(
+            &Props::new().with("concept", "greet"),
+            &mut Props::new().with("class", "guard"),
+            &mut Props::new(),
+            &mut rand::rng(),
+        );
+        assert_eq!(response.unwrap()[&Ustr::from("line")], "halt");
+
+        // Both engines must read weights from the same field
+        let mut engine = compile(base);
+        let (weighted, _) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_weight_field("probability")
+            .with_module("script.trl", dlc)
+            .compile();
+        let errors = engine.merge(weighted.unwrap()).unwrap_err();
+        assert_eq!(
+            errors,
+            [MergeError::ConflictingWeightField {
+                this: Ustr::from("weight"),
+                other: Ustr::from("probability"),
+            }]
+        );
+    }
+
+    #[test]
+    fn strict_types() {
+        // Ordinals are strings, but their positions can be compared as numbers
+        let script = r#"
+            (ordinal rank (bronze silver gold))
+            (criterion ConceptGreet (concept == greet))
+            (criterion Gold (rank == 2))
+            (rule Greet (ConceptGreet Gold) (Greet))
+            (response Greet (line "hello"))
+        "#;
+        let (engine, _) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        assert!(engine.is_some());
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .with_strict_types(true)
+            .compile();
+        assert!(engine.is_none());
+        assert!(matches!(
+            report.compile_errors[..],
+            [CompileError::IndeterminateVariableType { variable_name, .. }] if variable_name == "rank"
+        ));
+        assert!(
+            report
+                .render()
+                .contains("declared as an ordinal string here")
+        );
+
+        // A mismatch between two agreeing usages is caught either way
+        let script = r#"
+            (criterion Rich (gold in 100..))
+            (criterion Named (gold == lots))
+            (rule Spend (Rich) (Spend) gold :- 100)
+            (rule Boast (Named) (Boast))
+            (response Spend (line "here you go"))
+            (response Boast (line "I have lots"))
+        "#;
+        for strict in [false, true] {
+            let (engine, report) = ScriptCompiler::new()
+                .with_module("script.trl", script)
+                .with_strict_types(strict)
+                .compile();
+            assert!(engine.is_none());
+            assert_eq!(report.compile_errors.len(), 1);
+        }
+    }
+
+    #[test]
+    fn type_mismatch_between_usages() {
+        // `gold` is numeric in the criterion and in one assignment, but a
+        // string in the other, so whichever order the assignments are
+        // visited in the mismatch can end up in the middle
+        let script = r#"
+            (criterion Rich (gold in 100..))
+            (criterion ConceptSpend (concept == spend))
+            (criterion ConceptBoast (concept == boast))
+            (rule Spend (ConceptSpend Rich) (Spend) gold :- 100 mood := poor)
+            (rule Boast (ConceptBoast Rich) (Boast) gold := lots mood := 3)
+            (response Spend (line "here you go"))
+            (response Boast (line "I have lots"))
+        "#;
+        for _ in 0..8 {
+            let (engine, report) = ScriptCompiler::new()
+                .with_module("script.trl", script)
+                .compile();
+            assert!(engine.is_none());
+            let names: Vec<_> = report
+                .compile_errors
+                .iter()
+                .map(|error| match error {
+                    CompileError::IndeterminateVariableType {
+                        variable_name,
+                        usages,
+                    } => {
+                        if variable_name == "gold" {
+                            assert_eq!(usages.len(), 3);
+                        }
+                        variable_name.as_str()
+                    }
+                    error => panic!("unexpected error {error:?}"),
+                })
+                .collect();
+            assert_eq!(names, ["gold", "mood"]);
+        }
+    }
+
+    #[test]
+    fn duplicate_module_names() {
+        let mut compiler = ScriptCompiler::new();
+        let first = compiler.add_module("dialog", "(criterion ConceptIdle (concept == idle))");
+        let second = compiler.add_module("dialog", "(rule Idle (ConceptIdle) (Idle))");
+        let third = compiler.add_module("dialog", "(criterion Broken (concept ==))");
+        assert_eq!(first, "dialog");
+        assert_eq!(second, "dialog (2)");
+        assert_eq!(third, "dialog (3)");
+
+        let (engine, report) = compiler.compile();
+        assert!(engine.is_none());
+        assert_eq!(report.parse_errors.len(), 1);
+        assert!(report.render().contains("dialog (3)"));
+    }
+
+    #[test]
+    fn hot_swap_response_text() {
+        let (engine, _) = ScriptCompiler::new()
+            .with_module(
+                "script.trl",
+                r#"
+                (criterion ConceptGreet (concept == greet))
+                (rule Greet (ConceptGreet) (Greet))
+                (response Greet (line "hello"))
+            "#,
+            )
+            .compile();
+        let mut engine = engine.unwrap();
+        let request = Props::new().with("concept", "greet");
+        let line = Ustr::from("line");
+        let respond = |engine: &mut ResponseEngine| {
+            let response = engine.find_best_response_for(&request, &mut rand::rng());
+            response.unwrap()[&line].clone()
+        };
+        assert_eq!(respond(&mut engine), "hello");
+
+        assert!(engine.set_response_field("Greet", 0, line, "bonjour".to_string()));
+        assert_eq!(respond(&mut engine), "bonjour");
+        assert!(!engine.set_response_field("Greet", 1, line, "hola".to_string()));
+        assert!(!engine.set_response_field("Farewell", 0, line, "adieu".to_string()));
+
+        // The new responses are weighted, so only one of them can be picked
+        let responses = ["hola", "ciao"].map(|text| {
+            let mut response = UstrMap::default();
+            response.insert(line, text.to_string());
+            let weight = if text == "ciao" { "1" } else { "0" };
+            response.insert(Ustr::from("weight"), weight.to_string());
+            response
+        });
+        assert!(engine.replace_response_group("Greet", responses.into()));
+        assert_eq!(respond(&mut engine), "ciao");
+    }
+
+    #[test]
+    fn deterministic_rule_order() {
+        let definitions = [
+            "(criterion ConceptIdle (concept == idle))",
+            "(criterion Bored (boredom in 5..))",
+            "(rule Yawn (ConceptIdle Bored) (Idle))",
+            "(rule Stretch (ConceptIdle Bored) (Idle))",
+            "(rule Sigh (ConceptIdle Bored) (Idle))",
+            "(rule Wait (ConceptIdle) (Idle))",
+            "(rule Hum (ConceptIdle) (Idle))",
+            "(response Idle (line \"...\"))",
+        ];
+        let compile = |definitions: Vec<&str>| {
+            let (engine, _) = ScriptCompiler::new()
+                .with_partition_variable("concept")
+                .with_module("script.trl", definitions.join("\n"))
+                .compile();
+            engine.unwrap().partition_rules()
+        };
+
+        let forwards = compile(definitions.to_vec());
+        let backwards = compile(definitions.iter().rev().copied().collect());
+        assert_eq!(forwards, backwards);
+        assert_eq!(forwards, [["Sigh", "Stretch", "Yawn", "Hum", "Wait"]]);
+    }
+
+    #[test]
+    fn concatenated_response_strings() {
+        let (engine, report) = ScriptCompiler::new()
+            .with_module(
+                "script.trl",
+                r#"
+                (criterion ConceptGreet (concept == greet))
+                (rule Greet (ConceptGreet) (Greet))
+                (response Greet
+                    (line "Hello there. "
+                          "How are you?"
+                     mood "cheerful"))
+            "#,
+            )
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let request = Props::new().with("concept", "greet");
+        let response = engine
+            .find_best_response_for(&request, &mut rand::rng())
+            .unwrap();
+        assert_eq!(response[&Ustr::from("line")], "Hello there. How are you?");
+        assert_eq!(response[&Ustr::from("mood")], "cheerful");
+    }
+
+    #[test]
+    fn deterministic_partition_order() {
+        let definitions = [
+            "(criterion ConceptIdle (concept == idle))",
+            "(criterion ConceptGreet (concept == greet))",
+            "(criterion ConceptFlee (concept == flee))",
+            "(criterion IsGuard (class == guard))",
+            "(rule Idle (ConceptIdle) (Line))",
+            "(rule Greet (ConceptGreet) (Line))",
+            "(rule GuardGreet (ConceptGreet IsGuard) (Line))",
+            "(rule Flee (ConceptFlee) (Line))",
+            "(rule Anything () (Line))",
+            "(response Line (line \"...\"))",
+        ];
+        let compile = |definitions: Vec<&str>| {
+            let (engine, _) = ScriptCompiler::new()
+                .with_partition_variable("concept")
+                .with_partition_variable("class")
+                .with_module("script.trl", definitions.join("\n"))
+                .compile();
+            engine.unwrap().partition_rules()
+        };
+
+        let forwards = compile(definitions.to_vec());
+        let backwards = compile(definitions.iter().rev().copied().collect());
+        assert_eq!(forwards.len(), 5);
+        assert_eq!(forwards, backwards);
+    }
+
+    #[test]
+    fn compile_error_display() {
+        let error = CompileError::MissingCriterion {
+            criterion_name: Ustr::from("is_hungry"),
+            in_rule: Ustr::from("ask_for_food"),
+        };
+        assert_eq!(
+            error.to_string(),
+            "unable to find criterion definition is_hungry referenced in rule ask_for_food"
+        );
+
+        let mut compiler = trill_core::ResponseEngineCompiler::new();
+        compiler.with_rule(
+            "ask_for_food",
+            trill_core::Rule {
+                criteria: vec![Ustr::from("is_hungry")],
+                any_criteria: Vec::new(),
+                response_groups: Vec::new(),
+                response_group_weights: Vec::new(),
+                instructions: Vec::new(),
+                priority: 0.0,
+            },
+        );
+        let report = compiler.build().err().unwrap();
+        assert_eq!(report.errors.len(), 1);
+        let error: Box<dyn std::error::Error> = Box::new(report);
+        assert!(error.to_string().contains("is_hungry"));
+    }
+
+    #[test]
+    fn clamped_add() {
+        let script = r#"
+            (rule Rage ((concept == insult)) (Rage) anger :+ 10 clamp 0..5 calm :- 3 clamp -2..=10)
+            (response Rage (line "Grr"))
+        "#;
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+        assert_eq!(
+            engine.preview_instructions("Rage")[0],
+            (
+                Ustr::from("anger"),
+                InstructionTarget::Character,
+                Operation::NumAddClamped {
+                    delta: 10.0,
+                    min: 0.0,
+                    max: 5.0,
+                }
+            )
+        );
+
+        let query = Props::new().with("concept", "insult");
+        let mut actor = Props::new();
+        let mut world = Props::new();
+        for _ in 0..3 {
+            engine.find_best_response(&query, &mut actor, &mut world, &mut rand::rng());
+            assert_eq!(actor.get::<f32>("anger"), 5.0);
+        }
+        assert_eq!(actor.get::<f32>("calm"), -2.0);
+    }
+
+    #[test]
+    fn fact_scanner() {
+        use bevy_mod_props::Value;
+        use trill_core::engine::FactScanner;
+
+        let props = Props::new()
+            .with("alpha", 1.0)
+            .with("charlie", true)
+            .with("echo", "five");
+        let mut scanner = FactScanner::new(&props);
+        assert_eq!(scanner.scan_to("alpha"), Some(Value::Num(1.0)));
+        assert_eq!(scanner.scan_to("alpha"), Some(Value::Num(1.0)));
+        assert_eq!(scanner.scan_to("bravo"), None);
+        assert_eq!(scanner.scan_to("charlie"), Some(Value::Bool(true)));
+        assert_eq!(scanner.scan_to("delta"), None);
+        assert_eq!(scanner.scan_to("echo"), Some(Value::from("five")));
+        assert_eq!(scanner.scan_to("foxtrot"), None);
+        // Keys behind the scanner are missed
+        assert_eq!(scanner.scan_to("alpha"), None);
+    }
+
+    #[test]
+    fn silent_rule() {
+        let script = r#"
+            (rule Notice ((concept == see_player)) () alerted := true $sightings :+ 1)
+        "#;
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let query = Props::new().with("concept", "see_player");
+        let mut actor = Props::new();
+        let mut world = Props::new();
+        let response = engine.find_best_response(&query, &mut actor, &mut world, &mut rand::rng());
+        assert!(response.is_none());
+        assert!(actor.get::<bool>("alerted"));
+        assert_eq!(world.get::<f32>("sightings"), 1.0);
+
+        let found = engine.find_best_match(&query, &mut actor, &mut world, &mut rand::rng());
+        assert_eq!(found.rule, Some(Ustr::from("Notice")));
+        assert_eq!(found.response_group, None);
+        assert_eq!(world.get::<f32>("sightings"), 2.0);
+    }
+
+    #[test]
+    fn weighted_response_groups() {
+        let script = r#"
+            (rule Quip ((concept == idle)) (CommonQuip (RareQuip 0.25) (FallbackQuip 0)))
+            (response CommonQuip (line "common"))
+            (response RareQuip (line "rare"))
+            (response FallbackQuip (line "fallback"))
+        "#;
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let query = Props::new().with("concept", "idle");
+        let mut counts = UstrMap::<usize>::default();
+        for _ in 0..5000 {
+            let found = engine.find_best_match(
+                &query,
+                &mut Props::new(),
+                &mut Props::new(),
+                &mut rand::rng(),
+            );
+            *counts.entry(found.response_group.unwrap()).or_default() += 1;
+        }
+
+        // The common group should be picked four times as often as the rare
+        // one, and the fallback never, since the others always have responses
+        let common = counts[&Ustr::from("CommonQuip")] as f32;
+        let rare = counts[&Ustr::from("RareQuip")] as f32;
+        assert!((3.0..5.5).contains(&(common / rare)), "{counts:?}");
+        assert!(!counts.contains_key(&Ustr::from("FallbackQuip")));
+
+        // A group listed twice keeps the weight of each listing, so here it
+        // is picked once for every three times the other group is
+        let script = r#"
+            (rule Quip ((concept == idle)) ((RareQuip 0) (CommonQuip 3) (RareQuip 1)))
+            (response CommonQuip (line "common"))
+            (response RareQuip (line "rare"))
+        "#;
+        let (engine, _) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        let mut engine = engine.unwrap();
+        let mut counts = UstrMap::<usize>::default();
+        for _ in 0..5000 {
+            let found = engine.find_best_match(
+                &query,
+                &mut Props::new(),
+                &mut Props::new(),
+                &mut rand::rng(),
+            );
+            *counts.entry(found.response_group.unwrap()).or_default() += 1;
+        }
+        let common = counts[&Ustr::from("CommonQuip")] as f32;
+        let rare = counts[&Ustr::from("RareQuip")] as f32;
+        assert!((2.2..4.0).contains(&(common / rare)), "{counts:?}");
+    }
+
+    #[test]
+    fn engine_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ResponseEngine>();
+    }
+
+    #[test]
+    fn equality_epsilon() {
+        let script = r#"
+            (rule Exact ((concept == check) (x == 0.3)) (Exact))
+            (response Exact (line "matched"))
+        "#;
+        let compile = |epsilon| {
+            let (engine, report) = ScriptCompiler::new()
+                .with_partition_variable("concept")
+                .with_equality_epsilon(epsilon)
+                .with_module("script.trl", script)
+                .compile();
+            report.print();
+            engine.unwrap()
+        };
+
+        let respond = |engine: &mut ResponseEngine, x: f32| {
+            let query = Props::new().with("concept", "check").with("x", x);
+            engine
+                .find_best_response(
+                    &query,
+                    &mut Props::new(),
+                    &mut Props::new(),
+                    &mut rand::rng(),
+                )
+                .is_some()
+        };
+
+        // Arithmetic that lands a rounding step away from the literal only
+        // matches with some tolerance
+        let mut exact = compile(0.0);
+        assert!(respond(&mut exact, 0.3));
+        assert!(!respond(&mut exact, 0.3f32.next_up()));
+
+        let mut tolerant = compile(1e-6);
+        for x in [0.1 + 0.2, 0.3f32.next_up(), 0.3f32.next_down()] {
+            assert!(respond(&mut tolerant, x));
+        }
+        assert!(!respond(&mut tolerant, 0.31));
+    }
+
+    #[test]
+    fn response_chance() {
+        let script = r#"
+            (rule Remark ((concept == idle)) (Never Fallback))
+            (response Never (line "never said" chance "0.0"))
+            (response Fallback (line "fallback"))
+            (rule Bad ((concept == bad)) (Bad))
+            (response Bad (line "bad" chance "1.5"))
+        "#;
+        let (_, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        assert!(matches!(
+            report.compile_errors[..],
+            [CompileError::InvalidChance { .. }]
+        ));
+
+        let script = script.replace("chance \"1.5\"", "chance \"0.5\"");
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let query = Props::new().with("concept", "idle");
+        for _ in 0..50 {
+            let found = engine.find_best_match(
+                &query,
+                &mut Props::new(),
+                &mut Props::new(),
+                &mut rand::rng(),
+            );
+            assert_eq!(found.response_group, Some(Ustr::from("Fallback")));
+            assert!(!found.response.unwrap().contains_key(&Ustr::from("chance")));
+        }
+
+        // A response that fails its roll is still used up, and the rule is
+        // disabled once a deplete group runs out, whether or not the last
+        // response was given
+        let script = r#"
+            (rule Remark ((concept == idle)) (Remark))
+            (response Remark deplete
+                (line "never said" chance "0.0")
+                (line "always said"))
+        "#;
+        let (engine, _) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        let mut engine = engine.unwrap();
+        let mut fire = || {
+            let found = engine.find_best_match(
+                &query,
+                &mut Props::new(),
+                &mut Props::new(),
+                &mut rand::rng(),
+            );
+            let line = found
+                .response
+                .map(|response| response[&Ustr::from("line")].clone());
+            (found.rule, line)
+        };
+        let mut lines = vec![fire().1, fire().1];
+        lines.sort();
+        assert_eq!(lines, [None, Some("always said".to_string())]);
+        assert_eq!(fire(), (None, None));
+    }
+
+    #[test]
+    fn explain_accessed_variables() {
+        let script = r#"
+            (criterion IsGreet (concept == greet))
+            (criterion IsCitizen (class_name == citizen))
+            (criterion IsIdle (npc_state == idle))
+            (criterion IsNear (distance in 0..10))
+            (rule Greet (IsGreet IsCitizen IsIdle) (Hello))
+            (rule GreetNear (IsGreet IsCitizen IsNear) (Hello))
+            (response Hello () (line "hello"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+
+        report.print();
+
+        let mut engine = engine.unwrap();
+
+        // `npc_state` isn't set, but it is still looked up
+        let query = Props::new().with("concept", "greet");
+        let mut actor = Props::new()
+            .with("class_name", "citizen")
+            .with("distance", 5.0);
+        let mut world = Props::new();
+        let mut rng = rand::rng();
+        let explained =
+            engine.find_best_response_explained(&query, &mut actor, &mut world, &mut rng);
+
+        assert_eq!(explained.rule, Some(Ustr::from("GreetNear")));
+        for variable in ["concept", "class_name", "npc_state", "distance"] {
+            assert!(
+                explained.accessed_variables.contains(&Ustr::from(variable)),
+                "{variable} should have been accessed"
+            );
+        }
+        assert!(explained.accessed_variables.is_sorted());
+
+        let matched = engine.find_best_match(&query, &mut actor, &mut world, &mut rng);
+        assert!(matched.accessed_variables.is_empty());
+    }
+
+    #[test]
+    fn custom_weight_field() {
+        let script = r#"
+            (rule Greet () (Greet))
+            (response Greet random
+                (line "hello" probability "0" weight "1")
+                (line "hi" probability "1" weight "0"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_weight_field("probability")
+            .with_module("script.trl", script)
+            .compile();
+
+        report.print();
+
+        let mut engine = engine.unwrap();
+
+        let mut rng = rand::rng();
+        for _ in 0..20 {
+            let response = engine
+                .find_best_response_for(&Props::new(), &mut rng)
+                .unwrap();
+            assert_eq!(response[&Ustr::from("line")], "hi");
+            // The weight field is stripped, but other fields are kept, even
+            // if they are called `weight`
+            assert!(!response.contains_key(&Ustr::from("probability")));
+            assert_eq!(response[&Ustr::from("weight")], "0");
+        }
+    }
+
+    #[test]
+    fn range_boundaries() {
+        let matches = |range: &str, value: f32| {
+            let script = format!("(rule R ((x in {range})) (R)) (response R (line \"r\"))");
+            let (engine, report) = ScriptCompiler::new()
+                .with_module("script.trl", script)
+                .compile();
+            report.print();
+            let mut engine = engine.unwrap();
+            let query = Props::new().with("x", value);
+            engine
+                .find_best_response_for(&query, &mut rand::rng())
+                .is_some()
+        };
+
+        // The start is always inclusive
+        assert!(matches("0..10", 0.0));
+        assert!(!matches("0..10", 0.0f32.next_down()));
+        // The end is exclusive, unless written with `..=`
+        assert!(matches("0..10", 10.0f32.next_down()));
+        assert!(!matches("0..10", 10.0));
+        assert!(matches("0..=10", 10.0));
+        assert!(!matches("0..=10", 10.0f32.next_up()));
+        assert!(matches("0..", f32::INFINITY));
+        assert!(matches("..0", f32::NEG_INFINITY));
+
+        // Empty ranges never match
+        for value in [
+            0.0,
+            0.0f32.next_down(),
+            5.0,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+        ] {
+            assert!(!matches("0..0", value));
+            assert!(!matches("5..0", value));
+        }
+        assert!(matches("0..=0", 0.0));
+    }
+
+    #[test]
+    fn request_local_instructions() {
+        let script = r#"
+            (rule Greet ((concept == greet)) (Greet) ?tone := warm ?times :+ 1 greeted :+ 1 $greetings :+ 1)
+            (response Greet (line "hello"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+
+        report.print();
+
+        let mut engine = engine.unwrap();
+
+        let query = Props::new().with("concept", "greet").with("times", 5.0);
+        let mut actor = Props::new();
+        let mut world = Props::new();
+        let mut rng = rand::rng();
+        let found = engine.find_best_match(&query, &mut actor, &mut world, &mut rng);
+
+        assert_eq!(found.request_props.get::<&str>("tone"), "warm");
+        // Request-local variables start from the request's values
+        assert_eq!(found.request_props.get::<f32>("times"), 6.0);
+        assert_eq!(found.request_props.len(), 2);
+        assert_eq!(query.get::<f32>("times"), 5.0);
+
+        assert_eq!(actor.get::<f32>("greeted"), 1.0);
+        assert_eq!(world.get::<f32>("greetings"), 1.0);
+        for props in [&actor, &world] {
+            assert!(!props.contains_key("tone"));
+            assert!(!props.contains_key("times"));
+        }
+
+        // A request-local and a character variable with the same name are
+        // both set by the same rule
+        let script = r#"
+            (rule Greet ((concept == greet)) (Greet) ?times :+ 1 times :+ 10)
+            (response Greet (line "hello"))
+        "#;
+        let (engine, _) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        let mut engine = engine.unwrap();
+        let mut actor = Props::new().with("times", 2.0);
+        let found = engine.find_best_match(&query, &mut actor, &mut Props::new(), &mut rng);
+        assert_eq!(found.request_props.get::<f32>("times"), 6.0);
+        assert_eq!(actor.get::<f32>("times"), 12.0);
+    }
+
+    #[test]
+    fn custom_facts() {
+        use bevy_mod_props::Value;
+        use trill_core::engine::Facts;
+
+        // Deliberately unsorted, to check the engine doesn't rely on order
+        struct FactList(Vec<(Ustr, Value)>);
+
+        impl Facts for FactList {
+            fn iter(&self) -> impl Iterator<Item = (Ustr, Value)> {
+                self.0.iter().cloned()
+            }
+        }
+
+        let script = r#"
+            (criterion ConceptIdle (concept == idle))
+            (criterion IsClippy (name == clippy))
+            (criterion Bored (boredom in 5..))
+            (rule Idle (ConceptIdle IsClippy) (Idle))
+            (rule IdleBored (ConceptIdle IsClippy Bored) (IdleBored))
+            (response Idle (line "hello"))
+            (response IdleBored (line "yawn"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let facts = FactList(vec![
+            (Ustr::from("name"), Value::from("clippy")),
+            (Ustr::from("boredom"), Value::from(10.0)),
+            (Ustr::from("concept"), Value::from("idle")),
+        ]);
+        let response = engine.find_best_response_for(&facts, &mut rand::rng());
+        assert_eq!(response.unwrap().get(&Ustr::from("line")).unwrap(), "yawn");
+    }
+
+    #[test]
+    fn criterion_specificity() {
+        let script = r#"
+            (criterion ConceptIdle (concept == idle))
+            (criterion NearbyLoose (distance in 0..100))
+            (criterion NearbyTight (distance in 0..2))
+            (rule Loose (ConceptIdle NearbyLoose) (Loose))
+            (rule Tight (ConceptIdle NearbyTight) (Tight))
+            (response Loose (line "loose"))
+            (response Tight (line "tight"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_criterion_specificity(CriterionSpecificity::RangeWidth)
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        // Both rules match with the same number of criteria, but the tighter
+        // range should always win.
+        let query = Props::new().with("concept", "idle").with("distance", 1.0);
+        let mut rng = rand::rng();
+        for _ in 0..20 {
+            let response = engine
+                .find_best_response(&query, &mut Props::new(), &mut Props::new(), &mut rng)
+                .unwrap();
+            assert_eq!(response.get(&Ustr::from("line")).unwrap(), "tight");
+        }
+    }
+
+    #[test]
+    fn response_group_include() {
+        let script = r#"
+            (criterion ConceptBye (concept == bye))
+            (rule Bye (ConceptBye) (Goodbye))
+            (response Goodbye loop
+                (line "see ya")
+                (include CommonFarewell))
+            (response CommonFarewell
+                (line "farewell")
+                (line "take care"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let query = Props::new().with("concept", "bye");
+        let mut rng = rand::rng();
+        let lines: Vec<_> = (0..3)
+            .map(|_| {
+                let response = engine
+                    .find_best_response(&query, &mut Props::new(), &mut Props::new(), &mut rng)
+                    .unwrap();
+                response.get(&Ustr::from("line")).unwrap().clone()
+            })
+            .collect();
+
+        // The group's own responses come first, then the included ones
+        assert_eq!(lines, ["see ya", "farewell", "take care"]);
+    }
+
+    #[test]
+    fn response_group_include_cycle() {
+        let script = r#"
+            (response A (line "a") (include B))
+            (response B (line "b") (include A))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+
+        assert!(engine.is_none());
+        assert!(matches!(
+            report.compile_errors.as_slice(),
+            [CompileError::CyclicResponseGroupInclude { .. }]
+        ));
+    }
+
+    #[test]
+    fn rule_instructions_applied() {
+        // Instructions are written out of order on purpose
+        let script = r#"
+            (criterion ConceptGreet (concept == greet))
+            (rule Greet (ConceptGreet) (Greet)
+                times_greeted :+ 1
+                mood := happy
+                greeted := true
+                $last_speaker := clippy)
+            (response Greet (line "hi"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let query = Props::new().with("concept", "greet");
+        let mut rng = rand::rng();
+        for _ in 0..10 {
+            let mut actor = Props::new().with("times_greeted", 2.0);
+            let mut world = Props::new();
+            engine.find_best_response(&query, &mut actor, &mut world, &mut rng);
+
+            assert!(actor.get::<bool>("greeted"));
+            assert_eq!(actor.get::<&str>("mood"), "happy");
+            assert_eq!(actor.get::<f32>("times_greeted"), 3.0);
+            assert_eq!(world.get::<&str>("last_speaker"), "clippy");
+            assert_eq!(actor.len(), 3);
+            assert_eq!(world.len(), 1);
+        }
+    }
+
+    #[test]
+    fn instruction_order() {
+        // Instructions for the same variable are applied in the order they
+        // were written, so these rules only differ in their result
+        let script = r#"
+            (criterion ConceptSetThenAdd (concept == set_then_add))
+            (criterion ConceptAddThenSet (concept == add_then_set))
+            (rule SetThenAdd (ConceptSetThenAdd) ()
+                score := 1
+                mood := calm
+                score :+ 2)
+            (rule AddThenSet (ConceptAddThenSet) ()
+                score :+ 2
+                mood := calm
+                score := 1)
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let mut rng = rand::rng();
+        for (concept, score) in [("set_then_add", 3.0), ("add_then_set", 1.0)] {
+            for _ in 0..10 {
+                let query = Props::new().with("concept", concept);
+                let mut actor = Props::new();
+                engine.find_best_response(&query, &mut actor, &mut Props::new(), &mut rng);
+                assert_eq!(actor.get::<f32>("score"), score, "{concept}");
+                assert_eq!(actor.get::<&str>("mood"), "calm");
+            }
+        }
+    }
+
+    #[test]
+    fn preview_demo_instructions() {
+        let script = include_str!("../../../demo/assets/dialog.trl");
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_partition_variable("name")
+            .with_module("dialog.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let expected = vec![
+            (
+                Ustr::from("met_clippy"),
+                InstructionTarget::World,
+                Operation::NumSet(1.0),
+            ),
+            (
+                Ustr::from("said_line"),
+                InstructionTarget::Character,
+                Operation::NumAdd(1.0),
+            ),
+        ];
+        assert_eq!(engine.preview_instructions("Demo"), expected);
+        assert!(engine.preview_instructions("Missing").is_empty());
+
+        let query = Props::new().with("concept", "idle").with("name", "clippy");
+        let actor = Props::new();
+        let world = Props::new();
+        let preview = engine
+            .preview_best_rule(&query, &actor, &world, &mut rand::rng())
+            .unwrap();
+        assert_eq!(preview.rule, "Demo");
+        assert_eq!(preview.instructions, expected);
+        assert!(actor.is_empty());
+        assert!(world.is_empty());
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        let script = r#"
+            (criterion ConceptSalut (concept == salut))
+            (criterion ÉtatCalme (humeur_élevée == café))
+            (rule Réponse (ConceptSalut ÉtatCalme) (Réponse) émotion := ravi)
+            (response Réponse (line "Ça va?"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let query = Props::new()
+            .with("concept", "salut")
+            .with("humeur_élevée", "café");
+        let mut actor = Props::new();
+        let response = engine
+            .find_best_response(&query, &mut actor, &mut Props::new(), &mut rand::rng())
+            .unwrap();
+        assert_eq!(response.get(&Ustr::from("line")).unwrap(), "Ça va?");
+        assert_eq!(actor.get::<&str>("émotion"), "ravi");
+
+        // Case is still checked, using unicode case
+        let (engine, _) = ScriptCompiler::new()
+            .with_module("script.trl", "(criterion état (humeur == calme))")
+            .compile();
+        assert!(engine.is_none());
+    }
+
+    #[test]
+    fn number_literals() {
+        let script = r#"
+            (criterion ConceptCount (concept == count))
+            (criterion Rich (gold == 1_000))
+            (criterion Famous (fans in 1e6..))
+            (criterion Lucky (luck == 2.5e-3))
+            (criterion Agent (code == 007))
+            (rule Count (ConceptCount Rich Famous Lucky Agent) (Count))
+            (response Count (line "wow"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let query = Props::new()
+            .with("concept", "count")
+            .with("gold", 1000.0)
+            .with("fans", 1_000_000.0)
+            .with("luck", 0.0025)
+            .with("code", 7.0);
+        let response = engine.find_best_response(
+            &query,
+            &mut Props::new(),
+            &mut Props::new(),
+            &mut rand::rng(),
+        );
+        assert!(response.is_some());
+
+        // Repeated underscores are rejected, and the error covers the whole literal
+        let script = "(criterion Rich (gold == 1__0))";
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        assert!(engine.is_none());
+        let (_, error) = &report.parse_errors[0];
+        assert_eq!(&script[error.span.clone()], "1__0");
+    }
+
+    #[test]
+    fn lex_bool_literals() {
+        use logos::Logos;
+
+        use crate::lexer::Token;
+
+        let tokens: Vec<_> = Token::lexer("true false truething falsey")
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Bool(true),
+                Token::Bool(false),
+                Token::Symbol(Ustr::from("truething")),
+                Token::Symbol(Ustr::from("falsey")),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_minus() {
+        use crate::lexer::{Token, Tokens};
+
+        let lex = |source| Tokens::new(source).map(Result::unwrap).collect::<Vec<_>>();
+        assert_eq!(lex("-5"), [Token::Number(-5.0)]);
+        assert_eq!(
+            lex("5 - 3"),
+            [Token::Number(5.0), Token::Minus, Token::Number(3.0)]
+        );
+        assert_eq!(
+            lex("5-3"),
+            [Token::Number(5.0), Token::Minus, Token::Number(3.0)]
+        );
+        assert_eq!(lex("5 -3"), [Token::Number(5.0), Token::Number(-3.0)]);
+        assert_eq!(
+            lex("x)-1"),
+            [
+                Token::Symbol(Ustr::from("x")),
+                Token::ParenClose,
+                Token::Minus,
+                Token::Number(1.0)
+            ]
+        );
+        assert_eq!(
+            lex("-5..-1"),
+            [
+                Token::Number(-5.0),
+                Token::Range(false),
+                Token::Number(-1.0)
+            ]
+        );
+        assert_eq!(lex(":-1"), [Token::ColonMinus, Token::Number(1.0)]);
+    }
+
+    #[test]
+    fn namespaced_response_fields() {
+        let script = r#"
+            (criterion ConceptTaunt (concept == taunt))
+            (rule Taunt (ConceptTaunt) (Taunt))
+            (response Taunt (line "grr" prop:mood "angry"))
+        "#;
+
+        let (engine, _) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        let request = Props::new().with("concept", "taunt");
+        let mut engine = engine.unwrap();
+        let response = engine
+            .find_best_response_for(&request, &mut rand::rng())
+            .unwrap();
+        assert_eq!(response.get(&Ustr::from("prop:mood")).unwrap(), "angry");
+
+        // Namespaced symbols can't be used anywhere else
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", "(criterion Mood (prop:mood == angry))")
+            .compile();
+        assert!(engine.is_none());
+        assert_eq!(report.parse_errors.len(), 1);
+    }
+
+    #[test]
+    fn ordinal_range() {
+        let script = r#"
+            (ordinal rank (bronze silver gold platinum))
+            (criterion ConceptGreet (concept == greet))
+            (criterion Mid (rank in silver..=gold))
+            (criterion Low (rank in ..silver))
+            (rule GreetMid (ConceptGreet Mid) (GreetMid))
+            (rule GreetLow (ConceptGreet Low) (GreetLow))
+            (response GreetMid (line "mid"))
+            (response GreetLow (line "low"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_partition_variable("concept")
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let mut line_for = |rank: &str| {
+            let query = Props::new().with("concept", "greet").with("rank", rank);
+            engine
+                .find_best_response(
+                    &query,
+                    &mut Props::new(),
+                    &mut Props::new(),
+                    &mut rand::rng(),
+                )
+                .map(|response| response.get(&Ustr::from("line")).unwrap().clone())
+        };
+        assert_eq!(line_for("bronze").as_deref(), Some("low"));
+        assert_eq!(line_for("silver").as_deref(), Some("mid"));
+        assert_eq!(line_for("gold").as_deref(), Some("mid"));
+        assert_eq!(line_for("platinum"), None);
+        assert_eq!(line_for("tin"), None);
+    }
+
+    #[test]
+    fn enumerate_definitions() {
+        let script = include_str!("../../../demo/assets/dialog.trl");
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("dialog.trl", script)
+            .compile();
+        assert!(engine.is_some());
+
+        let criteria: Vec<_> = report.criteria().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(criteria, ["IsClippy", "ConceptIdle"]);
+        let rules: Vec<_> = report.rules().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(rules, ["Demo"]);
+        let groups: Vec<_> = report
+            .response_groups()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(groups, ["ClippyIdle", "Default"]);
+
+        let (_, location) = report.rules().next().unwrap();
+        assert!(script[location.span.clone()].starts_with("(rule Demo"));
+    }
+
+    #[test]
+    fn negative_weight() {
+        let script = r#"
+            (response Group
+                (line "a" weight "1")
+                (line "b" weight "-1"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+
+        assert!(engine.is_none());
+        assert!(matches!(
+            report.compile_errors.as_slice(),
+            [CompileError::NegativeWeight { weight, .. }] if *weight == -1.0
+        ));
+    }
+
+    #[test]
+    fn zero_weights_are_uniform() {
+        let script = r#"
+            (rule Idle () (Idle))
+            (response Idle random
+                (line "a" weight "0")
+                (line "b" weight "0"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        let query = Props::new();
+        let mut rng = rand::rng();
+        let mut seen = Vec::new();
+        for _ in 0..100 {
+            let response = engine
+                .find_best_response(&query, &mut Props::new(), &mut Props::new(), &mut rng)
+                .unwrap();
+            let line = response.get(&Ustr::from("line")).unwrap().clone();
+            if !seen.contains(&line) {
+                seen.push(line);
+            }
+        }
+        seen.sort();
+        assert_eq!(seen, ["a", "b"]);
+    }
+
+    #[test]
+    fn zero_weight_deplete_runs_out() {
+        let script = r#"
+            (rule Quip ((concept == idle) (mood == bored)) (Quip))
+            (rule Fallback ((concept == idle)) (Fallback))
+            (response Quip deplete
+                (line "first")
+                (line "never" weight "0"))
+            (response Fallback (line "fallback"))
+        "#;
+
+        let (engine, report) = ScriptCompiler::new()
+            .with_module("script.trl", script)
+            .compile();
+        report.print();
+        let mut engine = engine.unwrap();
+
+        // Only the zero-weight response is left after the first request, so
+        // the rule is disabled and the fallback takes over
+        let query = Props::new().with("concept", "idle").with("mood", "bored");
+        let mut rng = rand::rng();
+        let mut lines = Vec::new();
+        for _ in 0..3 {
+            let found =
+                engine.find_best_match(&query, &mut Props::new(), &mut Props::new(), &mut rng);
+            lines.push(found.response.unwrap()[&Ustr::from("line")].clone());
+        }
+        assert_eq!(lines, ["first", "fallback", "fallback"]);
+    }
 }
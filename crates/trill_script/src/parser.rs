@@ -1,10 +1,10 @@
-use logos::Lexer;
 use logos::Span;
 use ustr::Ustr;
 
 use trill_core::Criterion;
 use trill_core::Delivery;
 use trill_core::Instruction;
+use trill_core::InstructionTarget;
 use trill_core::Operation;
 use trill_core::Predicate;
 use trill_core::ResponseGroup;
@@ -15,6 +15,7 @@ use crate::error::AddSpan;
 use crate::error::ParseError;
 use crate::error::Spanned;
 use crate::lexer::Token;
+use crate::lexer::Tokens;
 
 #[derive(Debug)]
 pub enum Definition {
@@ -25,13 +26,30 @@ pub enum Definition {
     Rule {
         name: Ustr,
         rule: Rule,
+        inline_criteria: Vec<InlineCriterion>,
     },
     ResponseGroup {
         name: Ustr,
         response_group: ResponseGroup,
     },
+    Ordinal {
+        variable: Ustr,
+        values: Vec<Ustr>,
+    },
+}
+
+// An anonymous criterion written inline in a rule, with the name the rule
+// refers to it by
+#[derive(Debug)]
+pub struct InlineCriterion {
+    pub name: Ustr,
+    pub criterion: Criterion,
+    pub span: Span,
 }
 
+// The response groups of a rule, and the weights given to some of them
+type ResponseGroupList = (Vec<Ustr>, Vec<(Ustr, f32)>);
+
 impl Token {
     fn expect_number(self) -> Result<f32, ParseError> {
         if let Token::Number(number) = self {
@@ -102,39 +120,51 @@ trait ExpectUstrExt {
 impl ExpectUstrExt for Ustr {
     fn expect_ident(self) -> Result<Ustr, ParseError> {
         let first_char = self.chars().next().unwrap();
-        if first_char.is_ascii_uppercase() {
+        if self.contains(':') {
+            Err(ParseError::UnexpectedToken {
+                token: Token::Symbol(self),
+                expected: "an identifier",
+                hint: Some("only response field names may contain ':'"),
+            })
+        } else if first_char.is_uppercase() {
             Ok(self)
         } else {
             Err(ParseError::UnexpectedToken {
                 token: Token::Symbol(self),
                 expected: "an identifier",
-                hint: Some("identifiers must begin with an upper-case ascii letter"),
+                hint: Some("identifiers must begin with an upper-case letter"),
             })
         }
     }
 
     fn expect_var(self) -> Result<Ustr, ParseError> {
         let first_char = self.chars().next().unwrap();
-        if first_char.is_ascii_lowercase() {
+        if self.contains(':') {
+            Err(ParseError::UnexpectedToken {
+                token: Token::Symbol(self),
+                expected: "a variable name",
+                hint: Some("only response field names may contain ':'"),
+            })
+        } else if first_char.is_lowercase() {
             Ok(self)
         } else {
             Err(ParseError::UnexpectedToken {
                 token: Token::Symbol(self),
                 expected: "a variable name",
-                hint: Some("variable names must begin with a lower-case ascii letter"),
+                hint: Some("variable names must begin with a lower-case letter"),
             })
         }
     }
 }
 
 pub struct Parser<'src> {
-    lexer: Lexer<'src, Token>,
+    lexer: Tokens<'src>,
 }
 
 impl<'src> Parser<'src> {
     pub fn new(src: &'src str) -> Parser<'src> {
         Parser {
-            lexer: Lexer::new(src),
+            lexer: Tokens::new(src),
         }
     }
 
@@ -170,23 +200,48 @@ impl<'src> Parser<'src> {
             .expect_symbol()
             .span(self.lexer.span())?;
 
+        // Ordinals are named after a variable, rather than an identifier
+        if symbol == "ordinal" {
+            let variable = self
+                .parse_token()?
+                .expect_symbol()
+                .and_then(|s| s.expect_var())
+                .span(self.lexer.span())?;
+            let values = self
+                .parse_list(Token::expect_symbol)
+                .and_then(|values| {
+                    self.parse_token()?
+                        .expect_paren_close()
+                        .span(self.lexer.span())?;
+                    Ok(values)
+                })
+                .map_err(|error| error.in_definition(symbol, variable))?;
+            return Ok(Definition::Ordinal { variable, values });
+        }
+
         let name = self
             .parse_token()?
             .expect_symbol()
             .and_then(|s| s.expect_ident())
             .span(self.lexer.span())?;
 
+        // Errors within a definition note which definition they are in
+        let in_definition = |error: Spanned<ParseError>| error.in_definition(symbol, name);
         match symbol.as_str() {
             "criterion" => {
-                let criterion = self.parse_criterion()?;
+                let criterion = self.parse_criterion().map_err(in_definition)?;
                 Ok(Definition::Criterion { name, criterion })
             }
             "rule" => {
-                let rule = self.parse_rule()?;
-                Ok(Definition::Rule { name, rule })
+                let (rule, inline_criteria) = self.parse_rule(name).map_err(in_definition)?;
+                Ok(Definition::Rule {
+                    name,
+                    rule,
+                    inline_criteria,
+                })
             }
             "response" => {
-                let response_group = self.parse_response_group()?;
+                let response_group = self.parse_response_group().map_err(in_definition)?;
                 Ok(Definition::ResponseGroup {
                     name,
                     response_group,
@@ -195,7 +250,7 @@ impl<'src> Parser<'src> {
             _ => Err(Spanned {
                 error: ParseError::UnexpectedToken {
                     token: Token::Symbol(symbol),
-                    expected: "a symbol containing one of the keywords 'criterion', 'rule', or 'response'",
+                    expected: "a symbol containing one of the keywords 'criterion', 'rule', 'response', or 'ordinal'",
                     hint: None,
                 },
                 span: self.lexer.span(),
@@ -252,17 +307,11 @@ impl<'src> Parser<'src> {
     fn parse_predicate(&mut self) -> Result<Predicate, Spanned<ParseError>> {
         match self.parse_token()? {
             Token::DoubleEqual => match self.parse_token()? {
-                Token::Symbol(s) if s == "true" => {
+                Token::Bool(bool) => {
                     self.parse_token()?
                         .expect_paren_close()
                         .span(self.lexer.span())?;
-                    Ok(Predicate::BoolEqual(true))
-                }
-                Token::Symbol(s) if s == "false" => {
-                    self.parse_token()?
-                        .expect_paren_close()
-                        .span(self.lexer.span())?;
-                    Ok(Predicate::BoolEqual(false))
+                    Ok(Predicate::BoolEqual(bool))
                 }
                 Token::Symbol(symbol) => {
                     self.parse_token()?
@@ -285,87 +334,116 @@ impl<'src> Parser<'src> {
                     span: self.lexer.span(),
                 }),
             },
-            Token::Symbol(s) if s == "in" => match self.parse_token()? {
-                Token::Number(start) => {
-                    let inclusive = match self.parse_token()? {
-                        Token::Range(inclusive) => inclusive,
-                        token => {
-                            return Err(Spanned {
-                                error: ParseError::UnexpectedToken {
-                                    token,
-                                    expected: "either of the specifiers '..' or '..='",
-                                    hint: None,
-                                },
-                                span: self.lexer.span(),
-                            });
-                        }
-                    };
-                    match self.parse_token()? {
-                        Token::Number(mut end) => {
-                            self.parse_token()?
-                                .expect_paren_close()
-                                .span(self.lexer.span())?;
-                            if !inclusive {
-                                end = end.next_down();
-                            }
-                            Ok(Predicate::NumRange(Some(start), Some(end)))
-                        }
-                        Token::ParenClose => Ok(Predicate::NumRange(Some(start), None)),
-                        token => Err(Spanned {
+            Token::Symbol(s) if s == "in" => self.parse_range(),
+            token => Err(Spanned {
+                error: ParseError::UnexpectedToken {
+                    token,
+                    expected: "either a symbol containing the keyword 'in' or the specifier '=='",
+                    hint: None,
+                },
+                span: self.lexer.span(),
+            }),
+        }
+    }
+
+    // Parses a range of either numbers or ordinal values, like `0..5`, `..=10`,
+    // or `bronze..gold`
+    fn parse_range(&mut self) -> Result<Predicate, Spanned<ParseError>> {
+        enum Bound {
+            Num(f32),
+            Ordinal(Ustr),
+        }
+
+        let (start, inclusive) = match self.parse_token()? {
+            Token::Range(inclusive) => (None, inclusive),
+            token => {
+                let start = match token {
+                    Token::Number(num) => Bound::Num(num),
+                    Token::Symbol(symbol) => Bound::Ordinal(symbol),
+                    token => {
+                        return Err(Spanned {
                             error: ParseError::UnexpectedToken {
                                 token,
-                                expected: "either a numeric literal or a closing parenthesis",
+                                expected: "either a numeric literal, a symbol, or either of the specifiers '..' or '..='",
                                 hint: None,
                             },
                             span: self.lexer.span(),
-                        }),
+                        });
+                    }
+                };
+                match self.parse_token()? {
+                    Token::Range(inclusive) => (Some(start), inclusive),
+                    token => {
+                        return Err(Spanned {
+                            error: ParseError::UnexpectedToken {
+                                token,
+                                expected: "either of the specifiers '..' or '..='",
+                                hint: None,
+                            },
+                            span: self.lexer.span(),
+                        });
                     }
                 }
-                Token::Range(true) => {
-                    let end = self
-                        .parse_token()?
-                        .expect_number()
-                        .span(self.lexer.span())?;
-                    self.parse_token()?
-                        .expect_paren_close()
-                        .span(self.lexer.span())?;
-                    Ok(Predicate::NumRange(None, Some(end)))
-                }
-                Token::Range(false) => match self.parse_token()? {
-                    Token::Number(end) => {
-                        self.parse_token()?
-                            .expect_paren_close()
-                            .span(self.lexer.span())?;
-                        let end = end.next_down();
-                        Ok(Predicate::NumRange(None, Some(end)))
+            }
+        };
+
+        let end = match (&start, self.parse_token()?) {
+            (None | Some(Bound::Num(_)), Token::Number(num)) => Some(Bound::Num(num)),
+            (None | Some(Bound::Ordinal(_)), Token::Symbol(symbol)) => Some(Bound::Ordinal(symbol)),
+            // Ranges may be open at the end, unless they are inclusive
+            (_, Token::ParenClose) if !inclusive => None,
+            (_, token) => {
+                let expected = match (&start, inclusive) {
+                    (None, true) => "either a numeric literal or a symbol",
+                    (None, false) => "either a numeric literal, a symbol, or a closing parenthesis",
+                    (Some(Bound::Num(_)), true) => "a numeric literal",
+                    (Some(Bound::Num(_)), false) => {
+                        "either a numeric literal or a closing parenthesis"
                     }
-                    Token::ParenClose => Ok(Predicate::NumRange(None, None)),
-                    token => Err(Spanned {
-                        error: ParseError::UnexpectedToken {
-                            token,
-                            expected: "either a numeric literal or a closing parenthesis",
-                            hint: None,
-                        },
-                        span: self.lexer.span(),
-                    }),
-                },
-                token => Err(Spanned {
+                    (Some(Bound::Ordinal(_)), true) => "a symbol",
+                    (Some(Bound::Ordinal(_)), false) => "either a symbol or a closing parenthesis",
+                };
+                return Err(Spanned {
                     error: ParseError::UnexpectedToken {
                         token,
-                        expected: "either a numeric literal or either of the specifiers '..' or '..='",
+                        expected,
                         hint: None,
                     },
                     span: self.lexer.span(),
-                }),
-            },
-            token => Err(Spanned {
-                error: ParseError::UnexpectedToken {
-                    token,
-                    expected: "either a symbol containing the keyword 'in' or the specifier '=='",
-                    hint: None,
-                },
-                span: self.lexer.span(),
-            }),
+                });
+            }
+        };
+        if end.is_some() {
+            self.parse_token()?
+                .expect_paren_close()
+                .span(self.lexer.span())?;
+        }
+
+        match (start, end) {
+            (start @ Some(Bound::Ordinal(_)), end) | (start, end @ Some(Bound::Ordinal(_))) => {
+                let ordinal = |bound| match bound {
+                    Some(Bound::Ordinal(value)) => Some(value),
+                    _ => None,
+                };
+                Ok(Predicate::OrdinalRange {
+                    start: ordinal(start),
+                    end: ordinal(end),
+                    inclusive,
+                })
+            }
+            (start, end) => {
+                let num = |bound| match bound {
+                    Some(Bound::Num(num)) => Some(num),
+                    _ => None,
+                };
+                // Only the end can be exclusive, which is done by moving it to
+                // the next smaller float. The start is always inclusive.
+                let mut end = num(end);
+                if !inclusive {
+                    end = end.map(f32::next_down);
+                }
+                Ok(Predicate::NumRange(num(start), end))
+            }
         }
     }
 
@@ -388,17 +466,61 @@ impl<'src> Parser<'src> {
         }
     }
 
-    fn parse_ident_list(&mut self) -> Result<Vec<Ustr>, Spanned<ParseError>> {
-        self.parse_list(|token| token.expect_symbol()?.expect_ident())
+    // Parses the response groups of a rule. Each is either a name, or a name
+    // and a weight in parentheses, like `(RareQuip 0.25)`.
+    fn parse_response_group_list(&mut self) -> Result<ResponseGroupList, Spanned<ParseError>> {
+        self.parse_token()?
+            .expect_paren_open()
+            .span(self.lexer.span())?;
+        let mut groups = Vec::new();
+        let mut weights = Vec::new();
+        loop {
+            match self.parse_token()? {
+                Token::ParenClose => return Ok((groups, weights)),
+                Token::ParenOpen => {
+                    let group = self
+                        .parse_token()?
+                        .expect_symbol()
+                        .and_then(|s| s.expect_ident())
+                        .span(self.lexer.span())?;
+                    let weight = self
+                        .parse_token()?
+                        .expect_number()
+                        .span(self.lexer.span())?;
+                    if weight < 0.0 {
+                        return Err(Spanned {
+                            error: ParseError::UnexpectedToken {
+                                token: Token::Number(weight),
+                                expected: "a weight of zero or more",
+                                hint: None,
+                            },
+                            span: self.lexer.span(),
+                        });
+                    }
+                    self.parse_token()?
+                        .expect_paren_close()
+                        .span(self.lexer.span())?;
+                    groups.push(group);
+                    weights.push((group, weight));
+                }
+                token => groups.push(
+                    token
+                        .expect_symbol()
+                        .and_then(|s| s.expect_ident())
+                        .span(self.lexer.span())?,
+                ),
+            }
+        }
     }
 
     fn parse_operation(&mut self) -> Result<Operation, Spanned<ParseError>> {
         match self.parse_token()? {
             Token::ColonNegated => Ok(Operation::BoolToggle),
             Token::ColonEqual => match self.parse_token()? {
-                Token::Symbol(symbol) if symbol == "true" => Ok(Operation::BoolSet(true)),
-                Token::Symbol(symbol) if symbol == "false" => Ok(Operation::BoolSet(false)),
+                Token::Bool(bool) => Ok(Operation::BoolSet(bool)),
                 Token::Number(value) => Ok(Operation::NumSet(value)),
+                // `now` is reserved for the engine's clock
+                Token::Symbol(symbol) if symbol == "now" => Ok(Operation::NumSetNow),
                 Token::Symbol(symbol) => Ok(Operation::StrSet(symbol)),
                 token => Err(Spanned {
                     error: ParseError::UnexpectedToken {
@@ -434,15 +556,145 @@ impl<'src> Parser<'src> {
         }
     }
 
-    fn parse_rule(&mut self) -> Result<Rule, Spanned<ParseError>> {
-        let criteria = self.parse_ident_list()?;
-        let response_groups = self.parse_ident_list()?;
+    // Parses the range of a `clamp` modifier, like `0..100`. Both bounds are
+    // required, and both are inclusive.
+    fn parse_clamp_range(&mut self) -> Result<(f32, f32), Spanned<ParseError>> {
+        let min = self
+            .parse_token()?
+            .expect_number()
+            .span(self.lexer.span())?;
+        match self.parse_token()? {
+            Token::Range(_) => {}
+            token => {
+                return Err(Spanned {
+                    error: ParseError::UnexpectedToken {
+                        token,
+                        expected: "a range operator, either '..' or '..='",
+                        hint: None,
+                    },
+                    span: self.lexer.span(),
+                });
+            }
+        }
+        let max = self
+            .parse_token()?
+            .expect_number()
+            .span(self.lexer.span())?;
+        if max < min {
+            return Err(Spanned {
+                error: ParseError::UnexpectedToken {
+                    token: Token::Number(max),
+                    expected: "an upper bound no smaller than the lower bound",
+                    hint: None,
+                },
+                span: self.lexer.span(),
+            });
+        }
+        Ok((min, max))
+    }
+
+    // Parses the rest of an `(any ...)` group of criteria, after the keyword
+    fn parse_any_criteria(&mut self) -> Result<Vec<Ustr>, Spanned<ParseError>> {
+        let mut group = Vec::new();
+        loop {
+            let token = self.parse_token()?;
+            if token == Token::ParenClose && !group.is_empty() {
+                return Ok(group);
+            }
+            let criterion = token
+                .expect_symbol()
+                .and_then(|s| s.expect_ident())
+                .span(self.lexer.span())?;
+            group.push(criterion);
+        }
+    }
+
+    fn parse_rule(
+        &mut self,
+        name: Ustr,
+    ) -> Result<(Rule, Vec<InlineCriterion>), Spanned<ParseError>> {
+        // Criteria are a list of criterion names, `(any ...)` groups, and
+        // inline criteria like `(concept == idle)`
+        self.parse_token()?
+            .expect_paren_open()
+            .span(self.lexer.span())?;
+        let mut criteria = Vec::new();
+        let mut any_criteria = Vec::new();
+        let mut inline_criteria = Vec::new();
+        loop {
+            match self.parse_token()? {
+                Token::ParenClose => break,
+                Token::ParenOpen => {
+                    let start = self.lexer.span().start;
+                    let symbol = self
+                        .parse_token()?
+                        .expect_symbol()
+                        .span(self.lexer.span())?;
+                    if symbol == "any" {
+                        any_criteria.push(self.parse_any_criteria()?);
+                        continue;
+                    }
+                    let variable = symbol.expect_var().span(self.lexer.span())?;
+                    let criterion = Criterion {
+                        variable,
+                        predicate: self.parse_predicate()?,
+                        weight: 1.0,
+                    };
+                    // Inline criteria are named after their rule, with a '#'
+                    // so they can't collide with any criterion in a script
+                    let criterion_name =
+                        Ustr::from(&format!("{name}#{}", inline_criteria.len() + 1));
+                    inline_criteria.push(InlineCriterion {
+                        name: criterion_name,
+                        criterion,
+                        span: start..self.lexer.span().end,
+                    });
+                    criteria.push(criterion_name);
+                }
+                token => criteria.push(
+                    token
+                        .expect_symbol()
+                        .and_then(|s| s.expect_ident())
+                        .span(self.lexer.span())?,
+                ),
+            }
+        }
+        let (response_groups, response_group_weights) = self.parse_response_group_list()?;
 
         let mut instructions = Vec::new();
+        let mut priority = None;
         loop {
             match self.parse_token()? {
                 Token::ParenClose => break,
-                Token::DollarSign => {
+                Token::ParenOpen if priority.is_none() => {
+                    let keyword = self
+                        .parse_token()?
+                        .expect_symbol()
+                        .span(self.lexer.span())?;
+                    if keyword != "priority" {
+                        return Err(Spanned {
+                            error: ParseError::UnexpectedToken {
+                                token: Token::Symbol(keyword),
+                                expected: "a symbol containing the keyword 'priority'",
+                                hint: None,
+                            },
+                            span: self.lexer.span(),
+                        });
+                    }
+                    priority = Some(
+                        self.parse_token()?
+                            .expect_number()
+                            .span(self.lexer.span())?,
+                    );
+                    self.parse_token()?
+                        .expect_paren_close()
+                        .span(self.lexer.span())?;
+                }
+                token @ (Token::DollarSign | Token::QuestionMark) => {
+                    let target = match token {
+                        Token::DollarSign => InstructionTarget::World,
+                        _ => InstructionTarget::Request,
+                    };
                     let variable = self
                         .parse_token()?
                         .expect_symbol()
@@ -451,16 +703,35 @@ impl<'src> Parser<'src> {
                     let operation = self.parse_operation()?;
                     instructions.push(Instruction {
                         variable,
-                        global: true,
+                        target,
                         operation,
                     });
                 }
+                // `clamp` after an addition bounds the result, like
+                // `anger :+ 1 clamp 0..100`
+                Token::Symbol(symbol)
+                    if symbol == "clamp"
+                        && matches!(
+                            instructions.last(),
+                            Some(Instruction {
+                                operation: Operation::NumAdd(_),
+                                ..
+                            })
+                        ) =>
+                {
+                    let (min, max) = self.parse_clamp_range()?;
+                    let instruction = instructions.last_mut().unwrap();
+                    let Operation::NumAdd(delta) = instruction.operation else {
+                        unreachable!()
+                    };
+                    instruction.operation = Operation::NumAddClamped { delta, min, max };
+                }
                 Token::Symbol(var) => {
                     let variable = var.expect_var().span(self.lexer.span())?;
                     let operation = self.parse_operation()?;
                     instructions.push(Instruction {
                         variable,
-                        global: false,
+                        target: InstructionTarget::Character,
                         operation,
                     });
                 }
@@ -468,7 +739,7 @@ impl<'src> Parser<'src> {
                     return Err(Spanned {
                         error: ParseError::UnexpectedToken {
                             token,
-                            expected: "either a variable name, the '$' variable modifier, or a closing parenthesis",
+                            expected: "either a variable name, the '$' or '?' variable modifiers, a priority declaration, or a closing parenthesis",
                             hint: None,
                         },
                         span: self.lexer.span(),
@@ -479,24 +750,42 @@ impl<'src> Parser<'src> {
 
         let rule = Rule {
             criteria,
+            any_criteria,
             instructions,
             response_groups,
+            response_group_weights,
+            priority: priority.unwrap_or(0.0),
         };
 
-        Ok(rule)
+        Ok((rule, inline_criteria))
     }
 
-    fn parse_response(&mut self) -> Result<UstrMap<String>, Spanned<ParseError>> {
+    // Parses the rest of a response, starting after the value of its first
+    // field
+    fn parse_response(
+        &mut self,
+        mut key: Ustr,
+        mut value: String,
+    ) -> Result<UstrMap<String>, Spanned<ParseError>> {
         let mut response = UstrMap::default();
         loop {
-            match self.parse_token()? {
+            // Adjacent string literals are joined, so that long lines can be
+            // wrapped
+            let mut token = self.parse_token()?;
+            while let Token::String(string) = token {
+                value.push_str(&string);
+                token = self.parse_token()?;
+            }
+            response.insert(key, value);
+
+            match token {
                 Token::ParenClose => break,
-                Token::Symbol(key) => {
-                    let value = self
+                Token::Symbol(next_key) => {
+                    key = next_key;
+                    value = self
                         .parse_token()?
                         .expect_string()
                         .span(self.lexer.span())?;
-                    response.insert(key, value);
                 }
                 token => {
                     return Err(Spanned {
@@ -520,7 +809,8 @@ impl<'src> Parser<'src> {
             token = self.parse_token()?;
             match symbol.as_str() {
                 "shuffle" => Delivery::Shuffle,
-                "random" => Delivery::Random,
+                "random" | "weighted_random" => Delivery::WeightedRandom,
+                "random_no_repeat" => Delivery::RandomNoRepeat,
                 "deplete" => Delivery::Deplete,
                 "loop" => Delivery::Loop,
                 "list" => Delivery::List,
@@ -528,7 +818,7 @@ impl<'src> Parser<'src> {
                     return Err(Spanned {
                         error: ParseError::UnexpectedToken {
                             token: Token::Symbol(symbol),
-                            expected: "a symbol containing one of the keywords 'shuffle', 'random', 'deplete', 'loop', or 'list'",
+                            expected: "a symbol containing one of the keywords 'shuffle', 'random', 'weighted_random', 'random_no_repeat', 'deplete', 'loop', or 'list'",
                             hint: None,
                         },
                         span: self.lexer.span(),
@@ -540,13 +830,53 @@ impl<'src> Parser<'src> {
         };
 
         let mut responses = Vec::new();
+        let mut includes = Vec::new();
         loop {
             match token {
-                Token::ParenClose if !responses.is_empty() => break,
-                Token::ParenOpen => {
-                    let response = self.parse_response()?;
-                    responses.push(response);
-                }
+                Token::ParenClose if !responses.is_empty() || !includes.is_empty() => break,
+                Token::ParenOpen => match self.parse_token()? {
+                    // Includes another response group, like `(include Name)`
+                    Token::Symbol(s) if s == "include" => match self.parse_token()? {
+                        Token::Symbol(name) => {
+                            self.parse_token()?
+                                .expect_paren_close()
+                                .span(self.lexer.span())?;
+                            includes.push(name);
+                        }
+                        // Otherwise, it's just a response with an `include` key
+                        Token::String(value) => {
+                            responses.push(self.parse_response(s, value)?);
+                        }
+                        token => {
+                            return Err(Spanned {
+                                error: ParseError::UnexpectedToken {
+                                    token,
+                                    expected: "either the name of a response group or a string",
+                                    hint: None,
+                                },
+                                span: self.lexer.span(),
+                            });
+                        }
+                    },
+                    Token::Symbol(key) => {
+                        let value = self
+                            .parse_token()?
+                            .expect_string()
+                            .span(self.lexer.span())?;
+                        responses.push(self.parse_response(key, value)?);
+                    }
+                    Token::ParenClose => responses.push(UstrMap::default()),
+                    token => {
+                        return Err(Spanned {
+                            error: ParseError::UnexpectedToken {
+                                token,
+                                expected: "either a symbol or a closing parenthesis",
+                                hint: None,
+                            },
+                            span: self.lexer.span(),
+                        });
+                    }
+                },
                 token => {
                     return Err(Spanned {
                         error: ParseError::UnexpectedToken {
@@ -564,6 +894,8 @@ impl<'src> Parser<'src> {
         let response_group = ResponseGroup {
             delivery,
             responses,
+            weights: Vec::new(),
+            includes,
         };
 
         Ok(response_group)
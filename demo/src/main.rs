@@ -5,7 +5,7 @@ use bevy_trill::{LoadResponseEngine, RequestResponse, Response, TrillPlugin};
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
-        .add_plugins(TrillPlugin)
+        .add_plugins(TrillPlugin::default())
         .add_systems(Startup, setup)
         .add_systems(Update, idle_response)
         .run();